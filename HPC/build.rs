@@ -0,0 +1,44 @@
+use std::path::Path;
+
+/*
+    The built-in locations profiles without their secrets, written to OUT_DIR for
+    load_locations.rs to include, so no key ends up inside the binaries
+    The secrets are resolved at runtime instead, see secrets.rs
+*/
+
+const PROFILES: [&str; 3] = ["locations.json", "locations.dev.json", "locations.test.json"];
+
+const SECRETS: [&str; 4] = ["api_key", "api_key_next", "smtp_username", "smtp_password"];
+
+// the s3 keys under "storage"
+const STORAGE_SECRETS: [&str; 2] = ["access_key_id", "secret_access_key"];
+
+fn main() {
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+
+    for profile in PROFILES {
+        println!("cargo:rerun-if-changed={}", profile);
+
+        let bytes = std::fs
+            ::read(profile)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", profile, e));
+        let mut locations: serde_json::Value = serde_json
+            ::from_slice(&bytes)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {}", profile, e));
+
+        if let Some(locations) = locations.as_object_mut() {
+            for secret in SECRETS {
+                locations.remove(secret);
+            }
+            if let Some(storage) = locations.get_mut("storage").and_then(|s| s.as_object_mut()) {
+                for secret in STORAGE_SECRETS {
+                    storage.remove(secret);
+                }
+            }
+        }
+
+        std::fs
+            ::write(Path::new(&out_dir).join(profile), locations.to_string())
+            .unwrap_or_else(|e| panic!("Failed to write {}: {}", profile, e));
+    }
+}
@@ -0,0 +1,329 @@
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{ Context, Result };
+use chrono::Utc;
+use utils::{
+    api_client::ApiClient,
+    bin_locations::{ bin_location, project_root_bin_location, BinNames, ProjectBinNames },
+    cloud_storage::ServiceAccount,
+    load_locations::{ Locations, PipelineType },
+    send_email::check_smtp,
+    storage::{ storage_backend, StorageConfig },
+    tools::Tools,
+};
+
+/*
+    Everything a deployment needs before process_queue can run the pipelines
+    A failed check makes doctor exit nonzero, warnings don't
+*/
+
+pub const CONDA_ENVS: [&str; 6] = [
+    "tcsdr",
+    "ogv",
+    "intactness",
+    "coreceptor",
+    "splicing",
+    "locator",
+];
+
+const PIPELINE_TYPES: [PipelineType; 7] = [
+    PipelineType::Base,
+    PipelineType::Ogv,
+    PipelineType::Tcs,
+    PipelineType::Intact,
+    PipelineType::Coreceptor,
+    PipelineType::Splicing,
+    PipelineType::Locator,
+];
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    pub status: Status,
+    pub detail: String,
+}
+
+impl Check {
+    pub fn new(name: &str, result: Result<String>) -> Check {
+        match result {
+            Ok(detail) => Check { name: name.to_owned(), status: Status::Pass, detail },
+            Err(e) => Check {
+                name: name.to_owned(),
+                status: Status::Fail,
+                detail: format!("{:#}", e),
+            },
+        }
+    }
+}
+
+pub async fn run_checks(locations: &Locations, tools: Arc<dyn Tools>) -> Vec<Check> {
+    let mut checks: Vec<Check> = vec![];
+
+    for (name, path) in [
+        ("base", &locations.base),
+        ("ogv_base_path", &locations.ogv_base_path),
+        ("intactness_base_path", &locations.intactness_base_path),
+        ("coreceptor_base_path", &locations.coreceptor_base_path),
+    ] {
+        checks.push(Check::new(name, is_dir(path)));
+    }
+    checks.push(Check::new("private_key_location", check_private_key(locations)));
+
+    for pipeline_type in PIPELINE_TYPES {
+        let name = format!("{:?}", pipeline_type).to_lowercase();
+        checks.push(
+            Check::new(
+                &format!("log_dir.{}", name),
+                is_writable(&locations.log_dir[pipeline_type])
+            )
+        );
+        checks.push(
+            Check::new(
+                &format!("scratch_space.{}", name),
+                is_writable(&locations.scratch_space[pipeline_type])
+            )
+        );
+    }
+
+    checks.extend(check_conda_envs(tools.as_ref()));
+
+    for name in [ProjectBinNames::TCSDR, ProjectBinNames::SPLICING] {
+        checks.push(Check::new(name, project_root_bin_location(name).and_then(is_executable)));
+    }
+    for name in [
+        BinNames::OGV,
+        BinNames::TCSDR,
+        BinNames::INTACTNESS,
+        BinNames::SPLICING,
+        BinNames::LOCATOR,
+        BinNames::CORECEPTOR,
+    ] {
+        checks.push(
+            Check::new(&format!("bin {}", name), bin_location(name).and_then(is_executable))
+        );
+    }
+
+    if let StorageConfig::Gcs { .. } = locations.storage {
+        checks.push(Check::new("gsutil", succeeds(tools.as_ref(), "gsutil version")));
+    }
+    checks.push(Check::new("storage", check_storage(locations, tools).await));
+    checks.push(Check::new("api", check_api(locations).await));
+    if let Some(warning) = locations.api_key_age_warning(Utc::now().date_naive()) {
+        let name = "api key age".to_string();
+        checks.push(Check { name, status: Status::Warn, detail: warning });
+    }
+
+    let smtp = format!("{}:{}", &locations.smtp_address, locations.smtp_port);
+    checks.push(Check::new("smtp", check_smtp(locations, SMTP_TIMEOUT).await.map(|_| smtp)));
+
+    checks
+}
+
+// one line per check, then a summary
+pub fn report(checks: &[Check]) -> String {
+    let mut lines: Vec<String> = checks
+        .iter()
+        .map(|check| {
+            let status = match check.status {
+                Status::Pass => "PASS",
+                Status::Warn => "WARN",
+                Status::Fail => "FAIL",
+            };
+            // multi-line errors stay under their check
+            let detail = check.detail.replace('\n', "\n      ");
+            format!("{}  {}  {}", status, check.name, detail)
+        })
+        .collect();
+
+    let count = |status: Status| checks.iter().filter(|check| check.status == status).count();
+    lines.push(
+        format!(
+            "\n{} passed, {} warnings, {} failed",
+            count(Status::Pass),
+            count(Status::Warn),
+            count(Status::Fail)
+        )
+    );
+
+    lines.join("\n")
+}
+
+fn is_dir(path: &str) -> Result<String> {
+    if !Path::new(path).is_dir() {
+        return Err(anyhow::anyhow!("{} is not a directory", path));
+    }
+    Ok(path.to_owned())
+}
+
+// gcs links are signed with it, so it has to be a service account key
+fn check_private_key(locations: &Locations) -> Result<String> {
+    let path = is_file(&locations.private_key_location)?;
+    if let StorageConfig::Gcs { .. } = locations.storage {
+        let account = ServiceAccount::read(&path)?;
+        return Ok(format!("{} ({})", path, account.client_email));
+    }
+    Ok(path)
+}
+
+fn is_file(path: &str) -> Result<String> {
+    if !Path::new(path).is_file() {
+        return Err(anyhow::anyhow!("{} is not a file", path));
+    }
+    Ok(path.to_owned())
+}
+
+fn is_executable(path: String) -> Result<String> {
+    let mode = std::fs
+        ::metadata(&path)
+        .with_context(|| format!("{} is missing", path))?
+        .permissions()
+        .mode();
+
+    if mode & 0o111 == 0 {
+        return Err(anyhow::anyhow!("{} is not executable", path));
+    }
+    Ok(path)
+}
+
+// creates the directory if needed, like the pipelines do, then writes and removes a file
+fn is_writable(dir: &str) -> Result<String> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir))?;
+
+    let probe = Path::new(dir).join(".doctor");
+    std::fs::write(&probe, "").with_context(|| format!("{} is not writable", dir))?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(dir.to_owned())
+}
+
+fn succeeds(tools: &dyn Tools, cmd: &str) -> Result<String> {
+    let output = tools.output(cmd, ".")?;
+
+    if output.status != Some(0) {
+        return Err(anyhow::anyhow!("`{}` failed: {}", cmd, output.stderr.trim()));
+    }
+    Ok(output.stdout.lines().next().unwrap_or("").to_owned())
+}
+
+fn check_conda_envs(tools: &dyn Tools) -> Vec<Check> {
+    let envs = match tools.output("conda env list", ".") {
+        Ok(output) if output.status == Some(0) => parse_conda_envs(&output.stdout),
+        Ok(output) => {
+            return vec![Check::new("conda", Err(anyhow::anyhow!("{}", output.stderr.trim())))];
+        }
+        Err(e) => {
+            return vec![Check::new("conda", Err(e))];
+        }
+    };
+
+    CONDA_ENVS.iter()
+        .map(|env| {
+            let found = envs.iter().any(|name| name == env);
+            let result = if found {
+                Ok("installed".to_string())
+            } else {
+                Err(anyhow::anyhow!("not in `conda env list`"))
+            };
+            Check::new(&format!("conda env {}", env), result)
+        })
+        .collect()
+}
+
+// names from `conda env list`, unnamed envs are listed by path so use the last directory
+fn parse_conda_envs(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|env| env.rsplit('/').next().unwrap_or(env).to_owned())
+        .collect()
+}
+
+// the base bucket_url can be read with the configured storage
+async fn check_storage(locations: &Locations, tools: Arc<dyn Tools>) -> Result<String> {
+    let storage = storage_backend(&locations.storage, &locations.private_key_location, tools)?;
+    let location = &locations.bucket_url[PipelineType::Base];
+    let bytes = storage.du(location.trim_end_matches('/')).await?;
+    Ok(format!("{} holds {} bytes", location, bytes))
+}
+
+async fn check_api(locations: &Locations) -> Result<String> {
+    let api = ApiClient::new(&locations.api_key, &locations.api)?.with_next_key(
+        locations.api_key_next.as_deref()
+    );
+    api.queue(locations).await?;
+    Ok(locations.api_url[PipelineType::Base].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use utils::{ mock_api::MockApi, tools::{ FakeTools, Replay } };
+
+    #[test]
+    fn test_parse_conda_envs() {
+        let stdout = "# conda environments:\n#\nbase  *  /opt/conda\nogv  /opt/conda/envs/ogv\n\
+                      /home/hpc/envs/locator\n";
+
+        assert_eq!(parse_conda_envs(stdout), vec!["base", "ogv", "locator"]);
+    }
+
+    #[tokio::test]
+    async fn test_run_checks() {
+        let dir = std::env::temp_dir().join(format!("doctor_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let api = MockApi::start().await.unwrap();
+        let mut locations = api.locations(&dir);
+        api.queue(serde_json::json!({}));
+
+        // nothing listens on a port that was just freed
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        locations.smtp_address = "127.0.0.1".to_string();
+        locations.smtp_port = closed.local_addr().unwrap().port();
+        drop(closed);
+        locations.api_key_created = Some("2020-01-01".to_string());
+
+        let tools = FakeTools::new("doctor")
+            .on("conda env list", Replay::new().stdout("tcsdr\nogv\nintactness\nsplicing\n"))
+            .on("gsutil", Replay::new().status(127).stderr("gsutil: command not found"));
+
+        let checks = run_checks(&locations, Arc::new(tools)).await;
+        let status = |name: &str| {
+            checks
+                .iter()
+                .find(|check| check.name == name)
+                .map(|check| check.status)
+                .unwrap_or_else(|| panic!("no check {}", name))
+        };
+
+        assert_eq!(status("base"), Status::Pass);
+        assert_eq!(status("private_key_location"), Status::Pass);
+        assert_eq!(status("ogv_base_path"), Status::Fail);
+        assert_eq!(status("scratch_space.tcs"), Status::Pass);
+        assert!(dir.join("log/tcsdr").is_dir());
+        assert_eq!(status("conda env ogv"), Status::Pass);
+        assert_eq!(status("conda env locator"), Status::Fail);
+        assert_eq!(status("gsutil"), Status::Fail);
+        assert_eq!(status("storage"), Status::Pass);
+        assert_eq!(status("api"), Status::Pass);
+        assert_eq!(status("api key age"), Status::Warn);
+        assert_eq!(status("smtp"), Status::Fail);
+
+        let report = report(&checks);
+        assert!(report.contains("WARN  api key age  The API key is"));
+        assert!(report.ends_with("failed"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
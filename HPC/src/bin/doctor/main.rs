@@ -0,0 +1,27 @@
+use std::process::exit;
+use std::sync::Arc;
+use utils::{ load_locations::load_locations_with_source, tools::ShellTools };
+
+mod checks;
+
+use checks::{ report, run_checks, Check, Status };
+
+#[tokio::main]
+async fn main() {
+    let (locations, source) = match load_locations_with_source() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            println!("{}", report(&[Check::new("config", Err(e))]));
+            exit(1);
+        }
+    };
+
+    let mut checks = vec![Check::new("config", Ok(source.to_string()))];
+    checks.extend(run_checks(&locations, Arc::new(ShellTools)).await);
+
+    println!("{}", report(&checks));
+
+    if checks.iter().any(|check| check.status == Status::Fail) {
+        exit(1);
+    }
+}
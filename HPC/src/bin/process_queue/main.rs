@@ -1,70 +1,568 @@
-use anyhow::Result;
-use chrono::Local;
+use anyhow::{ Context, Result };
+use chrono::{ Local, SecondsFormat, Utc };
 use serde::Deserialize;
-use std::path::Path;
+use serde_json::Value;
+use std::path::{ Path, PathBuf };
 use std::process::exit;
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+use tokio::signal::unix::{ SignalKind, signal };
 use utils::{
-    bin_locations::{ BinNames, bin_location },
-    get_api::get_api,
+    api_client::{ ApiClient, PipelinePatch, QueueAPIData, SharedAPIData },
+    bin_locations::bin_location,
+    email_templates::cancelled_email_template,
+    job_usage::{ UsageConfig, UsageRecord, UsageStore },
     load_env_vars::{ EnvVars, load_env_vars },
     load_locations::{ Locations, PipelineType, load_locations },
     lock_file,
-    pipeline::{ IntactAPI, LocatorAPI, OgvAPI, Pipeline, SplicingAPI, TcsAPI },
-    run_command::run_command,
+    pipeline::{ pipeline_is_stale, visit_job_types, JobTypeVisitor, Pipeline, PipelineJob },
+    resource_estimate::InputSize,
+    scheduler::{ JobResources, JobSpec, JobState, JobStatus, Scheduler, load_scheduler },
+    tools::{ Email, ShellTools, ToolOutput, Tools },
 };
 
-#[derive(Debug, Deserialize)]
-struct SharedAPIData {
-    pub id: String,
-    pub submit: bool,
-    #[serde(rename = "uploadCount")]
-    pub upload_count: Option<u8>,
-}
-
-#[derive(Debug, Deserialize)]
-struct QueueAPIData {
-    pub ogvs: Vec<SharedAPIData>,
-    pub intacts: Vec<SharedAPIData>,
-    pub tcss: Vec<SharedAPIData>,
-    pub splicings: Vec<SharedAPIData>,
-    pub locators: Vec<SharedAPIData>,
-}
-
-fn create_sbatch_cmd(
-    output_file: &str,
-    cores: u8,
-    job_name: &str,
-    memory: u32,
-    time: u32,
-    wrap: &str
-) -> String {
-    let Locations { admin_email, base, .. } = load_locations().unwrap_or_else(|e| {
-        println!("Error loading locations.json: {:?}", e);
-        exit(1);
+// how often a run waiting on its local jobs refreshes lock_process
+const LOCK_REFRESH: Duration = Duration::from_secs(60 * 60);
+
+// pending submissions whose latest attempt has outlived the pipeline's time limit
+// running jobs are left to the scheduler, retries can be given longer than stale_hours
+fn find_stale(submissions: &[SharedAPIData], time_limit_in_hours: i64) -> Vec<&SharedAPIData> {
+    submissions
+        .iter()
+        .filter(|s| !s.submit && !s.cancel)
+        .filter(|s| s.scheduler_state.as_deref() != Some(JobState::Running.as_str()))
+        .filter(|s| pipeline_is_stale(&s.pending, s.pending_since(), time_limit_in_hours).0)
+        .collect()
+}
+
+// as MongoDB dates come back from the API, what pipeline_is_stale parses
+fn api_timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+// one pipeline's share of the queue
+struct PipelineQueue<'a> {
+    pipeline_type: PipelineType,
+    name: &'static str,
+    threaded: bool,
+    bin_location: String,
+    submissions: &'a [SharedAPIData],
+}
+
+impl<'a> PipelineQueue<'a> {
+    fn new<T: PipelineJob>(submissions: &'a [SharedAPIData]) -> Result<PipelineQueue<'a>> {
+        Ok(PipelineQueue {
+            pipeline_type: T::PIPELINE_TYPE,
+            name: T::NAME,
+            threaded: T::THREADED,
+            bin_location: bin_location(T::BIN)?,
+            submissions,
+        })
+    }
+}
+
+// scheduler job name, used to find the job again when polling
+fn job_name(pipeline_type: PipelineType, id: &str) -> String {
+    format!("{}-{}", pipeline_type, id)
+}
+
+// command line for a pipeline binary, `cores` is what the scheduler gives the job
+fn pipeline_cmd(queue: &PipelineQueue<'_>, id: &str, cores: u8, is_dev_cmd: &str) -> String {
+    if queue.threaded {
+        // the binary leaves the core first_attempt added for itself
+        format!("{} --id={} --cores={}{}", &queue.bin_location, id, cores - 1, is_dev_cmd)
+    } else {
+        format!("{} --id={}{}", &queue.bin_location, id, is_dev_cmd)
+    }
+}
+
+// the job gets one core more than the pipeline asks for
+fn first_attempt(estimate: JobResources) -> JobResources {
+    JobResources { cores: estimate.cores + 1, ..estimate }
+}
+
+fn job_spec(
+    queue: &PipelineQueue<'_>,
+    id: &str,
+    resources: JobResources,
+    locations: &Locations,
+    is_dev_cmd: &str
+) -> JobSpec {
+    JobSpec {
+        cmd: pipeline_cmd(queue, id, resources.cores, is_dev_cmd),
+        cores: resources.cores,
+        memory: resources.memory,
+        time: resources.time,
+        job_name: job_name(queue.pipeline_type, id),
+        output_file: format!("{}/{}.out", &locations.log_dir[queue.pipeline_type], id),
+    }
+}
+
+fn scheduler_state_patch(status: &JobStatus) -> PipelinePatch {
+    PipelinePatch {
+        scheduler_job_id: Some(status.job_id.clone()),
+        scheduler_state: Some(status.state.as_str().to_string()),
+        ..Default::default()
+    }
+}
+
+// remember which scheduler job belongs to the submission and what it was given
+async fn patch_job<T>(pipeline: &Pipeline<T>, job_id: Option<String>, job: &JobSpec, attempts: u32)
+    where T: for<'de> Deserialize<'de>
+{
+    let submitted = PipelinePatch {
+        scheduler_job_id: job_id.clone(),
+        attempts: Some(attempts),
+        submitted_at: Some(api_timestamp()),
+        resources: Some(job.resources()),
+        ..Default::default()
+    };
+
+    if let Err(e) = pipeline.patch_pipeline(&submitted).await {
+        println!("Error saving job {:?} for {}: {:?}", &job_id, &pipeline.id, e);
+    }
+}
+
+// pipelines send their emails and run their commands through `tools`, FakeTools in tests
+async fn load_pipeline<T>(
+    id: &str,
+    pipeline_type: PipelineType,
+    locations: &Locations,
+    tools: &Arc<dyn Tools>
+) -> Result<Pipeline<T>>
+    where T: for<'de> Deserialize<'de>
+{
+    Ok(Pipeline::with_locations(id, pipeline_type, locations).await?.with_tools(tools.clone()))
+}
+
+// the admin's copy of a cycle's problems
+async fn email_admin(tools: &Arc<dyn Tools>, subject: &str, body: &str, admin_email: &str) {
+    let email = Email {
+        subject: subject.to_owned(),
+        body: body.to_owned(),
+        to: admin_email.to_owned(),
+        include_admin: false,
+    };
+    tools.email(email).await.unwrap_or_else(|e| {
+        println!("Error emailing {}: {:?}", subject, e);
     });
+}
+
+// submission failures of one cycle, the admin gets them in one email at the end
+#[derive(Default)]
+struct Failures(Vec<String>);
+
+impl Failures {
+    fn report(&mut self, action: &str, e: &anyhow::Error) {
+        println!("Error {}: {:?}", action, e);
+        self.0.push(format!("Error {}: {:#}", action, e));
+    }
+
+    async fn notify(&self, tools: &Arc<dyn Tools>, admin_email: &str) {
+        if self.0.is_empty() {
+            return;
+        }
+
+        let subject = format!("process_queue Errors ({})", self.0.len());
+        email_admin(tools, &subject, &self.0.join("\n\n"), admin_email).await;
+    }
+}
+
+// start a usage record for the job, collect_usage fills it in once the job ends
+fn record_job(
+    store: &UsageStore,
+    pipeline_type: PipelineType,
+    id: &str,
+    job_id: &Option<String>,
+    input: InputSize,
+    job: &JobSpec
+) {
+    let Some(job_id) = job_id else {
+        return;
+    };
+
+    let record = UsageRecord::new(pipeline_type, id, job_id, input, job.resources());
+    if let Err(e) = store.add(record) {
+        println!("Error recording usage of job {}: {:?}", job_id, e);
+    }
+}
+
+// what finished jobs actually used, from the scheduler's accounting
+// records it will never report on and each pipeline's oldest over max_records are dropped first
+fn collect_usage(
+    store: &UsageStore,
+    scheduler: &dyn Scheduler,
+    config: &UsageConfig
+) -> Result<()> {
+    let mut records = store.load()?;
+    let pruned = config.prune(&mut records, Utc::now());
+
+    let unfinished: Vec<String> = records
+        .iter()
+        .filter(|record| record.used.is_none())
+        .map(|record| record.job_id.clone())
+        .collect();
+
+    let usage = scheduler.job_usage(&unfinished)?;
+    if usage.is_empty() && !pruned {
+        return Ok(());
+    }
 
-    let slurm_error_handling = format!("--mail-type=FAIL --mail-user={}", admin_email);
-
-    let sbatch_command = format!(
-        "sbatch {} -o {} --cpus-per-task={} --job-name='{}' --mem={} -t {} --wrap='{}'",
-        slurm_error_handling,
-        output_file,
-        cores,
-        job_name,
-        memory,
-        time,
-        wrap
+    for record in records.iter_mut().filter(|record| record.used.is_none()) {
+        record.used = usage.get(&record.job_id).cloned();
+    }
+
+    store.save(&records)
+}
+
+// resubmit jobs killed for memory or time, the user only hears about the last failed attempt
+async fn retry_failed_job(
+    queue: &PipelineQueue<'_>,
+    submission: &SharedAPIData,
+    status: &JobStatus,
+    pipeline: &Pipeline<Value>,
+    scheduler: &dyn Scheduler,
+    locations: &Locations,
+    is_dev_cmd: &str
+) -> Result<()> {
+    let attempts = submission.attempts.unwrap_or(1);
+    let next = submission.resources.and_then(|resources|
+        locations.retry.escalate(&resources, &status.state, attempts)
     );
 
-    // append sbatch command to a file located at {}/sbatch_commands.txt
-    let sbatch_commands_file = format!("{}/sbatch_commands.txt", &base);
-    let contents = format!("{}\n\n", &sbatch_command);
-    std::fs::write(&sbatch_commands_file, contents).unwrap_or_else(|e| {
-        println!("Error writing sbatch command to file: {:?}", e);
-        // exit(1);
-    });
+    if let Some(resources) = next {
+        // pick up after the stages the killed attempt finished
+        let job = job_spec(
+            queue,
+            &submission.id,
+            resources,
+            locations,
+            &format!("{} --resume", is_dev_cmd)
+        );
+
+        match scheduler.submit(&job) {
+            Ok(job_id) => {
+                // same input as the first attempt
+                let store = UsageStore::new(&locations.base);
+                if let Ok(Some(latest)) = store.latest(&submission.id) {
+                    record_job(
+                        &store,
+                        queue.pipeline_type,
+                        &submission.id,
+                        &job_id,
+                        latest.input,
+                        &job
+                    );
+                }
+
+                let _ = pipeline.add_log(
+                    &format!(
+                        "Resubmitted as attempt {} with {} MB and {} minutes",
+                        attempts + 1,
+                        resources.memory,
+                        resources.time
+                    )
+                );
+
+                pipeline.patch_pipeline(
+                    &(PipelinePatch {
+                        scheduler_job_id: job_id,
+                        scheduler_state: Some(JobState::Pending.as_str().to_string()),
+                        attempts: Some(attempts + 1),
+                        submitted_at: Some(api_timestamp()),
+                        resources: Some(resources),
+                        ..Default::default()
+                    })
+                ).await?;
+
+                return Ok(());
+            }
+            Err(e) => println!("Error resubmitting {}: {:?}", &job.job_name, e),
+        }
+    }
+
+    pipeline.patch_pipeline(&scheduler_state_patch(status)).await?;
 
-    sbatch_command
+    // job was killed by the scheduler, so the binary never reported the error itself
+    let msg = match submission.resources {
+        Some(JobResources { memory, time, .. }) =>
+            format!(
+                "Pipeline #{} ended with {} after {} attempt(s), last run with {} MB and {} minutes.",
+                &submission.id,
+                status.state.as_str(),
+                attempts,
+                memory,
+                time
+            ),
+        None => format!("Pipeline #{} ended with {}.", &submission.id, status.state.as_str()),
+    };
+
+    pipeline.add_error(
+        &format!("{} Error {}", queue.name, &submission.id),
+        &msg,
+        pipeline.data["email"].as_str().unwrap_or("")
+    ).await
+}
+
+// report live scheduler state of pending submissions back to the API
+async fn poll_job_states(
+    queue: &[PipelineQueue<'_>],
+    scheduler: &dyn Scheduler,
+    locations: &Locations,
+    tools: &Arc<dyn Tools>,
+    failures: &mut Failures,
+    is_dev_cmd: &str
+) {
+    for pipeline_queue in queue {
+        let PipelineQueue { pipeline_type, name, submissions, .. } = pipeline_queue;
+
+        let pending: Vec<&SharedAPIData> = submissions
+            .iter()
+            .filter(|s| s.pending && !s.submit && !s.cancel)
+            .collect();
+
+        let job_names: Vec<String> = pending
+            .iter()
+            .map(|s| job_name(*pipeline_type, &s.id))
+            .collect();
+
+        // one failed query, ex) sacct timing out, shouldn't hold up the other pipelines
+        let states = match scheduler.job_states(&job_names) {
+            Ok(states) => states,
+            Err(e) => {
+                failures.report(&format!("polling {} jobs", name), &e);
+                continue;
+            }
+        };
+
+        for submission in pending {
+            let Some(status) = states.get(&job_name(*pipeline_type, &submission.id)) else {
+                continue;
+            };
+
+            let unchanged =
+                submission.scheduler_state.as_deref() == Some(status.state.as_str()) &&
+                submission.scheduler_job_id.as_deref() == Some(status.job_id.as_str());
+            if unchanged {
+                continue;
+            }
+
+            println!(
+                "{} #{} job {} is {}",
+                name,
+                &submission.id,
+                &status.job_id,
+                status.state.as_str()
+            );
+
+            let pipeline: Pipeline<Value> = match
+                load_pipeline(&submission.id, *pipeline_type, locations, tools).await
+            {
+                Ok(p) => p,
+                Err(e) => {
+                    println!("Error creating pipeline: {:?}", e);
+                    continue;
+                }
+            };
+
+            if status.state.is_failure() {
+                let _ = pipeline.add_log(
+                    &format!("Scheduler job {} ended with {}", &status.job_id, status.state.as_str())
+                );
+            }
+
+            // plain failures are reported by the binary itself
+            if matches!(status.state, JobState::OutOfMemory | JobState::Timeout) {
+                if
+                    let Err(e) = retry_failed_job(
+                        pipeline_queue,
+                        submission,
+                        status,
+                        &pipeline,
+                        scheduler,
+                        locations,
+                        is_dev_cmd
+                    ).await
+                {
+                    println!("Error handling failed job {}: {:?}", &status.job_id, e);
+                }
+                continue;
+            }
+
+            pipeline
+                .patch_pipeline(&scheduler_state_patch(status)).await
+                .unwrap_or_else(|e| {
+                    println!("Error patching scheduler state: {:?}", e);
+                });
+        }
+    }
+}
+
+// stop user-cancelled submissions, remove their scratch files, then patch and email the user
+// a job the scheduler couldn't stop keeps `cancel` so the next cycle tries again
+async fn cancel_jobs(
+    queue: &[PipelineQueue<'_>],
+    scheduler: &dyn Scheduler,
+    locations: &Locations,
+    tools: &Arc<dyn Tools>,
+    failures: &mut Failures
+) -> Result<()> {
+    for PipelineQueue { pipeline_type, name, submissions, .. } in queue {
+        for submission in submissions.iter().filter(|s| s.cancel) {
+            let job_name = job_name(*pipeline_type, &submission.id);
+
+            if scheduler.is_dry_run() {
+                println!("Dry run: {} #{} would be cancelled ({})", name, &submission.id, &job_name);
+                continue;
+            }
+
+            let pipeline: Pipeline<Value> = match
+                load_pipeline(&submission.id, *pipeline_type, locations, tools).await
+            {
+                Ok(p) => p,
+                Err(e) => {
+                    println!("Error creating pipeline: {:?}", e);
+                    continue;
+                }
+            };
+
+            // nothing to stop if it was never dispatched
+            if submission.pending {
+                if let Err(e) = scheduler.cancel(&job_name, submission.scheduler_job_id.as_deref()) {
+                    failures.report(&format!("cancelling {}", &job_name), &e);
+                    continue;
+                }
+            }
+
+            // Pipeline::new creates the scratch dir, so remove it after
+            if Path::new(&pipeline.scratch_dir).exists() {
+                if let Err(e) = std::fs::remove_dir_all(&pipeline.scratch_dir) {
+                    println!("Error removing {}: {:?}", &pipeline.scratch_dir, e);
+                }
+            }
+
+            let _ = pipeline.add_log("Cancelled by user.");
+
+            let cancelled = PipelinePatch {
+                cancel: Some(false),
+                cancelled: Some(true),
+                scheduler_state: Some(JobState::Cancelled.as_str().to_string()),
+                ..PipelinePatch::completed()
+            };
+
+            if let Err(e) = pipeline.patch_pipeline(&cancelled).await {
+                println!("Error patching {} as cancelled: {:?}", &job_name, e);
+                continue;
+            }
+
+            let email = pipeline.data["email"].as_str().unwrap_or("");
+            let job_id = match pipeline.data["jobID"].as_str() {
+                Some(job_id) if !job_id.is_empty() => job_id,
+                _ => &submission.id,
+            };
+
+            if !email.is_empty() {
+                pipeline.email(
+                    &format!("{} Submission #{} Cancelled", name, job_id),
+                    &cancelled_email_template(&format!("ID: {}", &submission.id)),
+                    email,
+                    true
+                ).await.unwrap_or_else(|e| {
+                    println!("Error emailing cancellation: {:?}", e);
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// the pipeline binary's command that cancels a stale submission
+fn stale_cmd(bin_location: &str, id: &str, is_stale_cmd: &str, is_dev_cmd: &str) -> String {
+    format!("{} --id={}{}{}", bin_location, id, is_stale_cmd, is_dev_cmd)
+}
+
+// cancel stale submissions through each binary's --is_stale path and send the admin one summary
+// the scheduler job is stopped first, one still queued would otherwise run later anyway
+async fn sweep_stale_jobs(
+    queue: &[PipelineQueue<'_>],
+    scheduler: &dyn Scheduler,
+    locations: &Locations,
+    tools: &Arc<dyn Tools>,
+    is_dev_cmd: &str
+) -> Result<()> {
+    let is_dry_run = scheduler.is_dry_run();
+    let mut summary: Vec<String> = vec![];
+
+    for PipelineQueue { pipeline_type, name, bin_location, submissions, .. } in queue {
+        let time_limit_in_hours = locations.stale_hours[*pipeline_type];
+
+        for stale in find_stale(submissions, time_limit_in_hours) {
+            let (_, is_stale_cmd) = pipeline_is_stale(
+                &stale.pending,
+                stale.pending_since(),
+                time_limit_in_hours
+            );
+            let cmd = stale_cmd(bin_location, &stale.id, &is_stale_cmd, is_dev_cmd);
+
+            if is_dry_run {
+                println!("Dry run: {} #{} is stale, would run: {}", name, &stale.id, &cmd);
+                continue;
+            }
+
+            let job_name = job_name(*pipeline_type, &stale.id);
+            if let Err(e) = scheduler.cancel(&job_name, stale.scheduler_job_id.as_deref()) {
+                summary.push(
+                    format!(
+                        "{} #{} is stale but its job {} failed to cancel: {:?}",
+                        name,
+                        &stale.id,
+                        &job_name,
+                        e
+                    )
+                );
+                continue;
+            }
+
+            // the binary exits 1 when it couldn't mark the submission as failed
+            match tools.output(&cmd, &locations.base) {
+                Ok(ToolOutput { status: Some(0), .. }) =>
+                    summary.push(
+                        format!(
+                            "{} #{} pending since {} (over {} hours) was cancelled.",
+                            name,
+                            &stale.id,
+                            stale.pending_since(),
+                            time_limit_in_hours
+                        )
+                    ),
+                Ok(ToolOutput { status, stdout, stderr }) =>
+                    summary.push(
+                        format!(
+                            "{} #{} is stale but failed to cancel, exit status {:?}:\n{}{}",
+                            name,
+                            &stale.id,
+                            status,
+                            stdout,
+                            stderr
+                        )
+                    ),
+                Err(e) =>
+                    summary.push(
+                        format!("{} #{} is stale but failed to cancel: {:?}", name, &stale.id, e)
+                    ),
+            }
+        }
+    }
+
+    if summary.is_empty() {
+        return Ok(());
+    }
+
+    let subject = format!("Stale Submissions ({})", summary.len());
+    email_admin(tools, &subject, &summary.join("\n\n"), &locations.admin_email).await;
+
+    Ok(())
 }
 
 #[tokio::main]
@@ -74,54 +572,50 @@ async fn main() {
 
     if let Err(err) = run().await {
         eprintln!("process_queue failed: {:?}", err);
+        write_error_log(&err);
+        exit(1);
+    }
+}
 
-        match std::env::var("HOME") {
-            Ok(home) => {
-                let date_suffix = Local::now().format("%m_%d");
-                let mut path = format!("{}/process_queue_{}.error", home, date_suffix);
-
-                if Path::new(&path).exists() {
-                    for idx in 1.. {
-                        let candidate = format!(
-                            "{}/process_queue_{}_{}.error",
-                            home,
-                            date_suffix,
-                            idx
-                        );
-                        if !Path::new(&candidate).exists() {
-                            path = candidate;
-                            break;
-                        }
-                    }
-                }
+fn write_error_log(err: &anyhow::Error) {
+    match std::env::var("HOME") {
+        Ok(home) => {
+            let date_suffix = Local::now().format("%m_%d");
+            let mut path = format!("{}/process_queue_{}.error", home, date_suffix);
 
-                if let Err(write_err) = std::fs::write(&path, format!("{:?}\n", err)) {
-                    eprintln!("Failed to write error log to {}: {:?}", path, write_err);
+            if Path::new(&path).exists() {
+                for idx in 1.. {
+                    let candidate = format!(
+                        "{}/process_queue_{}_{}.error",
+                        home,
+                        date_suffix,
+                        idx
+                    );
+                    if !Path::new(&candidate).exists() {
+                        path = candidate;
+                        break;
+                    }
                 }
             }
-            Err(env_err) => {
-                eprintln!("Unable to resolve HOME for error logging: {:?}", env_err);
+
+            if let Err(write_err) = std::fs::write(&path, format!("{:?}\n", err)) {
+                eprintln!("Failed to write error log to {}: {:?}", path, write_err);
             }
         }
-
-        exit(1);
+        Err(env_err) => {
+            eprintln!("Unable to resolve HOME for error logging: {:?}", env_err);
+        }
     }
 }
 
 async fn run() -> Result<()> {
-    let EnvVars { is_dev, .. } = load_env_vars();
+    let EnvVars { is_dev, daemon, config, .. } = load_env_vars();
 
     let locations = load_locations().unwrap_or_else(|e| {
         println!("Error loading environment: {:?}", e);
         exit(1);
     });
 
-    let tcsdr_bin_location = bin_location(BinNames::TCSDR).unwrap();
-    let ogv_bin_location = bin_location(BinNames::OGV).unwrap();
-    let intactness_bin_location = bin_location(BinNames::INTACTNESS).unwrap();
-    let splicing_bin_location = bin_location(BinNames::SPLICING).unwrap();
-    let locator_bin_location = bin_location(BinNames::LOCATOR).unwrap();
-
     if is_dev {
         std::env::var("PORT").unwrap_or_else(|_| {
             println!("Dev not running in the docker shell. Exiting.");
@@ -129,215 +623,879 @@ async fn run() -> Result<()> {
         });
     }
 
-    let is_dev_cmd = if is_dev { " --is_dev" } else { "" };
+    let mut is_dev_cmd = String::from(if is_dev { " --is_dev" } else { "" });
 
-    let lock_file: lock_file::LockFile = lock_file::LockFile::new(
-        format!("{}/lock_process", &locations.base)
-    );
-    if lock_file.exists().unwrap_or(true) {
-        println!("\n\nProcess currently running.\nExiting.\n\n");
-        exit(1);
+    // jobs read the same config, PRIMER_ID_CONFIG reaches them through the environment
+    if let Some(config) = config.filter(|config| !config.is_empty()) {
+        let config = std::fs::canonicalize(&config).unwrap_or(PathBuf::from(config));
+        is_dev_cmd.push_str(&format!(" --config={}", config.display()));
+    }
+
+    let scheduler = load_scheduler(&locations, is_dev);
+    let tools: Arc<dyn Tools> = Arc::new(ShellTools);
+
+    if daemon {
+        run_daemon(&locations, scheduler.as_ref(), &tools, &is_dev_cmd).await
     } else {
-        lock_file.create()?;
+        run_cycle(&locations, scheduler.as_ref(), &tools, &is_dev_cmd, true).await
     }
+}
 
-    let queue_url = format!("{}/queue", &locations.api_url[PipelineType::Base]);
-    let QueueAPIData { ogvs, intacts, tcss, splicings, locators } = get_api(
-        &queue_url
-    ).await.unwrap_or_else(|e| {
-        println!("Error getting queue API: {:?}", e);
-        QueueAPIData {
-            ogvs: vec![],
-            intacts: vec![],
-            tcss: vec![],
-            splicings: vec![],
-            locators: vec![],
+// check the queue every poll_interval seconds until SIGTERM or SIGINT
+// a failed cycle is logged like a failed cron run and the next cycle runs as usual
+async fn run_daemon(
+    locations: &Locations,
+    scheduler: &dyn Scheduler,
+    tools: &Arc<dyn Tools>,
+    is_dev_cmd: &str
+) -> Result<()> {
+    let mut sigterm = signal(SignalKind::terminate()).context("Failed to listen for SIGTERM.")?;
+    let interval = Duration::from_secs(locations.poll_interval);
+    let heartbeat_file = format!("{}/process_queue_heartbeat", &locations.base);
+
+    println!(
+        "process_queue daemon started, checking the queue every {} seconds",
+        locations.poll_interval
+    );
+
+    for cycle in 1_u64.. {
+        let started = Instant::now();
+
+        let result = run_cycle(locations, scheduler, tools, is_dev_cmd, false).await;
+        if let Err(err) = &result {
+            eprintln!("process_queue cycle {} failed: {:?}", cycle, err);
+            write_error_log(err);
         }
-    });
 
-    for ogv in ogvs {
-        if ogv.submit {
-            let pipeline: Pipeline<OgvAPI> = match Pipeline::new(&ogv.id, PipelineType::Ogv).await {
-                Ok(p) => p,
-                Err(e) => {
-                    println!("Error creating pipeline: {:?}", e);
-                    continue;
-                }
-            };
+        // last line of the heartbeat file shows the daemon is alive
+        let heartbeat = format!(
+            "[{}] cycle {} {} in {:.1}s",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            cycle,
+            if result.is_ok() { "finished" } else { "failed" },
+            started.elapsed().as_secs_f64()
+        );
+        println!("{}", &heartbeat);
+        std::fs::write(&heartbeat_file, format!("{}\n", &heartbeat)).unwrap_or_else(|e| {
+            println!("Error writing heartbeat to {}: {:?}", &heartbeat_file, e);
+        });
 
-            let (cores, memory) = pipeline.cores_and_memory();
-
-            let mut cmd = format!("{} --id={}{}", ogv_bin_location, &ogv.id, &is_dev_cmd);
-            if !is_dev {
-                cmd = create_sbatch_cmd(
-                    &format!("{}/{}.out", &locations.log_dir[PipelineType::Ogv], &ogv.id),
-                    cores + 1,
-                    &format!("ogv-{}", &ogv.id),
-                    memory,
-                    5760,
-                    &cmd
-                );
+        tokio::select! {
+            _ = sigterm.recv() => {
+                println!("SIGTERM received, process_queue daemon stopping.");
+                break;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!("SIGINT received, process_queue daemon stopping.");
+                break;
             }
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
 
-            run_command(&cmd, &locations.base)?;
+    Ok(())
+}
+
+// jobs can run longer than a lock is trusted, so the wait keeps it fresh
+async fn wait_holding_lock(
+    scheduler: &dyn Scheduler,
+    lock_file: &lock_file::LockFile
+) -> Result<()> {
+    let mut wait = scheduler.wait();
+    let mut refresh = tokio::time::interval(LOCK_REFRESH);
+
+    loop {
+        tokio::select! {
+            result = &mut wait => {
+                return result;
+            }
+            _ = refresh.tick() => {
+                lock_file.touch().unwrap_or_else(|e| {
+                    println!("Error refreshing lock_process: {:?}", e);
+                });
+            }
+        }
+    }
+}
 
-            let _ = pipeline.send_receipt().await;
-            let _ = pipeline.patch_pending().await?;
+// one pass over the queue under the lock file, a one-shot run also waits for its local jobs
+async fn run_cycle(
+    locations: &Locations,
+    scheduler: &dyn Scheduler,
+    tools: &Arc<dyn Tools>,
+    is_dev_cmd: &str,
+    one_shot: bool
+) -> Result<()> {
+    let lock_file: lock_file::LockFile = lock_file::LockFile::new(
+        format!("{}/lock_process", &locations.base)
+    );
+    if lock_file.exists().unwrap_or(true) {
+        if !lock_file.is_stale().unwrap_or(false) {
+            println!("\n\nProcess currently running.\nExiting.\n\n");
+            return Ok(());
         }
+
+        // the holder died without releasing it
+        let msg = format!(
+            "{}/lock_process was over 12 hours old and has been removed.\n\nThe process_queue run holding it most likely crashed.",
+            &locations.base
+        );
+        println!("{}", &msg);
+        email_admin(tools, "Stale process_queue Lock", &msg, &locations.admin_email).await;
+        lock_file.delete()?;
     }
 
-    for intact in intacts {
-        if intact.submit {
-            let pipeline: Pipeline<IntactAPI> = match
-                Pipeline::new(&intact.id, PipelineType::Intact).await
-            {
-                Ok(p) => p,
-                Err(e) => {
-                    println!("Error creating pipeline: {:?}", e);
-                    continue;
-                }
-            };
+    lock_file.create()?;
 
-            let (cores, memory) = pipeline.cores_and_memory(intact.upload_count);
+    let mut result = handle_queue(locations, scheduler, tools, is_dev_cmd).await;
 
-            let mut cmd = format!(
-                "{} --id={} --cores={}{}",
-                intactness_bin_location,
-                &intact.id,
-                cores + 1,
-                &is_dev_cmd
-            );
+    // local jobs are children of this process, hold the lock until they finish
+    // the daemon keeps polling instead, its jobs finish in the background
+    if one_shot && result.is_ok() {
+        result = wait_holding_lock(scheduler, &lock_file).await;
+    }
 
-            if !is_dev {
-                cmd = create_sbatch_cmd(
-                    &format!("{}/{}.out", &locations.log_dir[PipelineType::Intact], &intact.id),
-                    cores + 1,
-                    &format!("intactness-{}", &intact.id),
-                    memory,
-                    1440,
-                    &cmd
-                );
+    // a lock left behind stops every later run, release it even when the cycle failed
+    lock_file.delete()?;
+
+    result
+}
+
+// submit everything flagged for it, a failed submission is reported and the rest carry on
+async fn dispatch<T: PipelineJob>(
+    queue: &PipelineQueue<'_>,
+    locations: &Locations,
+    tools: &Arc<dyn Tools>,
+    scheduler: &dyn Scheduler,
+    usage_store: &UsageStore,
+    failures: &mut Failures,
+    is_dev_cmd: &str
+) {
+    for submission in queue.submissions.iter().filter(|s| s.submit && !s.cancel) {
+        let pipeline: Pipeline<T> = match
+            load_pipeline(&submission.id, T::PIPELINE_TYPE, locations, tools).await
+        {
+            Ok(p) => p,
+            Err(e) => {
+                failures.report(&format!("loading {} #{}", T::NAME, &submission.id), &e);
+                continue;
+            }
+        };
+
+        let estimate = T::resources(&pipeline).await;
+
+        let job = job_spec(
+            queue,
+            &submission.id,
+            first_attempt(estimate.resources),
+            locations,
+            is_dev_cmd
+        );
+
+        let job_id = match scheduler.submit(&job) {
+            Ok(job_id) => job_id,
+            Err(e) => {
+                failures.report(&format!("submitting {} #{}", T::NAME, &submission.id), &e);
+                continue;
             }
+        };
 
-            run_command(&cmd, &locations.base)?;
+        if scheduler.is_dry_run() {
+            continue;
+        }
 
-            let _ = pipeline.send_receipt().await;
-            let _ = pipeline.patch_pending().await?;
+        // left as submit=true the next run would start a second job, so stop this one
+        if let Err(e) = pipeline.patch_pending().await {
+            failures.report(&format!("marking {} #{} pending", T::NAME, &submission.id), &e);
+            if let Err(e) = scheduler.cancel(&job.job_name, job_id.as_deref()) {
+                failures.report(&format!("cancelling {}", &job.job_name), &e);
+            }
+            continue;
         }
-    }
 
-    for tcs in tcss {
-        if tcs.submit {
-            let pipeline: Pipeline<TcsAPI> = match Pipeline::new(&tcs.id, PipelineType::Tcs).await {
-                Ok(p) => p,
-                Err(e) => {
-                    println!("Error creating pipeline: {:?}", e);
-                    continue;
-                }
-            };
+        if let Err(e) = pipeline.send_receipt().await {
+            failures.report(&format!("sending {} #{} receipt", T::NAME, &submission.id), &e);
+        }
 
-            let (cores, memory) = pipeline.cores_and_memory(&tcs.upload_count);
+        record_job(usage_store, T::PIPELINE_TYPE, &submission.id, &job_id, estimate.input, &job);
+        patch_job(&pipeline, job_id, &job, 1).await;
+    }
+}
 
-            let mut cmd = format!(
-                "{} --id={} --cores={}{}",
-                tcsdr_bin_location,
-                &tcs.id,
-                cores,
-                &is_dev_cmd
-            );
+// email a new results link to submissions flagged relink, finished ones only
+async fn relink_results<T: PipelineJob>(
+    submissions: &[SharedAPIData],
+    locations: &Locations,
+    tools: &Arc<dyn Tools>,
+    failures: &mut Failures,
+    is_dry_run: bool
+) {
+    for submission in submissions.iter().filter(|s| s.relink && !s.submit && !s.pending) {
+        if is_dry_run {
+            println!("Dry run: {} #{} would get a renewed results link", T::NAME, &submission.id);
+            continue;
+        }
 
-            if !is_dev {
-                cmd = create_sbatch_cmd(
-                    &format!("{}/{}.out", &locations.log_dir[PipelineType::Tcs], &tcs.id),
-                    cores + 1,
-                    &format!("tcs-{}", &tcs.id),
-                    memory,
-                    1440,
-                    &cmd
-                );
+        let pipeline: Pipeline<T> = match
+            load_pipeline(&submission.id, T::PIPELINE_TYPE, locations, tools).await
+        {
+            Ok(p) => p,
+            Err(e) => {
+                failures.report(&format!("loading {} #{}", T::NAME, &submission.id), &e);
+                continue;
             }
+        };
 
-            run_command(&cmd, &locations.base)?;
-
-            let _ = pipeline.send_receipt().await;
-            let _ = pipeline.patch_pending().await?;
+        if let Err(e) = pipeline.relink().await {
+            failures.report(&format!("relinking {} #{}", T::NAME, &submission.id), &e);
+            // the admin is told, and relink tells the user when the results expired
+            // clear the flag so it isn't retried every run
+            if let Err(e) = pipeline.patch_pipeline(&PipelinePatch::relinked()).await {
+                failures.report(&format!("patching {} #{}", T::NAME, &submission.id), &e);
+            }
         }
     }
+}
 
-    for splicing in splicings {
-        if splicing.submit {
-            let pipeline: Pipeline<SplicingAPI> = match
-                Pipeline::new(&splicing.id, PipelineType::Splicing).await
-            {
-                Ok(p) => p,
+// the queue of each job type, in visit_job_types order
+struct Queues<'a> {
+    queue: &'a QueueAPIData,
+    queues: Result<Vec<PipelineQueue<'a>>>,
+}
+
+impl JobTypeVisitor for Queues<'_> {
+    async fn visit<T: PipelineJob>(&mut self) {
+        if let Ok(queues) = &mut self.queues {
+            match PipelineQueue::new::<T>(self.queue.submissions(T::PIPELINE_TYPE)) {
+                Ok(queue) => queues.push(queue),
                 Err(e) => {
-                    println!("Error creating pipeline: {:?}", e);
-                    continue;
+                    self.queues = Err(e);
                 }
-            };
+            }
+        }
+    }
+}
 
-            let (cores, memory) = pipeline.cores_and_memory();
+// submit and relink what each job type has flagged
+struct Dispatch<'a> {
+    queues: &'a [PipelineQueue<'a>],
+    locations: &'a Locations,
+    tools: &'a Arc<dyn Tools>,
+    scheduler: &'a dyn Scheduler,
+    usage_store: &'a UsageStore,
+    failures: &'a mut Failures,
+    is_dev_cmd: &'a str,
+}
 
-            let mut cmd = format!(
-                "{} --id={} {}",
-                splicing_bin_location,
-                &splicing.id,
-                &is_dev_cmd
-            );
+impl JobTypeVisitor for Dispatch<'_> {
+    async fn visit<T: PipelineJob>(&mut self) {
+        let Some(queue) = self.queues.iter().find(|q| q.pipeline_type == T::PIPELINE_TYPE) else {
+            return;
+        };
 
-            if !is_dev {
-                cmd = create_sbatch_cmd(
-                    &format!("{}/{}.out", &locations.log_dir[PipelineType::Splicing], &splicing.id),
-                    cores + 1,
-                    &format!("splicing-{}", &splicing.id),
-                    memory,
-                    1440,
-                    &cmd
-                );
-            }
+        dispatch::<T>(
+            queue,
+            self.locations,
+            self.tools,
+            self.scheduler,
+            self.usage_store,
+            self.failures,
+            self.is_dev_cmd
+        ).await;
+
+        relink_results::<T>(
+            queue.submissions,
+            self.locations,
+            self.tools,
+            self.failures,
+            self.scheduler.is_dry_run()
+        ).await;
+    }
+}
+
+async fn handle_queue(
+    locations: &Locations,
+    scheduler: &dyn Scheduler,
+    tools: &Arc<dyn Tools>,
+    is_dev_cmd: &str
+) -> Result<()> {
+    let usage_store = UsageStore::new(&locations.base);
+    let mut failures = Failures::default();
+
+    let api = ApiClient::shared(locations)?;
+    let queue_data = api.queue(locations).await.unwrap_or_else(|e| {
+        println!("Error getting queue API: {:?}", e);
+        QueueAPIData::default()
+    });
 
-            run_command(&cmd, &locations.base)?;
+    let mut queues = Queues { queue: &queue_data, queues: Ok(vec![]) };
+    visit_job_types(&mut queues).await;
+    let queue = queues.queues?;
 
-            pipeline.send_receipt().await?;
-            pipeline.patch_pending().await?;
+    if let Err(e) = cancel_jobs(&queue, scheduler, locations, tools, &mut failures).await {
+        failures.report("cancelling jobs", &e);
+    }
+
+    if let Err(e) = collect_usage(&usage_store, scheduler, &locations.usage) {
+        failures.report("collecting job usage", &e);
+    }
+
+    poll_job_states(&queue, scheduler, locations, tools, &mut failures, is_dev_cmd).await;
+
+    if let Err(e) = sweep_stale_jobs(&queue, scheduler, locations, tools, is_dev_cmd).await {
+        failures.report("sweeping stale jobs", &e);
+    }
+
+    visit_job_types(
+        &mut (Dispatch {
+            queues: &queue,
+            locations,
+            tools,
+            scheduler,
+            usage_store: &usage_store,
+            failures: &mut failures,
+            is_dev_cmd,
+        })
+    ).await;
+
+    if !scheduler.is_dry_run() {
+        failures.notify(tools, &locations.admin_email).await;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use utils::bin_locations::BinNames;
+    use utils::mock_api::MockApi;
+    use utils::pipeline::{ CoreceptorAPI, OgvAPI, SplicingAPI, TcsAPI };
+    use utils::scheduler::SlurmScheduler;
+    use utils::tools::FakeTools;
+
+    #[test]
+    fn test_queue_includes_coreceptors() {
+        let queue: QueueAPIData = serde_json
+            ::from_str(
+                r#"{
+                    "ogvs": [],
+                    "intacts": [],
+                    "tcss": [],
+                    "splicings": [],
+                    "locators": [],
+                    "coreceptors": [{ "id": "abc123", "submit": true, "uploadCount": 0 }]
+                }"#
+            )
+            .unwrap();
+
+        let coreceptors = queue.submissions(PipelineType::Coreceptor);
+        assert_eq!(coreceptors.len(), 1);
+        assert_eq!(coreceptors[0].id, "abc123");
+        assert!(coreceptors[0].submit);
+    }
+
+    #[test]
+    fn test_queue_without_coreceptors() {
+        let queue: QueueAPIData = serde_json
+            ::from_str(
+                r#"{ "ogvs": [], "intacts": [], "tcss": [], "splicings": [], "locators": [] }"#
+            )
+            .unwrap();
+
+        assert!(queue.submissions(PipelineType::Coreceptor).is_empty());
+        assert!(queue.submissions(PipelineType::Base).is_empty());
+    }
+
+    fn submission(submit: bool, pending: bool, created_at: &str) -> SharedAPIData {
+        SharedAPIData {
+            id: "abc123".to_string(),
+            submit,
+            pending,
+            created_at: created_at.to_string(),
+            scheduler_job_id: None,
+            scheduler_state: None,
+            cancel: false,
+            relink: false,
+            attempts: None,
+            submitted_at: None,
+            resources: None,
         }
     }
 
-    for locator in locators {
-        if locator.submit {
-            let pipeline: Pipeline<LocatorAPI> = match
-                Pipeline::new(&locator.id, PipelineType::Locator).await
-            {
-                Ok(p) => p,
-                Err(e) => {
-                    println!("Error creating pipeline: {:?}", e);
-                    continue;
-                }
-            };
+    #[test]
+    fn test_job_name() {
+        assert_eq!(job_name(PipelineType::Tcs, "abc123"), "tcs-abc123");
+        assert_eq!(job_name(PipelineType::Intact, "abc123"), "intact-abc123");
+        assert_eq!(job_name(PipelineType::Coreceptor, "abc123"), "coreceptor-abc123");
+    }
 
-            let (cores, memory) = pipeline.cores_and_memory();
+    #[test]
+    fn test_pipeline_cmd() {
+        let tcs = first_attempt(JobResources { cores: 4, memory: 20000, time: 1440 });
+        let tcs_queue = PipelineQueue::new::<TcsAPI>(&[]).unwrap();
+        let splicing_queue = PipelineQueue::new::<SplicingAPI>(&[]).unwrap();
 
-            let mut cmd = format!("{} --id={} {}", locator_bin_location, &locator.id, &is_dev_cmd);
+        assert_eq!(tcs, JobResources { cores: 5, memory: 20000, time: 1440 });
+        assert_eq!(
+            pipeline_cmd(&tcs_queue, "abc123", tcs.cores, " --is_dev"),
+            format!("{} --id=abc123 --cores=4 --is_dev", bin_location(BinNames::TCSDR).unwrap())
+        );
+        assert_eq!(
+            pipeline_cmd(&splicing_queue, "abc123", 2, ""),
+            format!("{} --id=abc123", bin_location(BinNames::SPLICING).unwrap())
+        );
+    }
 
-            if !is_dev {
-                cmd = create_sbatch_cmd(
-                    &format!("{}/{}.out", &locations.log_dir[PipelineType::Locator], &locator.id),
-                    cores + 1,
-                    &format!("locator-{}", &locator.id),
-                    memory,
-                    1440,
-                    &cmd
-                );
-            }
+    #[tokio::test]
+    async fn test_queues_follow_job_types() {
+        let queue_data: QueueAPIData = serde_json
+            ::from_str(r#"{ "tcss": [{ "id": "abc123", "submit": true }] }"#)
+            .unwrap();
+
+        let mut queues = Queues { queue: &queue_data, queues: Ok(vec![]) };
+        visit_job_types(&mut queues).await;
+        let queue = queues.queues.unwrap();
+
+        let names: Vec<&str> = queue
+            .iter()
+            .map(|queue| queue.name)
+            .collect();
+        assert_eq!(names, vec!["OGV", "Intactness", "TCS/DR", "Splicing", "Locator", "Coreceptor"]);
+        assert_eq!(queue[2].submissions.len(), 1);
+        assert!(queue[1].threaded && queue[2].threaded && !queue[0].threaded);
+        assert!(queue[5].bin_location.ends_with("coreceptor"));
+    }
+
+    #[test]
+    fn test_stale_cmd_parses() {
+        let (is_stale, is_stale_cmd) = pipeline_is_stale(&true, "2020-01-01T00:00:00.000Z", 24);
+        assert!(is_stale);
+
+        let cmd = stale_cmd("/bin/ogv", "abc123", &is_stale_cmd, " --is_dev --config=/etc/l.json");
+        let env_vars = EnvVars::try_parse_from(cmd.split_whitespace()).unwrap();
+
+        assert!(env_vars.is_stale);
+        assert!(env_vars.is_dev);
+        assert_eq!(env_vars.id, "abc123");
+        assert_eq!(env_vars.config.as_deref(), Some("/etc/l.json"));
+
+        let tcs_queue = PipelineQueue::new::<TcsAPI>(&[]).unwrap();
+        let dispatched = pipeline_cmd(&tcs_queue, "abc123", 5, " --is_dev");
+        let env_vars = EnvVars::try_parse_from(dispatched.split_whitespace()).unwrap();
+        assert!(!env_vars.is_stale);
+        assert_eq!(env_vars.cores, 4);
+    }
+
+    #[test]
+    fn test_queue_reads_attempts_and_resources() {
+        let data: SharedAPIData = serde_json
+            ::from_str(
+                r#"{ "id": "abc123", "submit": false, "attempts": 2, "resources": { "cores": 2, "memory": 40000, "time": 1440 } }"#
+            )
+            .unwrap();
+
+        assert_eq!(data.attempts, Some(2));
+        assert_eq!(data.resources.unwrap().memory, 40000);
+    }
+
+    #[test]
+    fn test_failures_are_collected() {
+        let mut failures = Failures::default();
+        failures.report("submitting OGV #abc123", &anyhow::anyhow!("sbatch: error: invalid partition"));
+        failures.report("marking Locator #def456 pending", &anyhow::anyhow!("connection refused"));
+
+        assert_eq!(failures.0.len(), 2);
+        assert_eq!(failures.0[0], "Error submitting OGV #abc123: sbatch: error: invalid partition");
+    }
+
+    #[test]
+    fn test_find_stale() {
+        let old = "2020-01-01T00:00:00.000Z";
+        let recent = chrono::Utc
+            ::now()
+            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            .to_string();
+
+        let submissions = vec![
+            submission(false, true, old),
+            submission(false, true, &recent),
+            submission(false, false, old),
+            submission(true, true, old),
+            submission(false, true, "")
+        ];
+
+        let stale = find_stale(&submissions, 24);
+
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].created_at, old);
+    }
+
+    #[test]
+    fn test_cancelled_are_not_stale() {
+        let mut cancelled = submission(false, true, "2020-01-01T00:00:00.000Z");
+        cancelled.cancel = true;
+
+        assert!(find_stale(&[cancelled], 24).is_empty());
+    }
 
-            run_command(&cmd, &locations.base)?;
+    #[test]
+    fn test_retried_are_stale_from_last_submit() {
+        let mut retried = submission(false, true, "2020-01-01T00:00:00.000Z");
+        retried.attempts = Some(2);
+        retried.submitted_at = Some(api_timestamp());
+        assert!(find_stale(&[retried], 24).is_empty());
 
-            pipeline.send_receipt().await?;
-            pipeline.patch_pending().await?;
+        let mut running = submission(false, true, "2020-01-01T00:00:00.000Z");
+        running.scheduler_state = Some("RUNNING".to_string());
+        assert!(find_stale(&[running], 24).is_empty());
+
+        let mut queued = submission(false, true, "2020-01-01T00:00:00.000Z");
+        queued.submitted_at = Some("2020-01-02T00:00:00.000Z".to_string());
+        queued.scheduler_state = Some("PENDING".to_string());
+        assert_eq!(find_stale(&[queued], 24).len(), 1);
+    }
+
+    #[test]
+    fn test_queue_reads_pending_and_created_at() {
+        let queue: QueueAPIData = serde_json
+            ::from_str(
+                r#"{
+                    "ogvs": [],
+                    "intacts": [],
+                    "tcss": [{ "id": "abc123", "submit": false, "pending": true, "createdAt": "2020-01-01T00:00:00.000Z", "uploadCount": 4 }],
+                    "splicings": [],
+                    "locators": []
+                }"#
+            )
+            .unwrap();
+
+        let tcss = queue.submissions(PipelineType::Tcs);
+        assert!(tcss[0].pending);
+        assert_eq!(find_stale(tcss, 24).len(), 1);
+    }
+
+    #[test]
+    fn test_coreceptor_sbatch_cmd() {
+        let bin = bin_location(BinNames::CORECEPTOR).unwrap();
+        let cmd = SlurmScheduler::new("admin@uni.edu", "/app").sbatch_cmd(
+            &(JobSpec {
+                cmd: format!("{} --id=abc123", bin),
+                cores: 2,
+                memory: 5000,
+                time: 1440,
+                job_name: "coreceptor-abc123".to_string(),
+                output_file: "/log/coreceptor/abc123.out".to_string(),
+            })
+        );
+
+        assert!(bin.ends_with("coreceptor"));
+        assert!(cmd.starts_with("sbatch "));
+        assert!(cmd.contains("-o /log/coreceptor/abc123.out"));
+        assert!(cmd.contains("--cpus-per-task=2"));
+        assert!(cmd.contains("--job-name='coreceptor-abc123'"));
+        assert!(cmd.contains("--mem=5000"));
+        assert!(cmd.contains(&format!("--wrap='{} --id=abc123'", bin)));
+    }
+
+    // answers from a fixed list of states and remembers what it was asked to do
+    #[derive(Default)]
+    struct TestScheduler {
+        states: HashMap<String, JobStatus>,
+        // job_states and cancel fail when asked about these jobs, ex) "tcs-"
+        unreachable: Option<&'static str>,
+        submitted: Mutex<Vec<JobSpec>>,
+        cancelled: Mutex<Vec<String>>,
+    }
+
+    impl Scheduler for TestScheduler {
+        fn submit(&self, job: &JobSpec) -> Result<Option<String>> {
+            self.submitted.lock().unwrap().push(job.clone());
+            Ok(Some("42".to_string()))
+        }
+
+        fn cancel(&self, job_name: &str, _job_id: Option<&str>) -> Result<()> {
+            if self.unreachable.is_some_and(|prefix| job_name.starts_with(prefix)) {
+                return Err(anyhow::anyhow!("scancel: error: slurmctld not responding"));
+            }
+            self.cancelled.lock().unwrap().push(job_name.to_string());
+            Ok(())
+        }
+
+        fn job_states(&self, job_names: &[String]) -> Result<HashMap<String, JobStatus>> {
+            if let Some(prefix) = self.unreachable {
+                if job_names.iter().any(|name| name.starts_with(prefix)) {
+                    return Err(anyhow::anyhow!("sacct: error: slurmdbd not responding"));
+                }
+            }
+            Ok(self.states.clone())
         }
     }
 
-    lock_file.delete()?;
+    fn test_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("process_queue_{}_{}", name, std::process::id()))
+    }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_dispatch_marks_pending() {
+        let api = MockApi::start().await.unwrap();
+        let dir = test_dir("dispatch");
+        let locations = api.locations(&dir);
+        let mut coreceptor = api.submission(PipelineType::Coreceptor, "abc123");
+        coreceptor["email"] = "user@uni.edu".into();
+        api.serve("/api/coreceptor/abc123", coreceptor);
+
+        let submissions = vec![submission(true, false, "")];
+        let scheduler = TestScheduler::default();
+        let fake = Arc::new(FakeTools::new("coreceptor"));
+        let tools: Arc<dyn Tools> = fake.clone();
+        let mut failures = Failures::default();
+
+        dispatch::<CoreceptorAPI>(
+            &PipelineQueue::new::<CoreceptorAPI>(&submissions).unwrap(),
+            &locations,
+            &tools,
+            &scheduler,
+            &UsageStore::new(&locations.base),
+            &mut failures,
+            ""
+        ).await;
+
+        let submitted = scheduler.submitted.lock().unwrap();
+        assert_eq!(submitted.len(), 1);
+        assert_eq!(submitted[0].job_name, "coreceptor-abc123");
+
+        let patches = api.patches("/api/coreceptor/abc123");
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[0], json!({ "pending": true, "submit": false }));
+        assert_eq!(patches[1]["schedulerJobId"], "42");
+        assert_eq!(patches[1]["attempts"], 1);
+        assert_eq!(patches[1]["resources"]["cores"], submitted[0].cores);
+
+        let emails = fake.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Coreceptor Submission #coreceptor_abc123");
+        assert_eq!(emails[0].to, "user@uni.edu");
+        assert!(failures.0.is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_poll_reports_scheduler_state() {
+        let api = MockApi::start().await.unwrap();
+        let dir = test_dir("poll");
+        let locations = api.locations(&dir);
+        api.submission(PipelineType::Tcs, "abc123");
+
+        let scheduler = TestScheduler {
+            states: HashMap::from([
+                (
+                    "tcs-abc123".to_string(),
+                    JobStatus { job_id: "42".to_string(), state: JobState::Running },
+                ),
+            ]),
+            ..Default::default()
+        };
+        let submissions = vec![submission(false, true, "")];
+        let queue = [PipelineQueue::new::<TcsAPI>(&submissions).unwrap()];
+
+        let fake = Arc::new(FakeTools::new("tcsdr"));
+        let tools: Arc<dyn Tools> = fake.clone();
+        let mut failures = Failures::default();
+        poll_job_states(&queue, &scheduler, &locations, &tools, &mut failures, "").await;
+
+        assert!(failures.0.is_empty());
+        assert_eq!(
+            api.patches("/api/tcsdr/abc123"),
+            vec![json!({ "schedulerJobId": "42", "schedulerState": "RUNNING" })]
+        );
+        assert!(fake.emails().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_poll_continues_after_failure() {
+        let api = MockApi::start().await.unwrap();
+        let dir = test_dir("poll_failure");
+        let locations = api.locations(&dir);
+        api.submission(PipelineType::Ogv, "abc123");
+
+        let scheduler = TestScheduler {
+            states: HashMap::from([
+                (
+                    "ogv-abc123".to_string(),
+                    JobStatus { job_id: "42".to_string(), state: JobState::Running },
+                ),
+            ]),
+            unreachable: Some("tcs-"),
+            ..Default::default()
+        };
+        let submissions = vec![submission(false, true, "")];
+        let queue = [
+            PipelineQueue::new::<TcsAPI>(&submissions).unwrap(),
+            PipelineQueue::new::<OgvAPI>(&submissions).unwrap(),
+        ];
+
+        let fake = Arc::new(FakeTools::new("ogv"));
+        let tools: Arc<dyn Tools> = fake.clone();
+        let mut failures = Failures::default();
+        poll_job_states(&queue, &scheduler, &locations, &tools, &mut failures, "").await;
+
+        assert_eq!(failures.0.len(), 1);
+        assert!(failures.0[0].starts_with("Error polling TCS/DR jobs: sacct"));
+        assert_eq!(
+            api.patches("/api/ogv/abc123"),
+            vec![json!({ "schedulerJobId": "42", "schedulerState": "RUNNING" })]
+        );
+        assert!(fake.emails().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_jobs() {
+        let api = MockApi::start().await.unwrap();
+        let dir = test_dir("cancel");
+        let locations = api.locations(&dir);
+        let mut ogv = api.submission(PipelineType::Ogv, "abc123");
+        ogv["email"] = "user@uni.edu".into();
+        api.serve("/api/ogv/abc123", ogv);
+
+        let mut cancelled = submission(false, true, "");
+        cancelled.cancel = true;
+        let submissions = vec![cancelled];
+        let queue = [PipelineQueue::new::<OgvAPI>(&submissions).unwrap()];
+        let scheduler = TestScheduler::default();
+
+        let fake = Arc::new(FakeTools::new("ogv"));
+        let tools: Arc<dyn Tools> = fake.clone();
+
+        let mut failures = Failures::default();
+
+        cancel_jobs(&queue, &scheduler, &locations, &tools, &mut failures).await.unwrap();
+
+        assert_eq!(*scheduler.cancelled.lock().unwrap(), vec!["ogv-abc123".to_string()]);
+        assert_eq!(
+            api.patches("/api/ogv/abc123"),
+            vec![
+                json!({
+                    "pending": false,
+                    "submit": false,
+                    "cancel": false,
+                    "cancelled": true,
+                    "schedulerState": "CANCELLED"
+                })
+            ]
+        );
+        assert_eq!(api.get("/api/ogv/abc123").unwrap()["cancelled"], true);
+        assert!(!Path::new(&format!("{}/scratch/ogv/abc123", dir.display())).exists());
+
+        let emails = fake.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "OGV Submission #abc123 Cancelled");
+        assert_eq!(emails[0].to, "user@uni.edu");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_keeps_unstopped_job() {
+        let api = MockApi::start().await.unwrap();
+        let dir = test_dir("cancel_failure");
+        let locations = api.locations(&dir);
+        api.submission(PipelineType::Ogv, "abc123");
+
+        let mut cancelled = submission(false, true, "");
+        cancelled.cancel = true;
+        let submissions = vec![cancelled];
+        let queue = [PipelineQueue::new::<OgvAPI>(&submissions).unwrap()];
+        let scheduler = TestScheduler { unreachable: Some("ogv-"), ..Default::default() };
+        let fake = Arc::new(FakeTools::new("ogv"));
+        let tools: Arc<dyn Tools> = fake.clone();
+        let mut failures = Failures::default();
+
+        cancel_jobs(&queue, &scheduler, &locations, &tools, &mut failures).await.unwrap();
+
+        assert_eq!(failures.0.len(), 1);
+        assert!(failures.0[0].starts_with("Error cancelling ogv-abc123: scancel"));
+        // still queued, so the scratch dir, the API and the user are left alone
+        assert!(Path::new(&format!("{}/scratch/ogv/abc123", dir.display())).exists());
+        assert!(api.patches("/api/ogv/abc123").is_empty());
+        assert!(fake.emails().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_sweep_cancels_stale_job() {
+        let api = MockApi::start().await.unwrap();
+        let dir = test_dir("sweep");
+        let locations = api.locations(&dir);
+
+        // still waiting in the scheduler's queue long after it was submitted
+        let mut queued = submission(false, true, "2020-01-01T00:00:00.000Z");
+        queued.scheduler_job_id = Some("42".to_string());
+        queued.scheduler_state = Some("PENDING".to_string());
+        let submissions = vec![queued];
+        let queue = [PipelineQueue::new::<TcsAPI>(&submissions).unwrap()];
+        let scheduler = TestScheduler::default();
+        let fake = Arc::new(FakeTools::new("tcsdr"));
+        let tools: Arc<dyn Tools> = fake.clone();
+
+        sweep_stale_jobs(&queue, &scheduler, &locations, &tools, "").await.unwrap();
+
+        assert_eq!(*scheduler.cancelled.lock().unwrap(), vec!["tcs-abc123".to_string()]);
+        assert!(fake.calls()[0].contains("--id=abc123 --is_stale"));
+        let emails = fake.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Stale Submissions (1)");
+        assert!(emails[0].body.contains("was cancelled."));
+
+        // a job the scheduler couldn't stop isn't failed through the binary
+        let scheduler = TestScheduler { unreachable: Some("tcs-"), ..Default::default() };
+        let fake = Arc::new(FakeTools::new("tcsdr"));
+        let tools: Arc<dyn Tools> = fake.clone();
+
+        sweep_stale_jobs(&queue, &scheduler, &locations, &tools, "").await.unwrap();
+
+        assert!(!fake.calls().iter().any(|call| call.contains("--is_stale")));
+        assert!(fake.emails()[0].body.contains("its job tcs-abc123 failed to cancel"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_relink_expired_results() {
+        let api = MockApi::start().await.unwrap();
+        let dir = test_dir("relink");
+        let locations = api.locations(&dir);
+        let mut ogv = api.submission(PipelineType::Ogv, "abc123");
+        ogv["email"] = "user@uni.edu".into();
+        ogv["relink"] = true.into();
+        api.serve("/api/ogv/abc123", ogv);
+
+        let mut relink = submission(false, false, "");
+        relink.relink = true;
+        let fake = Arc::new(FakeTools::new("ogv"));
+        let tools: Arc<dyn Tools> = fake.clone();
+        let mut failures = Failures::default();
+
+        relink_results::<OgvAPI>(&[relink], &locations, &tools, &mut failures, false).await;
+
+        // retention deleted the archive, the user hears it's gone and the admin why
+        let emails = fake.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "OGV Results Expired #ogv_abc123");
+        assert_eq!(emails[0].to, "user@uni.edu");
+        assert!(failures.0[0].starts_with("Error relinking OGV #abc123: No results archive"));
+        assert_eq!(api.get("/api/ogv/abc123").unwrap()["relink"], false);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
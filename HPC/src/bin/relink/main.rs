@@ -0,0 +1,122 @@
+use std::process::exit;
+use utils::{
+    load_env_vars::load_env_vars,
+    load_locations::{ Locations, PipelineType, load_locations },
+    pipeline::{ visit_job_types, JobTypeVisitor, Pipeline, PipelineJob },
+};
+
+// new link to a submission's results, emailed to the user, same as the queue's relink flag
+async fn relink<T: PipelineJob>(id: &str, locations: &Locations) -> anyhow::Result<()> {
+    let pipeline: Pipeline<T> = Pipeline::with_locations(id, T::PIPELINE_TYPE, locations).await?;
+    pipeline.relink().await
+}
+
+// relinks through the job type matching --pipeline, None if nothing matched
+struct Relink<'a> {
+    pipeline_type: PipelineType,
+    id: &'a str,
+    locations: &'a Locations,
+    relinked: Option<anyhow::Result<()>>,
+}
+
+impl JobTypeVisitor for Relink<'_> {
+    async fn visit<T: PipelineJob>(&mut self) {
+        if T::PIPELINE_TYPE == self.pipeline_type {
+            self.relinked = Some(relink::<T>(self.id, self.locations).await);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let env_vars = load_env_vars();
+    let (id, pipeline_type) = match (env_vars.id.as_str(), env_vars.pipeline) {
+        (id, Some(pipeline_type)) if !id.is_empty() => (id.to_owned(), pipeline_type),
+        _ => {
+            println!("Usage: relink --id=<submission id> --pipeline=<ogv|tcs|intact|...>");
+            exit(1);
+        }
+    };
+
+    let locations = load_locations().unwrap_or_else(|e| {
+        println!("Failed to load locations: {:?}", e);
+        exit(1);
+    });
+
+    let mut relink = Relink { pipeline_type, id: &id, locations: &locations, relinked: None };
+    visit_job_types(&mut relink).await;
+    let relinked = relink.relinked.unwrap_or_else(|| {
+        Err(anyhow::anyhow!("{} is not a pipeline", pipeline_type))
+    });
+
+    if let Err(e) = relinked {
+        println!("Failed to relink #{}: {:?}", &id, e);
+        exit(1);
+    }
+    println!("Emailed a renewed results link for #{}", &id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use utils::{
+        mock_api::MockApi,
+        pipeline::OgvAPI,
+        storage::StorageConfig,
+        tools::{ FakeTools, Tools },
+    };
+
+    #[tokio::test]
+    async fn test_relink() {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("relink_{}", std::process::id()));
+        let mut locations = api.locations(&dir);
+        locations.storage = StorageConfig::Gcs {
+            region: "us-east1".to_string(),
+            link_expiry_days: 3,
+        };
+        let mut submission = api.submission(PipelineType::Ogv, "abc123");
+        submission["email"] = "user@uni.edu".into();
+        submission["relink"] = true.into();
+        api.serve("/api/ogv/abc123", submission);
+
+        let tools = Arc::new(FakeTools::new("ogv"));
+        let pipeline = Pipeline::<OgvAPI>
+            ::with_locations("abc123", PipelineType::Ogv, &locations).await
+            .unwrap()
+            .with_tools(tools.clone());
+
+        // nothing to link to once retention has deleted it, the user is told so
+        let error = pipeline.relink().await.unwrap_err().to_string();
+        assert!(error.contains("No results archive for #abc123"));
+        let emails = tools.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "OGV Results Expired #ogv_abc123");
+        assert_eq!(emails[0].to, "user@uni.edu");
+        assert!(emails[0].body.contains("Your results have expired"));
+
+        let archive = dir.join("ogv_abc123.zip");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&archive, "zipped").unwrap();
+        let results = "gs://bucket/ogv-dating/abc123/ogv_abc123.zip";
+        tools.upload(&archive.display().to_string(), results).unwrap();
+        tools.upload(&archive.display().to_string(), &format!("{}.bak", results)).unwrap();
+
+        pipeline.relink().await.unwrap();
+
+        let emails = &tools.emails()[1..];
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "OGV Results Link Renewed #ogv_abc123");
+        assert_eq!(emails[0].to, "user@uni.edu");
+        let link = "https://storage.googleapis.com/bucket/ogv-dating/abc123/ogv_abc123.zip?";
+        assert!(emails[0].body.contains(link));
+        assert!(emails[0].body.contains("Your results link was renewed"));
+        assert!(emails[0].body.contains("X-Goog-Expires=259200"));
+        let expires = (chrono::Utc::now() + chrono::Duration::days(3)).format("%m/%d/%Y");
+        assert!(emails[0].body.contains(&format!("This link expires {}", expires)));
+        assert_eq!(api.get("/api/ogv/abc123").unwrap()["relink"], false);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,60 @@
+use std::process::exit;
+use std::sync::Arc;
+use chrono::Utc;
+use utils::{
+    api_client::ApiClient,
+    load_env_vars::load_env_vars,
+    load_locations::load_locations,
+    retention::{ expire, find_expired, report },
+    storage::storage_backend,
+    tools::ShellTools,
+};
+
+#[tokio::main]
+async fn main() {
+    let dry_run = load_env_vars().dry_run;
+    let locations = load_locations().unwrap_or_else(|e| {
+        println!("Failed to load locations: {:?}", e);
+        exit(1);
+    });
+
+    let storage = storage_backend(
+        &locations.storage,
+        &locations.private_key_location,
+        Arc::new(ShellTools)
+    ).unwrap_or_else(|e| {
+        println!("Failed to set up storage: {:?}", e);
+        exit(1);
+    });
+
+    let expired = find_expired(&locations, storage.as_ref(), Utc::now()).await.unwrap_or_else(
+        |e| {
+            println!("Failed to find expired submissions: {:?}", e);
+            exit(1);
+        }
+    );
+
+    println!("{}", report(&expired, dry_run));
+    if dry_run {
+        return;
+    }
+
+    let api = ApiClient::shared(&locations).unwrap_or_else(|e| {
+        println!("Failed to create API client: {:?}", e);
+        exit(1);
+    });
+
+    // one failure shouldn't keep the rest around
+    let mut failed = 0;
+    for submission in &expired {
+        if let Err(e) = expire(submission, storage.as_ref(), &api, &locations).await {
+            println!("Failed to expire {}: {:?}", &submission.id, e);
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        println!("{} of {} submissions failed to expire", failed, expired.len());
+        exit(1);
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::{ Arc, OnceLock };
+use std::time::Duration;
+use anyhow::{ anyhow, Context, Result };
+use chrono::Utc;
+use reqwest::{ Client, RequestBuilder, StatusCode };
+use serde::{ de::DeserializeOwned, Deserialize, Serialize };
+
+use crate::load_locations::{ Locations, PipelineType };
+use crate::progress::Progress;
+use crate::scheduler::JobResources;
+
+/*
+    Requests to the web API
+    Every request has a timeout, 5xx responses and connection errors are retried with exponential
+    backoff and anything else that isn't 2xx is an error
+    While the API key is rotated a 401 switches to api_key_next for good, clones share the switch
+    Tuned under `api` in locations.json:
+        { "timeout": 30, "connect_timeout": 10, "max_retries": 3, "backoff_ms": 500,
+          "max_backoff_ms": 8000 }
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ApiConfig {
+    // seconds
+    pub timeout: u64,
+    pub connect_timeout: u64,
+    pub max_retries: u32,
+    // first wait, doubled after every retry up to max_backoff_ms
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        ApiConfig {
+            timeout: 30,
+            connect_timeout: 10,
+            max_retries: 3,
+            backoff_ms: 500,
+            max_backoff_ms: 8000,
+        }
+    }
+}
+
+impl ApiConfig {
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.backoff_ms.saturating_mul(2u64.saturating_pow(retry));
+        Duration::from_millis(backoff.min(self.max_backoff_ms))
+    }
+}
+
+// a submission as listed by the queue endpoint
+#[derive(Debug, Deserialize)]
+pub struct SharedAPIData {
+    pub id: String,
+    pub submit: bool,
+    #[serde(default)]
+    pub pending: bool,
+    #[serde(rename = "createdAt", default)]
+    pub created_at: String,
+    #[serde(rename = "schedulerJobId", default)]
+    pub scheduler_job_id: Option<String>,
+    #[serde(rename = "schedulerState", default)]
+    pub scheduler_state: Option<String>,
+    #[serde(default)]
+    pub cancel: bool,
+    // the user asked for a new results link, see Pipeline::relink
+    #[serde(default)]
+    pub relink: bool,
+    #[serde(default)]
+    pub attempts: Option<u32>,
+    // when the latest attempt was handed to the scheduler
+    #[serde(rename = "submittedAt", default)]
+    pub submitted_at: Option<String>,
+    #[serde(default)]
+    pub resources: Option<JobResources>,
+}
+
+impl SharedAPIData {
+    // a retried job has been pending since its last submit, not since it was created
+    pub fn pending_since(&self) -> &str {
+        self.submitted_at.as_deref().unwrap_or(&self.created_at)
+    }
+}
+
+// submissions keyed by the plural of the pipeline's name, ex) "tcss"
+#[derive(Debug, Deserialize, Default)]
+pub struct QueueAPIData(HashMap<String, Vec<SharedAPIData>>);
+
+impl QueueAPIData {
+    // empty when missing so an older web API without a pipeline doesn't empty the whole queue
+    pub fn submissions(&self, pipeline_type: PipelineType) -> &[SharedAPIData] {
+        self.0.get(&format!("{}s", pipeline_type)).map_or(&[], Vec::as_slice)
+    }
+}
+
+// fields to update on a submission, unset fields are left as they are
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PipelinePatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pending: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submit: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing_error: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancel: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancelled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduler_job_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scheduler_state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub submitted_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<JobResources>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<Progress>,
+    // uploads and results were deleted by retention
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expired: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relink: Option<bool>,
+}
+
+impl PipelinePatch {
+    pub fn pending() -> PipelinePatch {
+        PipelinePatch { pending: Some(true), submit: Some(false), ..Default::default() }
+    }
+
+    pub fn completed() -> PipelinePatch {
+        PipelinePatch { pending: Some(false), submit: Some(false), ..Default::default() }
+    }
+
+    pub fn processing_error() -> PipelinePatch {
+        PipelinePatch { pending: Some(false), processing_error: Some(true), ..Default::default() }
+    }
+
+    pub fn expired() -> PipelinePatch {
+        PipelinePatch { expired: Some(true), ..Default::default() }
+    }
+
+    pub fn relinked() -> PipelinePatch {
+        PipelinePatch { relink: Some(false), ..Default::default() }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ApiClient {
+    client: Client,
+    api_key: String,
+    next_key: Option<String>,
+    use_next: Arc<AtomicBool>,
+    config: ApiConfig,
+}
+
+// one client per process so connections are reused
+static SHARED: OnceLock<ApiClient> = OnceLock::new();
+
+impl ApiClient {
+    pub fn new(api_key: &str, config: &ApiConfig) -> Result<ApiClient> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout))
+            .connect_timeout(Duration::from_secs(config.connect_timeout))
+            .build()
+            .context("Failed to build API client.")?;
+
+        Ok(ApiClient {
+            client,
+            api_key: api_key.to_owned(),
+            config: config.clone(),
+            ..Default::default()
+        })
+    }
+
+    pub fn with_next_key(mut self, next_key: Option<&str>) -> ApiClient {
+        self.next_key = next_key.filter(|key| !key.is_empty()).map(str::to_owned);
+        self
+    }
+
+    pub fn shared(locations: &Locations) -> Result<ApiClient> {
+        if let Some(api) = SHARED.get() {
+            return Ok(api.clone());
+        }
+
+        if let Some(warning) = locations.api_key_age_warning(Utc::now().date_naive()) {
+            println!("Warning: {}", warning);
+        }
+
+        let api = ApiClient::new(&locations.api_key, &locations.api)?.with_next_key(
+            locations.api_key_next.as_deref()
+        );
+        Ok(SHARED.get_or_init(|| api).clone())
+    }
+
+    pub async fn fetch<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let body = self.send(url, || self.client.get(url)).await?;
+
+        serde_json::from_str(&body).with_context(|| {
+            format!("Invalid JSON response. url={} body={}", url, snippet(&body))
+        })
+    }
+
+    // submissions waiting for or running on the HPC, all pipelines at once
+    pub async fn queue(&self, locations: &Locations) -> Result<QueueAPIData> {
+        self.fetch(&format!("{}/queue", &locations.api_url[PipelineType::Base])).await
+    }
+
+    pub async fn patch(&self, url: &str, patch: &PipelinePatch) -> Result<()> {
+        self.send(url, || self.client.patch(url).json(patch)).await?;
+        Ok(())
+    }
+
+    async fn send(&self, url: &str, request: impl Fn() -> RequestBuilder) -> Result<String> {
+        let mut retry = 0;
+
+        loop {
+            let response = request().header("x-api-key", self.key()).send().await;
+
+            let error = match response {
+                Ok(response) => {
+                    let status = response.status();
+                    let body = response.text().await.context("Failed to read API response body.")?;
+
+                    if status.is_success() {
+                        return Ok(body);
+                    }
+
+                    let error = anyhow!(
+                        "API request failed. url={} status={} body={}",
+                        url,
+                        status,
+                        snippet(&body)
+                    );
+
+                    if status == StatusCode::UNAUTHORIZED && self.rotate() {
+                        println!("API key rejected, switching to api_key_next.");
+                        continue;
+                    }
+
+                    if !is_retryable(status) {
+                        return Err(error);
+                    }
+                    error
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => {
+                    anyhow!(e).context(format!("API request failed. url={}", url))
+                }
+                Err(e) => {
+                    return Err(anyhow!(e).context(format!("API request failed. url={}", url)));
+                }
+            };
+
+            if retry >= self.config.max_retries {
+                return Err(error.context(format!("Gave up after {} retries.", retry)));
+            }
+
+            let wait = self.config.backoff(retry);
+            println!("{:?}\nRetrying in {}ms.", error, wait.as_millis());
+            tokio::time::sleep(wait).await;
+            retry += 1;
+        }
+    }
+
+    fn key(&self) -> &str {
+        match &self.next_key {
+            Some(next_key) if self.use_next.load(Ordering::Relaxed) => next_key,
+            _ => &self.api_key,
+        }
+    }
+
+    // false when there is no next key or it is already in use
+    fn rotate(&self) -> bool {
+        self.next_key.is_some() && !self.use_next.swap(true, Ordering::Relaxed)
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+}
+
+fn snippet(body: &str) -> String {
+    body.chars().take(500).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_api::MockApi;
+
+    #[test]
+    fn test_backoff() {
+        let config = ApiConfig::default();
+
+        assert_eq!(config.backoff(0), Duration::from_millis(500));
+        assert_eq!(config.backoff(2), Duration::from_millis(2000));
+        assert_eq!(config.backoff(10), Duration::from_millis(8000));
+        assert_eq!(config.backoff(u32::MAX), Duration::from_millis(8000));
+    }
+
+    #[test]
+    fn test_retryable() {
+        assert!(is_retryable(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable(StatusCode::UNAUTHORIZED));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_patch_body() {
+        let body = serde_json::to_value(PipelinePatch::processing_error()).unwrap();
+        assert_eq!(body, serde_json::json!({ "pending": false, "processingError": true }));
+
+        let submitted = PipelinePatch {
+            scheduler_job_id: Some("100".to_string()),
+            attempts: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(submitted).unwrap(),
+            serde_json::json!({ "schedulerJobId": "100", "attempts": 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let api = MockApi::start().await.unwrap();
+        let locations = api.locations(std::path::Path::new("/tmp/api_client"));
+        let client = ApiClient::new("secret", &locations.api).unwrap();
+
+        api.queue(serde_json::json!({}));
+        api.respond("/api/queue", 500, "upstream down");
+        api.respond("/api/queue", 502, "");
+
+        let queue = client.queue(&locations).await.unwrap();
+        assert!(queue.submissions(PipelineType::Tcs).is_empty());
+        assert_eq!(api.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_fail_at_once() {
+        let api = MockApi::start().await.unwrap();
+        let locations = api.locations(std::path::Path::new("/tmp/api_client"));
+        let client = ApiClient::new("wrong", &locations.api).unwrap();
+
+        api.queue(serde_json::json!({}));
+        api.respond("/api/queue", 401, "{\"error\":\"Unauthorized\"}");
+
+        let error = client.queue(&locations).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("status=401"));
+        assert_eq!(api.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_retries() {
+        let api = MockApi::start().await.unwrap();
+        let locations = api.locations(std::path::Path::new("/tmp/api_client"));
+        let client = ApiClient::new("secret", &locations.api).unwrap();
+
+        for _ in 0..=locations.api.max_retries {
+            api.respond("/api/queue", 503, "");
+        }
+
+        assert!(client.queue(&locations).await.is_err());
+        assert_eq!(api.requests().len() as u32, locations.api.max_retries + 1);
+    }
+
+    #[tokio::test]
+    async fn test_rotates_to_next_key() {
+        let api = MockApi::start().await.unwrap();
+        let locations = api.locations(std::path::Path::new("/tmp/api_client"));
+        let client = ApiClient::new("old", &locations.api).unwrap().with_next_key(Some("new"));
+
+        api.queue(serde_json::json!({}));
+        api.respond("/api/queue", 401, "{\"error\":\"Unauthorized\"}");
+
+        client.queue(&locations).await.unwrap();
+        client.clone().queue(&locations).await.unwrap();
+
+        let keys: Vec<Option<String>> = api
+            .requests()
+            .into_iter()
+            .map(|request| request.api_key)
+            .collect();
+        assert_eq!(keys, vec![Some("old".into()), Some("new".into()), Some("new".into())]);
+
+        // rejected again with no key left to try
+        api.respond("/api/queue", 401, "");
+        assert!(client.queue(&locations).await.is_err());
+    }
+}
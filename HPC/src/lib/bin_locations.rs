@@ -12,6 +12,7 @@ impl BinNames {
     pub const INTACTNESS: &str = "intactness";
     pub const SPLICING: &str = "splicing";
     pub const LOCATOR: &str = "locator";
+    pub const CORECEPTOR: &str = "coreceptor";
 }
 
 pub fn bin_location(name: &str) -> anyhow::Result<String> {
@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use anyhow::{ Context, Result };
+use serde::{ de::DeserializeOwned, Serialize };
+
+/*
+    Finished stages of a run, one marker per stage at {scratch_dir}/.checkpoints/{stage}.json
+    A marker holds the stage's output so later stages get it back when the stage is skipped
+    Markers are always written, they're only read back when the binary runs with --resume,
+    a run without --resume starts over and drops the markers of previous runs
+*/
+
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoints {
+    dir: PathBuf,
+    resume: bool,
+}
+
+impl Checkpoints {
+    pub fn new(scratch_dir: &str) -> Checkpoints {
+        Checkpoints { dir: PathBuf::from(scratch_dir).join(".checkpoints"), resume: false }
+    }
+
+    pub fn start(&mut self, resume: bool) -> Result<()> {
+        self.resume = resume;
+
+        if !resume && self.dir.exists() {
+            std::fs
+                ::remove_dir_all(&self.dir)
+                .with_context(|| format!("Failed to clear {}", self.dir.display()))?;
+        }
+
+        Ok(())
+    }
+
+    // output of a stage finished in a previous run
+    pub fn load<R: DeserializeOwned>(&self, stage: &str) -> Option<R> {
+        if !self.resume {
+            return None;
+        }
+
+        let contents = std::fs::read_to_string(self.marker(stage)).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    pub fn done(&self, stage: &str) -> bool {
+        self.load::<serde_json::Value>(stage).is_some()
+    }
+
+    pub fn save<R: Serialize>(&self, stage: &str, output: &R) -> Result<()> {
+        std::fs
+            ::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        // write then rename so a crash never leaves a marker for an unfinished stage
+        let marker = self.marker(stage);
+        let tmp_path = marker.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string(output)?)?;
+        std::fs::rename(&tmp_path, &marker)?;
+        Ok(())
+    }
+
+    // stage names include lib names, keep them to one file name
+    fn marker(&self, stage: &str) -> PathBuf {
+        let file_name: String = stage
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect();
+
+        self.dir.join(format!("{}.json", file_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoints() {
+        let scratch_dir = std::env::temp_dir().join(format!("checkpoints_{}", std::process::id()));
+        let mut checkpoints = Checkpoints::new(&scratch_dir.display().to_string());
+
+        checkpoints.start(false).unwrap();
+        checkpoints.save("download", &()).unwrap();
+        checkpoints.save("compress", &("/tmp/abc123.zip", "abc123.zip")).unwrap();
+        checkpoints.save("library-lib/1", &()).unwrap();
+
+        // markers are only read back when resuming
+        assert!(!checkpoints.done("download"));
+
+        checkpoints.start(true).unwrap();
+        assert!(checkpoints.done("download"));
+        assert!(checkpoints.done("library-lib/1"));
+        assert!(!checkpoints.done("upload"));
+        assert_eq!(
+            checkpoints.load::<(String, String)>("compress"),
+            Some(("/tmp/abc123.zip".to_string(), "abc123.zip".to_string()))
+        );
+
+        // a fresh run drops them
+        checkpoints.start(false).unwrap();
+        checkpoints.start(true).unwrap();
+        assert!(!checkpoints.done("download"));
+
+        std::fs::remove_dir_all(&scratch_dir).unwrap();
+    }
+}
@@ -1,6 +1,21 @@
 use anyhow::{ Result, Context };
-use crate::{ run_command::run_command, load_locations::{ Locations, load_locations } };
+use chrono::{ DateTime, Utc };
+use openssl::pkey::{ PKey, Private };
+use serde::Deserialize;
+use crate::run_command::run_command;
+use crate::signing::hex;
+use crate::storage::StoredObject;
+use crate::signing::{
+    rsa_signature,
+    scope,
+    string_to_sign,
+    uri_encode,
+    CanonicalRequest,
+    GOOG_ALGORITHM,
+    UNSIGNED_PAYLOAD,
+};
 use std::process::Command;
+use std::sync::{ Arc, OnceLock };
 
 pub fn download(from: &str, to_local: &str, download_recursive: bool) -> Result<()> {
     if std::path::Path::new(from).exists() {
@@ -22,37 +37,322 @@ pub fn upload(from_local: &str, to: &str) -> Result<()> {
     Ok(())
 }
 
-pub fn get_signed_url(location: &str) -> Result<String> {
-    // sample output from gsutil signurl
-    // URL     HTTP Method     Expiration      Signed URL
-    // gs://bucket/file      GET     2022-10-12 07:25:31     https://storage.googleapis.com/user/file?x-goog-signature=...&x-goog-algorithm=...&x-goog-credential=....&x-goog-date=...&x-goog-signedheaders=...
+// total bytes stored under a bucket location
+pub fn du(location: &str) -> Result<u64> {
+    let output = Command::new("gsutil")
+        .args(["du", "-s", location])
+        .output()
+        .context("Command failed at gsutil du.")?;
+
+    if !output.status.success() {
+        return Err(
+            anyhow::anyhow!(
+                "gsutil du failed for {}: {}",
+                location,
+                String::from_utf8_lossy(&output.stderr)
+            )
+        );
+    }
+
+    parse_du(&String::from_utf8(output.stdout)?)
+}
+
+// sample output from gsutil du -s
+// 1048576      gs://bucket/id
+pub fn parse_du(du_output: &str) -> Result<u64> {
+    du_output
+        .split_whitespace()
+        .next()
+        .and_then(|bytes| bytes.parse::<u64>().ok())
+        .context("Failed to read size from gsutil du.")
+}
 
-    println!("Bucket location for signed url: {}", &location);
+pub fn rm(location: &str) -> Result<()> {
+    let output = Command::new("gsutil")
+        .args(["rm", location])
+        .output()
+        .context("Command failed at gsutil rm.")?;
 
-    let Locations { private_key_location, .. } = load_locations().unwrap();
+    if !output.status.success() {
+        return Err(
+            anyhow::anyhow!(
+                "gsutil rm failed for {}: {}",
+                location,
+                String::from_utf8_lossy(&output.stderr)
+            )
+        );
+    }
 
-    let args_str = format!("signurl -d 7d -r us-east1 {} {}", private_key_location, &location);
-    let args: Vec<&str> = args_str.split(" ").collect::<Vec<&str>>();
+    Ok(())
+}
 
-    let url = Command::new("gsutil")
-        .args(args)
+// every object whose location starts with `prefix`, with the hashes GCS keeps
+pub fn ls(prefix: &str) -> Result<Vec<StoredObject>> {
+    let pattern = format!("{}**", prefix);
+    let output = Command::new("gsutil")
+        .args(["ls", "-L", &pattern])
         .output()
-        .context("Command failed at generate signed url.")?;
+        .context("Command failed at gsutil ls.")?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        if stderr.contains("matched no objects") {
+            return Ok(vec![]);
+        }
+        return Err(anyhow::anyhow!("gsutil ls failed for {}: {}", pattern, stderr));
+    }
+
+    parse_ls(&String::from_utf8(output.stdout)?)
+}
+
+// sample output from gsutil ls -L, hashes are base64
+// gs://bucket/id/lib1/a.fasta:
+//     Creation time:          Tue, 01 Oct 2024 12:00:00 GMT
+//     Update time:            Tue, 01 Oct 2024 12:00:00 GMT
+//     Content-Length:         1234
+//     Hash (crc32c):          yZRlqg==
+//     Hash (md5):             1B2M2Y8AsgTpgAmY7PhCfg==
+// TOTAL: 1 objects, 1234 bytes (1.21 KiB)
+pub fn parse_ls(ls_output: &str) -> Result<Vec<StoredObject>> {
+    let mut objects: Vec<StoredObject> = vec![];
+
+    for line in ls_output.lines() {
+        if let Some(location) = line.strip_prefix("gs://").and_then(|line| line.strip_suffix(':')) {
+            let location = format!("gs://{}", location);
+            objects.push(StoredObject { location, ..Default::default() });
+            continue;
+        }
+
+        let (Some(object), Some((name, value))) = (objects.last_mut(), line.split_once(':')) else {
+            continue;
+        };
+        let value = value.trim();
+
+        match name.trim() {
+            "Content-Length" => {
+                object.size = value
+                    .parse()
+                    .with_context(|| format!("Bad Content-Length in gsutil ls: {}", value))?;
+            }
+            "Update time" => {
+                let updated = DateTime::parse_from_rfc2822(value)
+                    .with_context(|| format!("Bad Update time in gsutil ls: {}", value))?;
+                object.updated = Some(updated.to_utc());
+            }
+            "Hash (md5)" => {
+                let md5 = openssl::base64::decode_block(value).context("Bad md5 in gsutil ls")?;
+                object.md5 = Some(hex(&md5));
+            }
+            "Hash (crc32c)" => {
+                let crc = openssl::base64::decode_block(value).context("Bad crc32c in gsutil ls")?;
+                let crc: [u8; 4] = crc.try_into().map_err(|_| anyhow::anyhow!("Bad crc32c"))?;
+                object.crc32c = Some(u32::from_be_bytes(crc));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(objects)
+}
+
+// GCS won't accept signed URLs that last longer
+pub const MAX_LINK_EXPIRY_DAYS: u64 = 7;
 
-    let command_output = String::from_utf8(url.stdout)?;
+const GCS_HOST: &str = "storage.googleapis.com";
+
+// what signing needs from a service account key file, like private_key_location
+#[derive(Debug)]
+pub struct ServiceAccount {
+    pub client_email: String,
+    key: PKey<Private>,
+}
 
-    let signed_url: String = command_output
-        .split("https://")
-        .collect::<Vec<&str>>()
-        .pop()
-        .context("Failed to generate signed url. API may have changed.")?
-        .trim()
-        .to_string();
+impl ServiceAccount {
+    pub fn from_json(json: &str) -> Result<ServiceAccount> {
+        #[derive(Deserialize)]
+        struct KeyFile {
+            client_email: String,
+            private_key: String,
+        }
 
-    if !signed_url.contains("storage.googleapis.com") {
-        println!("Signed URL failure: {}", &signed_url);
-        return Err(anyhow::anyhow!("Failed to generate signed url. API may have changed."));
+        let file: KeyFile = serde_json
+            ::from_str(json)
+            .context("Not a service account key, client_email or private_key is missing.")?;
+        let key = PKey::private_key_from_pem(file.private_key.as_bytes()).context(
+            "Failed to read private_key from the service account key."
+        )?;
+
+        Ok(ServiceAccount { client_email: file.client_email, key })
     }
 
-    Ok(format!("https://{}", signed_url))
+    pub fn read(location: &str) -> Result<ServiceAccount> {
+        let json = std::fs
+            ::read_to_string(location)
+            .with_context(|| format!("Failed to read service account key {}", location))?;
+        ServiceAccount::from_json(&json).with_context(|| format!("In {}", location))
+    }
+}
+
+// V4 signed GET URL, ex) gs://bucket/tcs-dr/abc123/results.zip ->
+// https://storage.googleapis.com/bucket/tcs-dr/abc123/results.zip?X-Goog-Algorithm=...
+pub fn signed_url(
+    account: &ServiceAccount,
+    location: &str,
+    region: &str,
+    expires: u64,
+    now: DateTime<Utc>
+) -> Result<String> {
+    let object = location
+        .strip_prefix("gs://")
+        .filter(|object| object.contains('/'))
+        .with_context(|| format!("Not a gs://bucket/object location: {}", location))?;
+
+    let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let scope = scope(&now.format("%Y%m%d").to_string(), region, "storage", "goog4_request");
+
+    let request = CanonicalRequest {
+        method: "GET".to_string(),
+        path: format!("/{}", object),
+        query: vec![
+            ("X-Goog-Algorithm".to_string(), GOOG_ALGORITHM.to_string()),
+            ("X-Goog-Credential".to_string(), format!("{}/{}", &account.client_email, scope)),
+            ("X-Goog-Date".to_string(), timestamp.clone()),
+            ("X-Goog-Expires".to_string(), expires.to_string()),
+            ("X-Goog-SignedHeaders".to_string(), "host".to_string())
+        ],
+        headers: vec![("host".to_string(), GCS_HOST.to_string())],
+        payload_hash: UNSIGNED_PAYLOAD.to_string(),
+    };
+
+    let signature = rsa_signature(
+        &account.key,
+        &string_to_sign(GOOG_ALGORITHM, &timestamp, &scope, &request)
+    )?;
+
+    Ok(
+        format!(
+            "https://{}{}?{}&X-Goog-Signature={}",
+            GCS_HOST,
+            uri_encode(&request.path, false),
+            request.canonical_query(),
+            signature
+        )
+    )
+}
+
+// signs with the key at private_key_location, read once on first use
+#[derive(Debug, Clone)]
+pub struct UrlSigner {
+    key_location: String,
+    region: String,
+    // seconds
+    expires: u64,
+    account: Arc<OnceLock<ServiceAccount>>,
+}
+
+impl UrlSigner {
+    pub fn new(key_location: &str, region: &str, expiry_days: u64) -> UrlSigner {
+        UrlSigner {
+            key_location: key_location.to_owned(),
+            region: region.to_owned(),
+            expires: expiry_days.min(MAX_LINK_EXPIRY_DAYS) * 24 * 60 * 60,
+            account: Arc::new(OnceLock::new()),
+        }
+    }
+
+    pub fn sign(&self, location: &str) -> Result<String> {
+        let account = match self.account.get() {
+            Some(account) => account,
+            None => {
+                let _ = self.account.set(ServiceAccount::read(&self.key_location)?);
+                self.account.get().context("Service account was not loaded.")?
+            }
+        };
+
+        signed_url(account, location, &self.region, self.expires, Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_du() {
+        assert_eq!(parse_du("1048576      gs://bucket/abc123\n").unwrap(), 1048576);
+        assert!(parse_du("").is_err());
+    }
+
+    #[test]
+    fn test_parse_ls() {
+        let output =
+            "gs://bucket/abc123/lib1/a.fasta:\n\
+             \x20   Creation time:          Tue, 01 Oct 2024 12:00:00 GMT\n\
+             \x20   Update time:            Wed, 02 Oct 2024 12:00:00 GMT\n\
+             \x20   Content-Length:         9\n\
+             \x20   Hash (crc32c):          4waSgw==\n\
+             \x20   Hash (md5):             JfnnlDI7RTiF9RgfG2JNCw==\n\
+             gs://bucket/abc123/b.fasta:\n\
+             \x20   Content-Length:         0\n\
+             TOTAL: 2 objects, 9 bytes (9 B)\n";
+        let objects = parse_ls(output).unwrap();
+
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0].location, "gs://bucket/abc123/lib1/a.fasta");
+        assert_eq!(objects[0].size, 9);
+        assert_eq!(objects[0].crc32c, Some(0xe3069283));
+        assert_eq!(objects[0].md5.as_deref(), Some("25f9e794323b453885f5181f1b624d0b"));
+        assert_eq!(objects[0].updated.unwrap().to_rfc3339(), "2024-10-02T12:00:00+00:00");
+        assert_eq!(objects[1].md5, None);
+        assert!(parse_ls("gs://bucket/a:\n    Content-Length: many\n").is_err());
+    }
+
+    #[test]
+    fn test_signed_url() {
+        let key_location = format!(
+            "{}/tests/fixtures/service-account.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        let account = ServiceAccount::read(&key_location).unwrap();
+        let now = DateTime::parse_from_rfc3339("2026-01-02T03:04:05Z").unwrap().to_utc();
+
+        let url = signed_url(&account, "gs://bucket/tcs-dr/abc123/a b.zip", "us-east1", 604800, now)
+            .unwrap();
+        let (unsigned, signature) = url.split_once("&X-Goog-Signature=").unwrap();
+
+        let query = concat!(
+            "X-Goog-Algorithm=GOOG4-RSA-SHA256",
+            "&X-Goog-Credential=primer-id%40primer-id-test.iam.gserviceaccount.com",
+            "%2F20260102%2Fus-east1%2Fstorage%2Fgoog4_request",
+            "&X-Goog-Date=20260102T030405Z&X-Goog-Expires=604800&X-Goog-SignedHeaders=host"
+        );
+        assert_eq!(
+            unsigned,
+            format!("https://storage.googleapis.com/bucket/tcs-dr/abc123/a%20b.zip?{}", query)
+        );
+
+        // the string to sign, written out the way the GCS docs lay it out
+        let canonical_request = format!(
+            "GET\n/bucket/tcs-dr/abc123/a%20b.zip\n{}\nhost:storage.googleapis.com\n\nhost\n{}",
+            query,
+            "UNSIGNED-PAYLOAD"
+        );
+        let string_to_sign = format!(
+            "GOOG4-RSA-SHA256\n20260102T030405Z\n20260102/us-east1/storage/goog4_request\n{}",
+            crate::signing::sha256_hex(canonical_request.as_bytes())
+        );
+
+        let signature: Vec<u8> = (0..signature.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&signature[i..i + 2], 16).unwrap())
+            .collect();
+        let mut verifier = openssl::sign::Verifier
+            ::new(openssl::hash::MessageDigest::sha256(), &account.key)
+            .unwrap();
+        verifier.update(string_to_sign.as_bytes()).unwrap();
+        assert!(verifier.verify(&signature).unwrap());
+
+        assert!(signed_url(&account, "gs://bucket", "us-east1", 60, now).is_err());
+        assert!(ServiceAccount::from_json(r#"{ "client_email": "a@b.c" }"#).is_err());
+    }
 }
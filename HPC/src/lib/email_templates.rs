@@ -22,11 +22,11 @@ fn email_signature() -> String {
     )
 }
 
-// try for a printed expiration timestamp else a generic "in 7 days"
-fn expiration_date() -> String {
-    match Utc::now().checked_add_signed(Duration::days(7)) {
+// try for a printed expiration timestamp else a generic "in {days} days"
+fn expiration_date(days: u64) -> String {
+    match Utc::now().checked_add_signed(Duration::days(days as i64)) {
         Some(date) => date.format("%m/%d/%Y").to_string(),
-        None => { "In 7 days".to_string() }
+        None => { format!("In {} days", days) }
     }
 }
 
@@ -40,16 +40,27 @@ pub fn receipt_email_template(details: &str) -> String {
     receipt
 }
 
-pub fn results_email_template(signed_url: String, extra_notes: &str) -> String {
-    let download_link = format!(
-        "<a href='{}' style='font-size: 16px;'>Download Results</a><br><small>This link expires {}</small>",
+// `expiry_days` of the storage that signed the link, see StorageConfig::link_expiry_days
+fn download_link(signed_url: &str, expiry_days: Option<u64>) -> String {
+    let expires = match expiry_days {
+        Some(days) => format!("<br><small>This link expires {}</small>", expiration_date(days)),
+        None => String::new(),
+    };
+    format!(
+        "<a href='{}' style='font-size: 16px;'>Download Results</a>{}",
         signed_url,
-        expiration_date()
-    );
+        expires
+    )
+}
 
+pub fn results_email_template(
+    signed_url: String,
+    expiry_days: Option<u64>,
+    extra_notes: &str
+) -> String {
     let body = format!(
         "<html><body>Your results are ready for download.<br><br>{}{}{}</body></html>",
-        download_link,
+        download_link(&signed_url, expiry_days),
         extra_notes,
         email_signature()
     );
@@ -57,6 +68,38 @@ pub fn results_email_template(signed_url: String, extra_notes: &str) -> String {
     body
 }
 
+// the same results with a fresh link, sent by relink
+pub fn renewed_link_email_template(signed_url: String, expiry_days: Option<u64>) -> String {
+    let body = format!(
+        "<html><body>Your results link was renewed, your results can be downloaded again.<br><br>{}{}</body></html>",
+        download_link(&signed_url, expiry_days),
+        email_signature()
+    );
+
+    body
+}
+
+pub fn cancelled_email_template(details: &str) -> String {
+    let body = format!(
+        "<html><body>Your submission has been cancelled as requested and its files have been removed.<br><br>{}<br><br>You are welcome to submit again at any time.{}</body></html>",
+        details,
+        email_signature()
+    );
+
+    body
+}
+
+// sent by relink once retention has deleted the results
+pub fn expired_results_email_template(details: &str) -> String {
+    let body = format!(
+        "<html><body>Your results have expired and been deleted, so their link can't be renewed.<br><br>{}<br><br>You are welcome to submit again at any time.{}</body></html>",
+        details,
+        email_signature()
+    );
+
+    body
+}
+
 pub fn generate_tcs_receipt(data: &TcsAPI) -> String {
     let mut content = String::new();
 
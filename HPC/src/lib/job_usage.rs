@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use anyhow::{ Context, Result };
+use chrono::{ DateTime, Duration, Utc };
+use serde::{ Deserialize, Serialize };
+
+use crate::load_locations::PipelineType;
+use crate::resource_estimate::InputSize;
+use crate::scheduler::{ ACCOUNTING_DAYS, JobResources, JobState, JobUsage };
+
+/*
+    Requested vs used resources of every dispatched job, kept at {base}/job_usage.json
+    process_queue adds a record on submit and fills in `used` from sacct once the job ends
+    Estimates switch to past usage of similar inputs once there are enough samples,
+    tuned under `usage` in locations.json:
+        { "percentile": 95.0, "headroom": 1.2, "min_samples": 5, "similar_within": 2.0,
+          "max_records": 1000 }
+    Records sacct never reported on are dropped once they're older than its ACCOUNTING_DAYS,
+    and each pipeline keeps only its newest max_records
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub pipeline_type: PipelineType,
+    pub id: String,
+    pub job_id: String,
+    pub input: InputSize,
+    pub requested: JobResources,
+    pub submitted_at: String,
+    #[serde(default)]
+    pub used: Option<JobUsage>,
+}
+
+impl UsageRecord {
+    pub fn new(
+        pipeline_type: PipelineType,
+        id: &str,
+        job_id: &str,
+        input: InputSize,
+        requested: JobResources
+    ) -> UsageRecord {
+        UsageRecord {
+            pipeline_type,
+            id: id.to_owned(),
+            job_id: job_id.to_owned(),
+            input,
+            requested,
+            submitted_at: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            used: None,
+        }
+    }
+}
+
+pub struct UsageStore {
+    path: PathBuf,
+}
+
+impl UsageStore {
+    pub fn new(base: &str) -> UsageStore {
+        UsageStore { path: PathBuf::from(format!("{}/job_usage.json", base)) }
+    }
+
+    pub fn load(&self) -> Result<Vec<UsageRecord>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+
+        let contents = std::fs
+            ::read_to_string(&self.path)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+
+        serde_json::from_str(&contents).with_context(|| format!("Failed to parse {}", self.path.display()))
+    }
+
+    pub fn save(&self, records: &[UsageRecord]) -> Result<()> {
+        // write then rename so a crash never leaves half a file
+        let tmp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(records)?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+
+    pub fn add(&self, record: UsageRecord) -> Result<()> {
+        let mut records = self.load()?;
+        records.push(record);
+        self.save(&records)
+    }
+
+    // latest record for a submission, retries reuse its input size
+    pub fn latest(&self, id: &str) -> Result<Option<UsageRecord>> {
+        Ok(
+            self
+                .load()?
+                .into_iter()
+                .rev()
+                .find(|record| record.id == id)
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct UsageConfig {
+    pub percentile: f64,
+    // multiplier over the percentile
+    pub headroom: f64,
+    pub min_samples: usize,
+    // inputs are similar when the larger is at most this many times the smaller
+    pub similar_within: f64,
+    // per pipeline, the oldest go first
+    pub max_records: usize,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            percentile: 95.0,
+            headroom: 1.2,
+            min_samples: 5,
+            similar_within: 2.0,
+            max_records: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryEstimate {
+    pub memory: u32,
+    pub time: u32,
+    pub samples: usize,
+}
+
+impl UsageConfig {
+    // memory and time from completed jobs of the same pipeline with a similar input
+    pub fn from_history(
+        &self,
+        records: &[UsageRecord],
+        pipeline_type: PipelineType,
+        input: &InputSize
+    ) -> Option<HistoryEstimate> {
+        let used: Vec<&JobUsage> = records
+            .iter()
+            .filter(|record| record.pipeline_type == pipeline_type)
+            .filter(|record| self.is_similar(&record.input, input))
+            .filter_map(|record| record.used.as_ref())
+            .filter(|used| JobState::from_slurm(&used.state) == JobState::Completed)
+            .collect();
+
+        if used.is_empty() || used.len() < self.min_samples {
+            return None;
+        }
+
+        let memory = percentile(
+            used.iter().map(|u| u.memory).collect(),
+            self.percentile
+        );
+        let time = percentile(
+            used.iter().map(|u| u.time).collect(),
+            self.percentile
+        );
+
+        Some(HistoryEstimate {
+            memory: ((memory as f64) * self.headroom).ceil() as u32,
+            time: ((time as f64) * self.headroom).ceil() as u32,
+            samples: used.len(),
+        })
+    }
+
+    // what collect_usage no longer needs to ask about or keep, false when nothing was dropped
+    pub fn prune(&self, records: &mut Vec<UsageRecord>, now: DateTime<Utc>) -> bool {
+        let count = records.len();
+        let cutoff = now - Duration::days(ACCOUNTING_DAYS);
+
+        records.retain(|record| {
+            record.used.is_some() ||
+                DateTime::parse_from_rfc3339(&record.submitted_at).is_ok_and(|at| at > cutoff)
+        });
+
+        // newest first, so each pipeline's oldest beyond max_records are the ones dropped
+        let mut per_type: HashMap<PipelineType, usize> = HashMap::new();
+        let mut newest: Vec<UsageRecord> = records
+            .drain(..)
+            .rev()
+            .filter(|record| {
+                let n = per_type.entry(record.pipeline_type).or_default();
+                *n += 1;
+                *n <= self.max_records
+            })
+            .collect();
+        newest.reverse();
+        *records = newest;
+
+        records.len() != count
+    }
+
+    fn is_similar(&self, a: &InputSize, b: &InputSize) -> bool {
+        // sequence pipelines are sized by record count, the rest by bytes
+        let (a, b) = if a.sequences > 0 || b.sequences > 0 {
+            (a.sequences as f64, b.sequences as f64)
+        } else {
+            (a.bytes as f64, b.bytes as f64)
+        };
+
+        if a == 0.0 || b == 0.0 {
+            return a == b;
+        }
+
+        a.max(b) / a.min(b) <= self.similar_within
+    }
+}
+
+// nearest-rank percentile
+fn percentile(mut values: Vec<u32>, percentile: f64) -> u32 {
+    values.sort_unstable();
+    let rank = ((percentile / 100.0) * (values.len() as f64)).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(bytes: u64, memory: u32, state: &str) -> UsageRecord {
+        let mut record = UsageRecord::new(
+            PipelineType::Tcs,
+            "abc123",
+            "100",
+            InputSize { bytes, files: 2, sequences: 0 },
+            JobResources { cores: 2, memory: 25000, time: 1440 }
+        );
+        record.used = Some(JobUsage { state: state.to_string(), memory, time: 60 });
+        record
+    }
+
+    #[test]
+    fn test_from_history() {
+        let config = UsageConfig { min_samples: 3, ..UsageConfig::default() };
+        let input = InputSize { bytes: 1_000_000_000, files: 2, sequences: 0 };
+
+        let mut records = vec![
+            record(900_000_000, 4000, "COMPLETED"),
+            record(1_200_000_000, 5000, "COMPLETED"),
+            // too big, failed or still running are left out
+            record(9_000_000_000, 90000, "COMPLETED"),
+            record(1_000_000_000, 1000, "OUT_OF_MEMORY")
+        ];
+        assert_eq!(config.from_history(&records, PipelineType::Tcs, &input), None);
+
+        records.push(record(1_000_000_000, 6000, "COMPLETED"));
+        let estimate = config.from_history(&records, PipelineType::Tcs, &input).unwrap();
+
+        assert_eq!(estimate, HistoryEstimate { memory: 7200, time: 72, samples: 3 });
+        assert_eq!(config.from_history(&records, PipelineType::Ogv, &input), None);
+    }
+
+    #[test]
+    fn test_prune() {
+        let config = UsageConfig { max_records: 2, ..UsageConfig::default() };
+        let now = Utc::now();
+        let days_ago = |days: i64| {
+            (now - Duration::days(days)).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        };
+
+        let unfinished = |job_id: &str, days: i64| UsageRecord {
+            job_id: job_id.to_string(),
+            submitted_at: days_ago(days),
+            used: None,
+            ..record(1, 1, "COMPLETED")
+        };
+        let mut records = vec![
+            // aged out of accounting, or a local job sacct never knew about
+            unfinished("100", 20),
+            unfinished("local-1", 15),
+            unfinished("101", 1),
+            record(1, 1, "COMPLETED")
+        ];
+        assert!(config.prune(&mut records, now));
+        let job_ids: Vec<&str> = records
+            .iter()
+            .map(|record| record.job_id.as_str())
+            .collect();
+        assert_eq!(job_ids, vec!["101", "100"]);
+        assert!(!config.prune(&mut records, now));
+
+        // only the newest max_records of each pipeline are kept
+        records.push(UsageRecord { job_id: "102".to_string(), ..record(1, 1, "COMPLETED") });
+        records.push(UsageRecord { pipeline_type: PipelineType::Ogv, ..record(1, 1, "COMPLETED") });
+        assert!(config.prune(&mut records, now));
+        let job_ids: Vec<(PipelineType, &str)> = records
+            .iter()
+            .map(|record| (record.pipeline_type, record.job_id.as_str()))
+            .collect();
+        assert_eq!(
+            job_ids,
+            vec![
+                (PipelineType::Tcs, "100"),
+                (PipelineType::Tcs, "102"),
+                (PipelineType::Ogv, "100")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(vec![5, 1, 4, 2, 3], 95.0), 5);
+        assert_eq!(percentile(vec![5, 1, 4, 2, 3], 50.0), 3);
+        assert_eq!(percentile(vec![7], 0.0), 7);
+    }
+
+    #[test]
+    fn test_store() {
+        let base = std::env::temp_dir().join(format!("job_usage_{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        let store = UsageStore::new(&base.display().to_string());
+
+        assert!(store.load().unwrap().is_empty());
+
+        store.add(record(1, 1, "COMPLETED")).unwrap();
+        store.add(UsageRecord { job_id: "101".to_string(), ..record(1, 1, "COMPLETED") }).unwrap();
+
+        assert_eq!(store.load().unwrap().len(), 2);
+        assert_eq!(store.latest("abc123").unwrap().unwrap().job_id, "101");
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
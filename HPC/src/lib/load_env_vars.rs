@@ -1,18 +1,37 @@
 use clap::Parser;
 
+use crate::load_locations::PipelineType;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about = "PrimerID CLI options", long_about = None)]
 pub struct EnvVars {
-    #[arg(long)]
+    // process_queue passes --is_dev to the jobs it starts
+    #[arg(long, alias = "is_dev")]
     pub is_dev: bool,
     #[arg(long)]
     pub is_test: bool,
     #[arg(long, default_value = "")]
     pub id: String,
-    #[arg(long, default_value = "")]
-    pub is_stale: String,
+    // how process_queue's stale sweep spells it, see pipeline_is_stale
+    #[arg(long = "is_stale", alias = "is-stale")]
+    pub is_stale: bool,
     #[arg(long, default_value_t = 1)]
     pub cores: usize,
+    // skip stages finished by a previous run of the same submission
+    #[arg(long)]
+    pub resume: bool,
+    // locations file to use instead of the usual search, see load_locations
+    #[arg(long)]
+    pub config: Option<String>,
+    // process_queue only, keep polling instead of running once
+    #[arg(long)]
+    pub daemon: bool,
+    // retention only, report what has expired without deleting anything
+    #[arg(long)]
+    pub dry_run: bool,
+    // relink only, which pipeline --id belongs to
+    #[arg(long)]
+    pub pipeline: Option<PipelineType>,
 }
 
 pub fn load_env_vars() -> EnvVars {
@@ -1,25 +1,42 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::ops::Index;
+use std::str::FromStr;
+use std::path::{ Path, PathBuf };
 use serde::{ Serialize, Deserialize };
 use anyhow::{ Context, Result };
+use chrono::{ NaiveDate, Utc };
 
+use crate::api_client::ApiConfig;
 use crate::load_env_vars::{ load_env_vars, EnvVars };
+use crate::job_usage::UsageConfig;
+use crate::resource_estimate::{ Coefficients, default_estimator };
+use crate::scheduler::{ RetryConfig, SchedulerConfig };
+use crate::secrets::{ check_permissions, key_age_days, lookup, read_secrets_file, SecretsConfig };
+use crate::storage::StorageConfig;
 
-static LOCATIONS_FILE: &'static [u8] = include_bytes!("../../locations.json");
-static LOCATIONS_FILE_DEV: &'static [u8] = include_bytes!("../../locations.dev.json");
-static LOCATIONS_FILE_TEST: &'static [u8] = include_bytes!("../../locations.test.json");
+// without their secrets, see build.rs
+static LOCATIONS_FILE: &'static [u8] = include_bytes!(concat!(env!("OUT_DIR"), "/locations.json"));
+static LOCATIONS_FILE_DEV: &'static [u8] = include_bytes!(
+    concat!(env!("OUT_DIR"), "/locations.dev.json")
+);
+static LOCATIONS_FILE_TEST: &'static [u8] = include_bytes!(
+    concat!(env!("OUT_DIR"), "/locations.test.json")
+);
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PipelineKeys {
-    pub base: String,
-    pub ogv: String,
-    pub tcs: String,
-    pub intact: String,
-    pub coreceptor: String,
-    pub splicing: String,
-    pub locator: String,
+pub struct PipelineKeys<T = String> {
+    pub base: T,
+    pub ogv: T,
+    pub tcs: T,
+    pub intact: T,
+    pub coreceptor: T,
+    pub splicing: T,
+    pub locator: T,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
 pub enum PipelineType {
     Base,
     Ogv,
@@ -30,8 +47,26 @@ pub enum PipelineType {
     Locator,
 }
 
-impl Index<PipelineType> for PipelineKeys {
-    type Output = String;
+// the names in config keys, ex) --pipeline=ogv
+impl FromStr for PipelineType {
+    type Err = String;
+    fn from_str(name: &str) -> std::result::Result<PipelineType, String> {
+        let expected = "ogv, tcs, intact, coreceptor, splicing or locator";
+        serde_json
+            ::from_value(serde_json::Value::String(name.to_owned()))
+            .map_err(|_| format!("unknown pipeline {:?}, expected {}", name, expected))
+    }
+}
+
+// same lowercase names, ex) "intact"
+impl fmt::Display for PipelineType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl<T> Index<PipelineType> for PipelineKeys<T> {
+    type Output = T;
     fn index(&self, index: PipelineType) -> &Self::Output {
         match index {
             PipelineType::Base => &self.base,
@@ -59,28 +94,364 @@ pub struct Locations {
     pub ogv_base_path: String,
     pub coreceptor_base_path: String,
     pub intactness_base_path: String,
+    // secrets may be left out here and resolved from the environment or a secrets file
+    #[serde(default)]
     pub api_key: String,
+    // accepted by the API alongside api_key while rotating, used once api_key is rejected
+    #[serde(default)]
+    pub api_key_next: Option<String>,
+    // YYYY-MM-DD, for the warning once the key is older than secrets.max_key_age_days
+    #[serde(default)]
+    pub api_key_created: Option<String>,
     pub smtp_address: String,
     pub smtp_port: u16,
+    // SMTP AUTH, used when both are set
+    #[serde(default)]
+    pub smtp_username: Option<String>,
+    #[serde(default)]
+    pub smtp_password: Option<String>,
+    #[serde(default)]
+    pub secrets: SecretsConfig,
+    // gcs, local or s3, bucket_url and tcs_log_bucket_url use its scheme, see storage.rs
+    #[serde(default)]
+    pub storage: StorageConfig,
     pub tcs_log_bucket_url: String,
+    #[serde(default = "default_stale_hours")]
+    pub stale_hours: PipelineKeys<i64>,
+    #[serde(default = "default_retention_days")]
+    pub retention_days: PipelineKeys<u64>,
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub retry: RetryConfig,
+    #[serde(default = "default_estimator")]
+    pub estimator: PipelineKeys<Coefficients>,
+    #[serde(default)]
+    pub usage: UsageConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    // seconds between queue checks in process_queue --daemon
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    // least seconds between progress updates a pipeline sends to the web API
+    #[serde(default = "default_progress_interval")]
+    pub progress_interval: u64,
+}
+
+// hours a submission may stay pending before process_queue cancels it
+fn default_stale_hours() -> PipelineKeys<i64> {
+    PipelineKeys {
+        base: 24,
+        ogv: 36,
+        tcs: 24,
+        intact: 24,
+        coreceptor: 24,
+        splicing: 24,
+        locator: 36,
+    }
+}
+
+// days a submission's uploads and results are kept after its last change, 0 keeps them,
+// see retention.rs
+fn default_retention_days() -> PipelineKeys<u64> {
+    PipelineKeys {
+        base: 60,
+        ogv: 60,
+        tcs: 60,
+        intact: 60,
+        coreceptor: 60,
+        splicing: 60,
+        locator: 60,
+    }
+}
+
+fn default_poll_interval() -> u64 {
+    300
+}
+
+fn default_progress_interval() -> u64 {
+    30
+}
+
+/*
+    Where locations come from, the first that exists wins
+        1. --config /path/to/locations.json
+        2. PRIMER_ID_CONFIG=/path/to/locations.json
+        3. $XDG_CONFIG_HOME/primer_id/locations.json (~/.config when unset),
+           then /etc/primer_id/locations.json
+           with --is_dev or --is_test the file is locations.dev.json or locations.test.json
+        4. the profile built into the binary, without its secrets
+    Secrets are then resolved from the environment, secret files or a secrets file, see
+    secrets.rs, and the config itself must not be world-readable if it holds any
+    A built-in profile has no api_key, so one of those must provide it
+*/
+
+pub const CONFIG_ENV: &str = "PRIMER_ID_CONFIG";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    Flag(PathBuf),
+    Env(PathBuf),
+    File(PathBuf),
+    BuiltIn(&'static str),
+}
+
+impl std::fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConfigSource::Flag(path) => write!(f, "{} (--config)", path.display()),
+            ConfigSource::Env(path) => write!(f, "{} ({})", path.display(), CONFIG_ENV),
+            ConfigSource::File(path) => write!(f, "{}", path.display()),
+            ConfigSource::BuiltIn(name) => write!(f, "built-in {}", name),
+        }
+    }
+}
+
+fn profile_file_name(is_dev: bool, is_test: bool) -> &'static str {
+    if is_dev {
+        "locations.dev.json"
+    } else if is_test {
+        "locations.test.json"
+    } else {
+        "locations.json"
+    }
+}
+
+fn built_in(file_name: &'static str) -> &'static [u8] {
+    match file_name {
+        "locations.dev.json" => LOCATIONS_FILE_DEV,
+        "locations.test.json" => LOCATIONS_FILE_TEST,
+        _ => LOCATIONS_FILE,
+    }
+}
+
+// config files looked for when neither --config nor PRIMER_ID_CONFIG is set
+pub fn config_dirs() -> Vec<PathBuf> {
+    let xdg = std::env
+        ::var("XDG_CONFIG_HOME")
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var("HOME").ok().map(|home| Path::new(&home).join(".config")));
+
+    let mut dirs: Vec<PathBuf> = vec![];
+    if let Some(xdg) = xdg {
+        dirs.push(xdg.join("primer_id"));
+    }
+    dirs.push(PathBuf::from("/etc/primer_id"));
+    dirs
+}
+
+pub fn config_source(
+    flag: Option<&str>,
+    env: Option<&str>,
+    dirs: &[PathBuf],
+    file_name: &'static str
+) -> ConfigSource {
+    if let Some(path) = flag.filter(|path| !path.is_empty()) {
+        return ConfigSource::Flag(PathBuf::from(path));
+    }
+    if let Some(path) = env.filter(|path| !path.is_empty()) {
+        return ConfigSource::Env(PathBuf::from(path));
+    }
+
+    dirs.iter()
+        .map(|dir| dir.join(file_name))
+        .find(|path| path.exists())
+        .map_or(ConfigSource::BuiltIn(file_name), ConfigSource::File)
+}
+
+pub fn read_locations(source: &ConfigSource) -> Result<Locations> {
+    let bytes = match source {
+        ConfigSource::Flag(path) | ConfigSource::Env(path) | ConfigSource::File(path) =>
+            std::fs
+                ::read(path)
+                .with_context(|| format!("Failed to read config {}.", source))?,
+        ConfigSource::BuiltIn(file_name) => built_in(file_name).to_vec(),
+    };
+
+    let mut locations: Locations = serde_json
+        ::from_slice(&bytes)
+        .with_context(|| format!("Failed to parse config {}.", source))?;
+
+    let path = match source {
+        ConfigSource::Flag(path) | ConfigSource::Env(path) | ConfigSource::File(path) =>
+            Some(path.as_path()),
+        ConfigSource::BuiltIn(_) => None,
+    };
+    locations
+        .resolve_secrets(|name| std::env::var(name).ok(), path)
+        .with_context(|| format!("Failed to resolve secrets for config {}.", source))?;
+    locations.validate().with_context(|| format!("Invalid config {}.", source))?;
+
+    Ok(locations)
+}
+
+pub fn load_locations_with_source() -> Result<(Locations, ConfigSource)> {
+    let EnvVars { is_dev, is_test, config, .. } = load_env_vars();
+
+    let source = config_source(
+        config.as_deref(),
+        std::env::var(CONFIG_ENV).ok().as_deref(),
+        &config_dirs(),
+        profile_file_name(is_dev, is_test)
+    );
+
+    let locations = read_locations(&source).context("Failed to load locations.")?;
+    Ok((locations, source))
 }
 
 pub fn load_locations() -> Result<Locations> {
-    let EnvVars { is_dev, is_test, .. } = load_env_vars();
-
-    let locations: Locations = serde_json
-        ::from_slice(
-            if is_dev {
-                LOCATIONS_FILE_DEV
-            } else if is_test {
-                LOCATIONS_FILE_TEST
-            } else {
-                LOCATIONS_FILE
+    Ok(load_locations_with_source()?.0)
+}
+
+impl Locations {
+    // `var` reads the environment, `config` is the file these locations were read from
+    pub fn resolve_secrets(
+        &mut self,
+        var: impl Fn(&str) -> Option<String>,
+        config: Option<&Path>
+    ) -> Result<()> {
+        let inline_s3 = matches!(
+            &self.storage,
+            StorageConfig::S3 { secret_access_key, .. } if !secret_access_key.is_empty()
+        );
+        let inline = !self.api_key.is_empty() ||
+            self.api_key_next.is_some() ||
+            self.smtp_password.is_some() ||
+            inline_s3;
+        if let Some(config) = config.filter(|_| inline) {
+            check_permissions(config)?;
+        }
+
+        let secrets_file = match self.secrets.file.as_deref() {
+            Some(file) if !file.is_empty() => read_secrets_file(Path::new(file))?,
+            _ => HashMap::new(),
+        };
+        let resolve = |name: &str| lookup(name, &var, &secrets_file);
+
+        if let Some(api_key) = resolve("api_key")? {
+            self.api_key = api_key;
+        }
+        if let Some(location) = resolve("private_key_location")? {
+            self.private_key_location = location;
+        }
+        self.api_key_next = resolve("api_key_next")?.or(self.api_key_next.take());
+        self.api_key_created = resolve("api_key_created")?.or(self.api_key_created.take());
+        self.smtp_username = resolve("smtp_username")?.or(self.smtp_username.take());
+        self.smtp_password = resolve("smtp_password")?.or(self.smtp_password.take());
+
+        if let StorageConfig::S3 { access_key_id, secret_access_key, .. } = &mut self.storage {
+            if let Some(key) = resolve("s3_access_key_id")? {
+                *access_key_id = key;
+            }
+            if let Some(key) = resolve("s3_secret_access_key")? {
+                *secret_access_key = key;
             }
+        }
+
+        // the service account key gsutil signs URLs with
+        let private_key = Path::new(&self.private_key_location);
+        if private_key.is_file() {
+            check_permissions(private_key)?;
+        }
+
+        Ok(())
+    }
+
+    // None while the key is younger than secrets.max_key_age_days or its age is unknown
+    pub fn api_key_age_warning(&self, today: NaiveDate) -> Option<String> {
+        let age = key_age_days(self.api_key_created.as_deref()?, today).ok()?;
+
+        if age <= self.secrets.max_key_age_days {
+            return None;
+        }
+
+        Some(
+            format!(
+                "The API key is {} days old (secrets.max_key_age_days is {}), rotate it by {}",
+                age,
+                self.secrets.max_key_age_days,
+                "setting api_key_next here and API_KEY_NEXT on the web API."
+            )
         )
-        .context("Failed to load locations.")?;
+    }
 
-    Ok(locations)
+    // every problem at once, so a new deployment isn't fixed one error per run
+    pub fn validate(&self) -> Result<()> {
+        let mut problems: Vec<String> = vec![];
+
+        if !self.admin_email.contains('@') {
+            problems.push(format!("admin_email is not an email address: {:?}", self.admin_email));
+        }
+        if self.api_key.trim().is_empty() {
+            problems.push("api_key is empty".to_string());
+        }
+        if self.smtp_port == 0 {
+            problems.push("smtp_port is 0".to_string());
+        }
+        if let Some(created) = &self.api_key_created {
+            if let Err(e) = key_age_days(created, Utc::now().date_naive()) {
+                problems.push(e.to_string());
+            }
+        }
+        if self.smtp_username.is_some() != self.smtp_password.is_some() {
+            problems.push("smtp_username and smtp_password must be set together".to_string());
+        }
+
+        let scheme = self.storage.scheme();
+        problems.extend(self.storage.problems());
+        if !self.tcs_log_bucket_url.starts_with(scheme) {
+            problems.push(
+                format!(
+                    "tcs_log_bucket_url is not a {} URL: {:?}",
+                    scheme,
+                    &self.tcs_log_bucket_url
+                )
+            );
+        }
+
+        let types = [
+            PipelineType::Base,
+            PipelineType::Ogv,
+            PipelineType::Tcs,
+            PipelineType::Intact,
+            PipelineType::Coreceptor,
+            PipelineType::Splicing,
+            PipelineType::Locator,
+        ];
+
+        for pipeline_type in types {
+            let name = format!("{:?}", pipeline_type).to_lowercase();
+            let api_url = &self.api_url[pipeline_type];
+
+            if !(api_url.starts_with("http://") || api_url.starts_with("https://")) {
+                problems.push(format!("api_url.{} is not an http(s) URL: {:?}", name, api_url));
+            }
+            if !self.bucket_url[pipeline_type].starts_with(scheme) {
+                problems.push(
+                    format!(
+                        "bucket_url.{} is not a {} URL: {:?}",
+                        name,
+                        scheme,
+                        &self.bucket_url[pipeline_type]
+                    )
+                );
+            }
+            if self.log_dir[pipeline_type].is_empty() {
+                problems.push(format!("log_dir.{} is empty", name));
+            }
+            if self.scratch_space[pipeline_type].is_empty() {
+                problems.push(format!("scratch_space.{} is empty", name));
+            }
+        }
+
+        if problems.is_empty() {
+            return Ok(());
+        }
+
+        Err(anyhow::anyhow!("{}", problems.join("\n")))
+    }
 }
 
 #[cfg(test)]
@@ -89,7 +460,21 @@ mod tests {
 
     #[test]
     fn test_read() {
-        let locations: Locations = load_locations().unwrap();
+        // the built-in profile is only valid once the key comes from elsewhere
+        let mut locations: Locations = serde_json
+            ::from_slice(built_in("locations.json"))
+            .unwrap();
+        assert!(format!("{:#}", locations.validate().unwrap_err()).contains("api_key is empty"));
+
+        locations
+            .resolve_secrets(|name| {
+                match name {
+                    "PRIMER_ID_API_KEY" => Some("from-env".to_string()),
+                    _ => None,
+                }
+            }, None)
+            .unwrap();
+        locations.validate().unwrap();
 
         assert!(locations.api_url[PipelineType::Ogv].contains("api"));
         assert!(locations.api_url[PipelineType::Tcs].contains("api"));
@@ -98,4 +483,140 @@ mod tests {
         assert!(locations.api_url[PipelineType::Splicing].contains("api"));
         assert!(locations.api_url[PipelineType::Locator].contains("api"));
     }
+
+    fn example() -> Locations {
+        serde_json::from_str(include_str!("../../locations.example.json")).unwrap()
+    }
+
+    #[test]
+    fn test_built_in_has_no_secrets() {
+        for file_name in ["locations.json", "locations.dev.json", "locations.test.json"] {
+            let profile: serde_json::Value = serde_json::from_slice(built_in(file_name)).unwrap();
+            assert!(profile.get("admin_email").is_some());
+            for secret in ["api_key", "api_key_next", "smtp_username", "smtp_password"] {
+                assert!(profile.get(secret).is_none(), "{} in built-in {}", secret, file_name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_config_source() {
+        let dir = std::env::temp_dir().join(format!("config_source_{}", std::process::id()));
+        let dirs = vec![dir.join("missing"), dir.clone()];
+        std::fs::create_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            config_source(Some("/a.json"), Some("/b.json"), &dirs, "locations.json"),
+            ConfigSource::Flag(PathBuf::from("/a.json"))
+        );
+        assert_eq!(
+            config_source(None, Some("/b.json"), &dirs, "locations.json"),
+            ConfigSource::Env(PathBuf::from("/b.json"))
+        );
+        assert_eq!(
+            config_source(None, Some(""), &dirs, "locations.json"),
+            ConfigSource::BuiltIn("locations.json")
+        );
+
+        std::fs::write(dir.join("locations.dev.json"), "{}").unwrap();
+        assert_eq!(
+            config_source(None, None, &dirs, "locations.dev.json"),
+            ConfigSource::File(dir.join("locations.dev.json"))
+        );
+        assert_eq!(
+            config_source(None, None, &dirs, "locations.json"),
+            ConfigSource::BuiltIn("locations.json")
+        );
+
+        let invalid = ConfigSource::File(dir.join("locations.dev.json"));
+        let error = read_locations(&invalid).unwrap_err();
+        assert!(format!("{:#}", error).starts_with("Failed to parse config"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(example().validate().is_ok());
+
+        let mut locations = example();
+        locations.api_key = "".to_string();
+        locations.api_url.tcs = "localhost:3000/api/tcsdr".to_string();
+        locations.bucket_url.ogv = "bucket/ogv-dating".to_string();
+
+        let error = locations.validate().unwrap_err().to_string();
+        assert_eq!(
+            error.lines().collect::<Vec<&str>>(),
+            vec![
+                "api_key is empty",
+                "bucket_url.ogv is not a gs:// URL: \"bucket/ogv-dating\"",
+                "api_url.tcs is not an http(s) URL: \"localhost:3000/api/tcsdr\""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_secrets() {
+        let dir = std::env::temp_dir().join(format!("resolve_secrets_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let secrets = dir.join("secrets.json");
+        std::fs::write(&secrets, r#"{ "smtp_username": "hpc", "smtp_password": "pw" }"#).unwrap();
+
+        let mut locations = example();
+        locations.secrets.file = Some(secrets.display().to_string());
+        locations.api_key_next = Some("next".to_string());
+        let error = locations.clone().resolve_secrets(|_| None, None).unwrap_err();
+        assert!(error.to_string().contains("world-readable"));
+
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&secrets, std::fs::Permissions::from_mode(0o600)).unwrap();
+        locations
+            .resolve_secrets(|name| {
+                match name {
+                    "PRIMER_ID_API_KEY" => Some("from-env".to_string()),
+                    _ => None,
+                }
+            }, None)
+            .unwrap();
+
+        assert_eq!(locations.api_key, "from-env");
+        assert_eq!(locations.api_key_next.as_deref(), Some("next"));
+        assert_eq!(locations.smtp_username.as_deref(), Some("hpc"));
+        assert_eq!(locations.smtp_password.as_deref(), Some("pw"));
+        assert_eq!(locations.private_key_location, "/app/storage-admin.json");
+
+        // a config holding secrets is checked like a secrets file
+        let config = dir.join("locations.json");
+        std::fs::write(&config, "{}").unwrap();
+        std::fs::set_permissions(&config, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(example().resolve_secrets(|_| None, Some(&config)).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_api_key_age_warning() {
+        let today = NaiveDate::from_ymd_opt(2026, 10, 1).unwrap();
+        let mut locations = example();
+        assert_eq!(locations.api_key_age_warning(today), None);
+
+        locations.api_key_created = Some("2026-08-01".to_string());
+        assert_eq!(locations.api_key_age_warning(today), None);
+
+        locations.api_key_created = Some("2026-01-01".to_string());
+        assert!(locations.api_key_age_warning(today).unwrap().contains("273 days old"));
+
+        locations.api_key_created = Some("01/01/2026".to_string());
+        assert!(locations.validate().unwrap_err().to_string().contains("YYYY-MM-DD"));
+    }
+
+    #[test]
+    fn test_validate_storage() {
+        let mut locations = example();
+        locations.storage = StorageConfig::Local { root: "/srv".to_string(), public_url: None };
+
+        let error = locations.validate().unwrap_err().to_string();
+        assert!(error.starts_with("tcs_log_bucket_url is not a file:// URL: \"gs://bucket\""));
+        assert_eq!(error.lines().count(), 8);
+    }
 }
@@ -27,6 +27,16 @@ impl LockFile {
 
         Ok(())
     }
+    // a run holding the lock for longer than is_stale allows refreshes it while it works
+    pub fn touch(&self) -> Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .open(&self.file_path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .with_context(|| { format!("Could not refresh lock file: {}", &self.file_path) })?;
+
+        Ok(())
+    }
     pub fn delete(&self) -> Result<()> {
         fs::remove_file(&self.file_path)?;
         Ok(())
@@ -73,4 +83,24 @@ mod tests {
         let exists_deleted = lock_file.exists().unwrap();
         assert_eq!(exists_deleted, false);
     }
+
+    #[test]
+    fn test_touch_keeps_lock_fresh() {
+        let lock_file = LockFile::new("test_touch.lock".to_string());
+        lock_file.create().unwrap();
+
+        let thirteen_hours_ago = SystemTime::now() - Duration::from_secs(13 * 60 * 60);
+        OpenOptions::new()
+            .write(true)
+            .open("test_touch.lock")
+            .unwrap()
+            .set_modified(thirteen_hours_ago)
+            .unwrap();
+        assert!(lock_file.is_stale().unwrap());
+
+        lock_file.touch().unwrap();
+        assert!(!lock_file.is_stale().unwrap());
+
+        lock_file.delete().unwrap();
+    }
 }
@@ -0,0 +1,232 @@
+use std::cell::RefCell;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{ Arc, Mutex, OnceLock };
+use std::time::Duration;
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::load_locations::PipelineType;
+
+/*
+    Pipeline logs, one JSON object per line at {log_dir}/{id}.log
+        {"timestamp":"...","level":"info","pipeline":"tcs","id":"abc123","stage":"tcs",
+         "library":"lib1","message":"Running DR command: ..."}
+    External commands add {"command":{"cmd":"...","status":0,"duration_ms":1234}}
+    The console gets the same records in a readable format
+        [2026-01-01T00:00:00Z] tcs abc123 [tcs] [lib1] Running DR command: ...
+    `stage` follows Pipeline::stage, `library` is set per thread with library_span
+*/
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CommandRecord {
+    pub cmd: String,
+    // None when killed by a signal or it never started
+    pub status: Option<i32>,
+    pub duration_ms: u128,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct LogRecord {
+    pub timestamp: String,
+    pub level: Level,
+    pub pipeline: PipelineType,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub library: Option<String>,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<CommandRecord>,
+}
+
+impl LogRecord {
+    pub fn console(&self) -> String {
+        let mut line = format!("[{}] ", self.timestamp);
+
+        match self.level {
+            Level::Info => {}
+            Level::Warn => line.push_str("WARN "),
+            Level::Error => line.push_str("ERROR "),
+        }
+
+        let pipeline = format!("{:?}", self.pipeline).to_lowercase();
+        line.push_str(&format!("{} {}", pipeline, self.id));
+
+        if let Some(stage) = &self.stage {
+            line.push_str(&format!(" [{}]", stage));
+        }
+        if let Some(library) = &self.library {
+            line.push_str(&format!(" [{}]", library));
+        }
+
+        line.push_str(&format!(" {}", self.message));
+
+        if let Some(command) = &self.command {
+            let status = command.status.map_or("none".to_string(), |status| status.to_string());
+            line.push_str(&format!(" (exit {}, {}ms)", status, command.duration_ms));
+        }
+
+        line
+    }
+}
+
+thread_local! {
+    static LIBRARY: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+// tags every record on this thread with the library until dropped
+pub struct LibrarySpan {
+    previous: Option<String>,
+}
+
+pub fn library_span(library: &str) -> LibrarySpan {
+    let previous = LIBRARY.with(|current| current.replace(Some(library.to_owned())));
+    LibrarySpan { previous }
+}
+
+impl Drop for LibrarySpan {
+    fn drop(&mut self) {
+        LIBRARY.with(|current| {
+            *current.borrow_mut() = self.previous.take();
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Logger {
+    pipeline_type: PipelineType,
+    id: String,
+    file: PathBuf,
+    stage: Arc<Mutex<Option<String>>>,
+}
+
+// Pipeline skips it when deserializing
+impl Default for Logger {
+    fn default() -> Self {
+        Logger::new(PipelineType::Base, "", "/dev/null")
+    }
+}
+
+impl Logger {
+    pub fn new(pipeline_type: PipelineType, id: &str, file: &str) -> Logger {
+        Logger {
+            pipeline_type,
+            id: id.to_owned(),
+            file: PathBuf::from(file),
+            stage: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn stage(&self, stage: &str) {
+        *self.stage.lock().unwrap() = Some(stage.to_owned());
+    }
+
+    pub fn record(&self, level: Level, message: &str) -> LogRecord {
+        LogRecord {
+            timestamp: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            level,
+            pipeline: self.pipeline_type,
+            id: self.id.clone(),
+            stage: self.stage.lock().unwrap().clone(),
+            library: LIBRARY.with(|current| current.borrow().clone()),
+            message: message.to_owned(),
+            command: None,
+        }
+    }
+
+    pub fn write(&self, record: &LogRecord) -> std::io::Result<()> {
+        println!("{}", record.console());
+
+        // a single write per line keeps lines from rayon workers whole
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.file)?;
+        file.write_all(line.as_bytes())
+    }
+
+    pub fn log(&self, level: Level, message: &str) -> std::io::Result<()> {
+        self.write(&self.record(level, message))
+    }
+
+    pub fn command(&self, cmd: &str, status: Option<i32>, duration: Duration) {
+        let level = if status == Some(0) { Level::Info } else { Level::Warn };
+        let mut record = self.record(level, "Command finished.");
+        record.command = Some(CommandRecord {
+            cmd: cmd.to_owned(),
+            status,
+            duration_ms: duration.as_millis(),
+        });
+
+        if let Err(e) = self.write(&record) {
+            eprintln!("Couldn't write to log file: {}", e);
+        }
+    }
+}
+
+// the running pipeline's logger, for code without a Pipeline such as run_command
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+pub fn init(logger: Logger) {
+    let _ = LOGGER.set(logger);
+}
+
+pub fn log_command(cmd: &str, status: Option<i32>, duration: Duration) {
+    match LOGGER.get() {
+        Some(logger) => logger.command(cmd, status, duration),
+        None => println!("Command exited with {:?} after {}ms", status, duration.as_millis()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_record() {
+        let logger = Logger::new(PipelineType::Tcs, "abc123", "/dev/null");
+        logger.stage("tcs");
+
+        let record = {
+            let _span = library_span("lib1");
+            logger.record(Level::Info, "Running DR command.")
+        };
+        let json: serde_json::Value = serde_json::to_value(&record).unwrap();
+
+        assert_eq!(json["pipeline"], "tcs");
+        assert_eq!(json["stage"], "tcs");
+        assert_eq!(json["library"], "lib1");
+        assert!(json.get("command").is_none());
+        assert!(record.console().ends_with("tcs abc123 [tcs] [lib1] Running DR command."));
+
+        // the span ends with its scope
+        assert_eq!(logger.record(Level::Info, "done").library, None);
+    }
+
+    #[test]
+    fn test_command_record() {
+        let logger = Logger::new(PipelineType::Ogv, "abc123", "/dev/null");
+        let mut record = logger.record(Level::Warn, "Command finished.");
+        record.command = Some(CommandRecord {
+            cmd: "ls".to_string(),
+            status: Some(2),
+            duration_ms: 15,
+        });
+
+        let json: serde_json::Value = serde_json::to_value(&record).unwrap();
+        assert_eq!(json["level"], "warn");
+        assert_eq!(json["command"]["status"], 2);
+        assert!(record.console().contains("WARN ogv abc123 Command finished. (exit 2, 15ms)"));
+    }
+}
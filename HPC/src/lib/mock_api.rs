@@ -0,0 +1,376 @@
+use std::collections::{ HashMap, VecDeque };
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{ Arc, Mutex };
+use anyhow::{ Context, Result };
+use serde_json::{ json, Value };
+use tokio::io::{ AsyncReadExt, AsyncWriteExt };
+use tokio::net::{ TcpListener, TcpStream };
+
+use crate::load_locations::{ Locations, PipelineKeys, PipelineType };
+
+/*
+    In-process stand-in for the web API, for tests of anything that talks to it
+        let api = MockApi::start().await?;
+        let locations = api.locations(&scratch);
+        api.submission(PipelineType::Ogv, "abc123");
+        ... Pipeline::with_locations("abc123", PipelineType::Ogv, &locations) ...
+        assert_eq!(api.patches("/api/ogv/abc123"), vec![json!({ "pending": true, ... })]);
+    GETs return the JSON served at the path, PATCHes are recorded and merged into it, so a
+    later GET sees the change like the real API. Anything else is a 404
+    Paths are the ones in locations.example.json, ex) /api/queue, /api/tcsdr/{id}
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub api_key: Option<String>,
+    // Null when there was no body
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    served: HashMap<String, Value>,
+    // one-off responses used before the served JSON, ex) a 500 to test retries
+    scripted: HashMap<String, VecDeque<(u16, String)>>,
+    requests: Vec<MockRequest>,
+}
+
+pub struct MockApi {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl MockApi {
+    pub async fn start() -> Result<MockApi> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.context(
+            "Failed to bind mock API."
+        )?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = state.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, state).await {
+                        println!("Mock API error: {:?}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(MockApi { addr, state, server })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/api", self.addr)
+    }
+
+    // locations.example.json pointed at this server, with logs and scratch space under `dir`
+    pub fn locations(&self, dir: &Path) -> Locations {
+        let mut locations: Locations = serde_json
+            ::from_str(include_str!("../../locations.example.json"))
+            .expect("locations.example.json is valid");
+
+        let url = self.url();
+        let dir = dir.display();
+
+        locations.base = dir.to_string();
+        locations.api_url = pipeline_keys(|segment| {
+            if segment.is_empty() { url.clone() } else { format!("{}/{}", &url, segment) }
+        });
+        locations.log_dir = pipeline_keys(|segment| format!("{}/log/{}", dir, segment));
+        locations.scratch_space = pipeline_keys(|segment| format!("{}/scratch/{}", dir, segment));
+        // a throwaway key, so results links get signed for real
+        locations.private_key_location = format!(
+            "{}/tests/fixtures/service-account.json",
+            env!("CARGO_MANIFEST_DIR")
+        );
+        // fail fast, the tests script their own retries
+        locations.api.backoff_ms = 1;
+        locations.api.max_backoff_ms = 1;
+        locations
+    }
+
+    pub fn serve(&self, path: &str, body: Value) {
+        self.state.lock().unwrap().served.insert(path.to_owned(), body);
+    }
+
+    // the next request to `path` gets this instead, queued responses are used in order
+    pub fn respond(&self, path: &str, status: u16, body: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .scripted.entry(path.to_owned())
+            .or_default()
+            .push_back((status, body.to_owned()));
+    }
+
+    // a minimal valid submission of the type, served at its API path
+    pub fn submission(&self, pipeline_type: PipelineType, id: &str) -> Value {
+        let body = submission(pipeline_type, id);
+        self.serve(&submission_path(pipeline_type, id), body.clone());
+        body
+    }
+
+    pub fn queue(&self, queue: Value) {
+        let mut full = json!({
+            "ogvs": [],
+            "intacts": [],
+            "tcss": [],
+            "splicings": [],
+            "locators": [],
+            "coreceptors": [],
+        });
+        merge(&mut full, &queue);
+        self.serve("/api/queue", full);
+    }
+
+    // the JSON at `path` with every PATCH so far applied
+    pub fn get(&self, path: &str) -> Option<Value> {
+        self.state.lock().unwrap().served.get(path).cloned()
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    // PATCH bodies sent to `path`, oldest first
+    pub fn patches(&self, path: &str) -> Vec<Value> {
+        self.requests()
+            .into_iter()
+            .filter(|request| request.method == "PATCH" && request.path == path)
+            .map(|request| request.body)
+            .collect()
+    }
+}
+
+impl Drop for MockApi {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+// the path segment of each pipeline under /api, as in locations.example.json
+pub fn api_segment(pipeline_type: PipelineType) -> &'static str {
+    match pipeline_type {
+        PipelineType::Base => "",
+        PipelineType::Ogv => "ogv",
+        PipelineType::Tcs => "tcsdr",
+        PipelineType::Intact => "intactness",
+        PipelineType::Coreceptor => "coreceptor",
+        PipelineType::Splicing => "splicing",
+        PipelineType::Locator => "locator",
+    }
+}
+
+pub fn submission_path(pipeline_type: PipelineType, id: &str) -> String {
+    format!("/api/{}/{}", api_segment(pipeline_type), id)
+}
+
+pub fn submission(pipeline_type: PipelineType, id: &str) -> Value {
+    let mut body =
+        json!({
+        "id": id,
+        "createdAt": "2026-01-01T00:00:00.000Z",
+        "jobID": "",
+        "resultsFormat": "zip",
+        "email": "",
+        "submit": true,
+        "pending": false,
+        "processingError": false,
+    });
+
+    let extra = match pipeline_type {
+        PipelineType::Base => json!({}),
+        PipelineType::Ogv => json!({ "uploads": [], "conversion": {} }),
+        PipelineType::Tcs => json!({ "uploads": [], "primers": [], "drVersion": "v1" }),
+        PipelineType::Intact | PipelineType::Coreceptor => json!({ "sequences": ">seq1\nACGT\n" }),
+        PipelineType::Splicing =>
+            json!({
+                "strain": "NL43",
+                "assay": "ALL",
+                "distance": 15,
+                "sequence": "",
+                "uploads": [],
+            }),
+        PipelineType::Locator => json!({ "refGenome": "HXB2", "uploads": [] }),
+    };
+
+    merge(&mut body, &extra);
+    body
+}
+
+fn pipeline_keys(value: impl Fn(&str) -> String) -> PipelineKeys {
+    PipelineKeys {
+        base: value(api_segment(PipelineType::Base)),
+        ogv: value(api_segment(PipelineType::Ogv)),
+        tcs: value(api_segment(PipelineType::Tcs)),
+        intact: value(api_segment(PipelineType::Intact)),
+        coreceptor: value(api_segment(PipelineType::Coreceptor)),
+        splicing: value(api_segment(PipelineType::Splicing)),
+        locator: value(api_segment(PipelineType::Locator)),
+    }
+}
+
+// top level keys of `patch` replace those of `target`, like the API's update
+fn merge(target: &mut Value, patch: &Value) {
+    if let (Some(target), Some(patch)) = (target.as_object_mut(), patch.as_object()) {
+        for (key, value) in patch {
+            target.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+// an HTTP/1.1 request as read off the socket, header names lowercase
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RawRequest {
+    pub method: String,
+    // with the query string
+    pub target: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+// None when the client hung up before sending anything
+pub(crate) async fn read_request(stream: &mut TcpStream) -> Result<Option<RawRequest>> {
+    let mut buffer: Vec<u8> = vec![];
+    let mut chunk = [0u8; 4096];
+
+    // headers first, then however much body Content-Length says
+    let header_end = loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("").to_owned();
+    let target = request_line.next().unwrap_or("").to_owned();
+
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_owned()))
+        .collect();
+
+    let length: usize = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+
+    let end = buffer.len().min(header_end + length);
+    let body = buffer[header_end..end].to_vec();
+
+    Ok(Some(RawRequest { method, target, headers, body }))
+}
+
+pub(crate) async fn write_response(
+    stream: &mut TcpStream,
+    status: u16,
+    headers: &[(&str, String)],
+    body: &[u8]
+) -> Result<()> {
+    let reason = match status {
+        200..=299 => "OK",
+        404 => "Not Found",
+        400..=499 => "Client Error",
+        _ => "Server Error",
+    };
+
+    let mut head = format!("HTTP/1.1 {} {}\r\nContent-Length: {}\r\n", status, reason, body.len());
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("Connection: close\r\n\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+async fn handle(mut stream: TcpStream, state: Arc<Mutex<State>>) -> Result<()> {
+    let Some(request) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+    let RawRequest { method, target: path, headers, body } = request;
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let (status, response) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(MockRequest {
+            method: method.clone(),
+            path: path.clone(),
+            api_key: headers.get("x-api-key").cloned(),
+            body: body.clone(),
+        });
+
+        let scripted = state.scripted.get_mut(&path).and_then(|responses| responses.pop_front());
+
+        match (scripted, state.served.get_mut(&path)) {
+            (Some(scripted), _) => scripted,
+            (None, Some(served)) if method == "PATCH" => {
+                merge(served, &body);
+                (200, served.to_string())
+            }
+            (None, Some(served)) if method == "GET" => (200, served.to_string()),
+            _ => (404, json!({ "error": "Not found" }).to_string()),
+        }
+    };
+
+    let content_type = ("Content-Type", "application/json".to_string());
+    write_response(&mut stream, status, &[content_type], response.as_bytes()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_client::{ ApiClient, PipelinePatch };
+
+    #[tokio::test]
+    async fn test_serves_and_records() {
+        let api = MockApi::start().await.unwrap();
+        let locations = api.locations(Path::new("/tmp/mock_api"));
+        let client = ApiClient::new("secret", &locations.api).unwrap();
+
+        api.submission(PipelineType::Ogv, "abc123");
+        let url = format!("{}/abc123", &locations.api_url[PipelineType::Ogv]);
+
+        let data: Value = client.fetch(&url).await.unwrap();
+        assert_eq!(data["id"], "abc123");
+
+        client.patch(&url, &PipelinePatch::pending()).await.unwrap();
+
+        assert_eq!(
+            api.patches("/api/ogv/abc123"),
+            vec![json!({ "pending": true, "submit": false })]
+        );
+        assert_eq!(api.get("/api/ogv/abc123").unwrap()["pending"], true);
+        assert_eq!(api.requests()[0].api_key.as_deref(), Some("secret"));
+
+        let missing: Result<Value> = client.fetch(&format!("{}/missing", api.url())).await;
+        assert!(missing.is_err());
+    }
+}
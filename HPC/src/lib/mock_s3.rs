@@ -0,0 +1,340 @@
+use std::collections::{ BTreeMap, HashMap };
+use std::net::SocketAddr;
+use std::sync::{ Arc, Mutex };
+use anyhow::{ Context, Result };
+use chrono::{ DateTime, NaiveDateTime, SecondsFormat, Utc };
+use openssl::hash::{ hash, MessageDigest };
+use tokio::net::{ TcpListener, TcpStream };
+
+use crate::mock_api::{ read_request, write_response, RawRequest };
+use crate::signing::{
+    aws_signature,
+    aws_signing_key,
+    hex,
+    sha256_hex,
+    string_to_sign,
+    CanonicalRequest,
+    AWS_ALGORITHM,
+    UNSIGNED_PAYLOAD,
+};
+
+/*
+    In-process stand-in for an S3-compatible server like MinIO, for tests of S3Storage
+        let s3 = MockS3::start().await?;
+        S3Storage::new(&s3.endpoint(), "us-east-1", MOCK_ACCESS_KEY, MOCK_SECRET_KEY)?
+        s3.put("bucket/ogv/abc123/a.fasta", b">a\nACGT\n");
+        ... s3.object("bucket/ogv/abc123/results.zip")
+    Requests must be signed with the mock's keys, in headers or as a presigned URL, or they get
+    a 403 like from a real server. Objects are kept in memory under "{bucket}/{key}"
+    Supports PUT, GET (with a Range to continue from) and DELETE of objects and ListObjectsV2 in
+    pages of `page_size` keys
+*/
+
+pub const MOCK_ACCESS_KEY: &str = "MOCKACCESSKEY";
+pub const MOCK_SECRET_KEY: &str = "mock/secret/key";
+
+#[derive(Debug)]
+struct State {
+    objects: BTreeMap<String, Vec<u8>>,
+    modified: HashMap<String, DateTime<Utc>>,
+    // "{method} {path}", oldest first
+    requests: Vec<String>,
+    page_size: usize,
+}
+
+pub struct MockS3 {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    server: tokio::task::JoinHandle<()>,
+}
+
+impl MockS3 {
+    pub async fn start() -> Result<MockS3> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.context("Failed to bind mock S3.")?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(
+            Mutex::new(State {
+                objects: BTreeMap::new(),
+                modified: HashMap::new(),
+                requests: vec![],
+                page_size: 1000,
+            })
+        );
+
+        let server_state = state.clone();
+        let server = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream, state).await {
+                        println!("Mock S3 error: {:?}", e);
+                    }
+                });
+            }
+        });
+
+        Ok(MockS3 { addr, state, server })
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    // keys per ListObjectsV2 page, small pages test continuation tokens
+    pub fn set_page_size(&self, page_size: usize) {
+        self.state.lock().unwrap().page_size = page_size;
+    }
+
+    // `path` is "{bucket}/{key}"
+    pub fn put(&self, path: &str, body: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        state.objects.insert(path.to_owned(), body.to_vec());
+        state.modified.insert(path.to_owned(), Utc::now());
+    }
+
+    // LastModified in listings, ex) to make an object old enough to expire
+    pub fn set_modified(&self, path: &str, modified: DateTime<Utc>) {
+        self.state.lock().unwrap().modified.insert(path.to_owned(), modified);
+    }
+
+    pub fn object(&self, path: &str) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().objects.get(path).cloned()
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.state.lock().unwrap().objects.keys().cloned().collect()
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockS3 {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+pub fn md5_hex(body: &[u8]) -> String {
+    hash(MessageDigest::md5(), body).map_or_else(|_| String::new(), |digest| hex(&digest))
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded: Vec<u8> = vec![];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+// the signature the client should have sent, None when the request isn't signed properly
+fn verify(request: &RawRequest, path: &str, query: &[(String, String)]) -> Option<()> {
+    let param = |name: &str| {
+        query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    let (credential, signed_headers, signature, timestamp, payload_hash) = match
+        param("X-Amz-Signature")
+    {
+        Some(signature) => {
+            let timestamp = param("X-Amz-Date")?;
+            let expires: i64 = param("X-Amz-Expires")?.parse().ok()?;
+            let signed = NaiveDateTime::parse_from_str(&timestamp, "%Y%m%dT%H%M%SZ").ok()?;
+            if (Utc::now().naive_utc() - signed).num_seconds() > expires {
+                return None;
+            }
+            (
+                param("X-Amz-Credential")?,
+                param("X-Amz-SignedHeaders")?,
+                signature,
+                timestamp,
+                UNSIGNED_PAYLOAD.to_string(),
+            )
+        }
+        None => {
+            let authorization = request.headers.get("authorization")?;
+            let fields = authorization.strip_prefix(AWS_ALGORITHM)?;
+            let field = |name: &str| {
+                fields
+                    .split(',')
+                    .find_map(|field| field.trim().strip_prefix(name))
+                    .map(str::to_owned)
+            };
+            let payload_hash = request.headers.get("x-amz-content-sha256")?.clone();
+            if payload_hash != UNSIGNED_PAYLOAD && payload_hash != sha256_hex(&request.body) {
+                return None;
+            }
+            (
+                field("Credential=")?,
+                field("SignedHeaders=")?,
+                field("Signature=")?,
+                request.headers.get("x-amz-date")?.clone(),
+                payload_hash,
+            )
+        }
+    };
+
+    // AKID/20260101/us-east-1/s3/aws4_request
+    let (access_key, scope) = credential.split_once('/')?;
+    let mut parts = scope.split('/');
+    let (date, region, service) = (parts.next()?, parts.next()?, parts.next()?);
+    if access_key != MOCK_ACCESS_KEY {
+        return None;
+    }
+
+    let canonical = CanonicalRequest {
+        method: request.method.clone(),
+        path: path.to_owned(),
+        query: query
+            .iter()
+            .filter(|(key, _)| key != "X-Amz-Signature")
+            .cloned()
+            .collect(),
+        headers: signed_headers
+            .split(';')
+            .map(|name| Some((name.to_owned(), request.headers.get(name)?.clone())))
+            .collect::<Option<Vec<(String, String)>>>()?,
+        payload_hash,
+    };
+
+    let key = aws_signing_key(MOCK_SECRET_KEY, date, region, service).ok()?;
+    let expected = aws_signature(
+        &key,
+        &string_to_sign(AWS_ALGORITHM, &timestamp, scope, &canonical)
+    ).ok()?;
+
+    (expected == signature).then_some(())
+}
+
+fn list(state: &State, bucket: &str, query: &HashMap<String, String>) -> String {
+    let prefix = format!("{}/{}", bucket, query.get("prefix").map_or("", String::as_str));
+    let after = query.get("continuation-token").map(|token| format!("{}/{}", bucket, token));
+
+    let matching: Vec<(&String, &Vec<u8>)> = state.objects
+        .iter()
+        .filter(|(path, _)| path.starts_with(&prefix))
+        .filter(|(path, _)| after.as_ref().is_none_or(|after| *path > after))
+        .collect();
+    let page = &matching[..matching.len().min(state.page_size)];
+    let truncated = matching.len() > page.len();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?><ListBucketResult>");
+    xml.push_str(&format!("<Name>{}</Name><KeyCount>{}</KeyCount>", bucket, page.len()));
+    xml.push_str(&format!("<IsTruncated>{}</IsTruncated>", truncated));
+
+    for (path, body) in page {
+        let key = &path[bucket.len() + 1..];
+        xml.push_str(
+            &format!(
+                "<Contents><Key>{}</Key><Size>{}</Size><ETag>\"{}\"</ETag>{}</Contents>",
+                xml_escape(key),
+                body.len(),
+                md5_hex(body),
+                state.modified.get(*path).map_or(String::new(), |modified| {
+                    let modified = modified.to_rfc3339_opts(SecondsFormat::Millis, true);
+                    format!("<LastModified>{}</LastModified>", modified)
+                })
+            )
+        );
+    }
+    if let (true, Some((path, _))) = (truncated, page.last()) {
+        let token = &path[bucket.len() + 1..];
+        xml.push_str(
+            &format!("<NextContinuationToken>{}</NextContinuationToken>", xml_escape(token))
+        );
+    }
+
+    xml.push_str("</ListBucketResult>");
+    xml
+}
+
+fn error(code: &str) -> Vec<u8> {
+    format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?><Error><Code>{}</Code></Error>", code)
+        .into_bytes()
+}
+
+async fn handle(mut stream: TcpStream, state: Arc<Mutex<State>>) -> Result<()> {
+    let Some(request) = read_request(&mut stream).await? else {
+        return Ok(());
+    };
+
+    let (raw_path, raw_query) = request.target.split_once('?').unwrap_or((&request.target, ""));
+    let path = percent_decode(raw_path);
+    let query: Vec<(String, String)> = raw_query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+
+    let (status, headers, body) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(format!("{} {}", &request.method, &path));
+
+        let object_path = path.trim_start_matches('/').to_owned();
+        let (bucket, key) = object_path.split_once('/').unwrap_or((&object_path, ""));
+
+        if verify(&request, &path, &query).is_none() {
+            (403, vec![], error("SignatureDoesNotMatch"))
+        } else {
+            match (request.method.as_str(), key.is_empty()) {
+                ("GET", true) => {
+                    let query: HashMap<String, String> = query.into_iter().collect();
+                    (200, vec![], list(&state, bucket, &query).into_bytes())
+                }
+                ("GET", false) => {
+                    // only "bytes={start}-", what S3Storage sends to continue a download
+                    let start = request.headers
+                        .get("range")
+                        .and_then(|range| range.strip_prefix("bytes="))
+                        .and_then(|range| range.strip_suffix('-'))
+                        .and_then(|start| start.parse::<usize>().ok());
+                    match (state.objects.get(&object_path), start) {
+                        (Some(body), Some(start)) if start < body.len() =>
+                            (206, vec![], body[start..].to_vec()),
+                        (Some(body), _) => (200, vec![], body.clone()),
+                        (None, _) => (404, vec![], error("NoSuchKey")),
+                    }
+                }
+                ("PUT", false) => {
+                    let etag = format!("\"{}\"", md5_hex(&request.body));
+                    state.objects.insert(object_path.clone(), request.body.clone());
+                    state.modified.insert(object_path.clone(), Utc::now());
+                    (200, vec![("ETag", etag)], vec![])
+                }
+                ("DELETE", false) => {
+                    state.objects.remove(&object_path);
+                    state.modified.remove(&object_path);
+                    (204, vec![], vec![])
+                }
+                _ => (400, vec![], error("NotImplemented")),
+            }
+        }
+    };
+
+    write_response(&mut stream, status, &headers, &body).await
+}
@@ -1,6 +1,7 @@
 pub mod bin_locations;
-pub mod get_api;
+pub mod api_client;
 pub mod pipeline;
+pub mod pipelines;
 pub mod load_env_vars;
 pub mod load_locations;
 pub mod lock_file;
@@ -10,3 +11,20 @@ pub mod run_command;
 pub mod email_templates;
 pub mod cloud_storage;
 pub mod string_map_to_string;
+pub mod scheduler;
+pub mod resource_estimate;
+pub mod job_usage;
+pub mod checkpoint;
+pub mod progress;
+pub mod logging;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock_api;
+pub mod tools;
+pub mod secrets;
+pub mod signing;
+pub mod storage;
+pub mod s3;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock_s3;
+pub mod transfer;
+pub mod retention;
@@ -1,24 +1,43 @@
 use crate::{
-    cloud_storage::{ download, get_signed_url, upload },
+    api_client::{ ApiClient, PipelinePatch },
+    bin_locations::BinNames,
+    checkpoint::Checkpoints,
     email_templates::{
+        expired_results_email_template,
         generate_locator_receipt,
         generate_ogv_receipt,
         generate_splicing_receipt,
         generate_tcs_receipt,
         receipt_email_template,
+        renewed_link_email_template,
     },
-    get_api::get_api,
-    load_locations::{ PipelineType, load_locations },
-    send_email::send_email,
+    load_env_vars::{ EnvVars, load_env_vars },
+    load_locations::{ Locations, PipelineKeys, PipelineType, load_locations },
+    job_usage::{ UsageConfig, UsageStore },
+    logging::{ self, Level, Logger },
+    pipelines,
+    progress::ProgressReporter,
+    resource_estimate::{
+        Coefficients,
+        Estimate,
+        InputSize,
+        default_estimator,
+        dir_size,
+        sequence_size,
+    },
+    cloud_storage::UrlSigner,
+    transfer,
+    storage::{ storage_backend, GcsStorage, StorageBackend, StorageConfig },
     string_map_to_string::string_map_to_string,
+    tools::{ Email, ShellTools, Tools },
 };
 use chrono::prelude::*;
-use reqwest::Client;
-use serde::Deserialize;
-use serde_json::json;
-use serde_json::Value;
+use serde::{ de::DeserializeOwned, Deserialize, Serialize };
+use std::future::Future;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use std::time::Duration;
 use std::{ collections::HashMap, fs::OpenOptions };
 
 use anyhow::{ Context, Result };
@@ -217,26 +236,52 @@ pub struct Pipeline<ApiData> {
     pub id: String,
     pub base: String,
     pub scratch_dir: String,
-    log_file: String,
     log_error_file: String,
     pub data: ApiData,
     api_url: String,
     bucket_url: String,
-    api_key: String,
+    #[serde(skip)]
+    api: ApiClient,
+    #[serde(skip)]
+    pub checkpoints: Checkpoints,
+    #[serde(skip)]
+    pub progress: ProgressReporter,
+    #[serde(skip)]
+    pub logger: Logger,
+    // external programs, swapped for FakeTools in tests
+    #[serde(skip, default = "shell_tools")]
+    pub tools: Arc<dyn Tools>,
+    // where bucket_url points, see storage.rs
+    #[serde(skip, default = "gcs_storage")]
+    pub storage: Arc<dyn StorageBackend>,
+    #[serde(skip)]
+    storage_config: StorageConfig,
+    #[serde(skip)]
+    private_key_location: String,
+    // from the Locations the pipeline was made with, for estimate
+    #[serde(skip, default = "default_estimator")]
+    estimator: PipelineKeys<Coefficients>,
+    #[serde(skip)]
+    usage: UsageConfig,
 }
 
 impl<ApiData> Pipeline<ApiData> where ApiData: for<'de> serde::Deserialize<'de> {
     pub async fn new(id: &str, pipeline_type: PipelineType) -> Result<Pipeline<ApiData>> {
-        let locations = load_locations().unwrap();
+        let locations = load_locations()?;
+        Pipeline::with_locations(id, pipeline_type, &locations).await
+    }
 
+    pub async fn with_locations(
+        id: &str,
+        pipeline_type: PipelineType,
+        locations: &Locations
+    ) -> Result<Pipeline<ApiData>> {
         let api_url: String = String::from(&locations.api_url[pipeline_type]);
 
         let url = format!("{}/{}", &api_url, id);
-        let data: ApiData = get_api(&url).await.unwrap_or_else(|e| {
-            // try again later
-            println!("Error getting API: {:?}", e);
-            std::process::exit(1);
-        });
+        // callers exit or skip the submission, it's tried again on the next run
+        let api = ApiClient::shared(locations)?;
+        let data: ApiData = api.fetch(&url).await.context("Error getting API")?;
 
         let bucket_url: String = String::from(&locations.bucket_url[pipeline_type]);
         let scratch_dir: String = format!("{}/{}", &locations.scratch_space[pipeline_type], id);
@@ -255,38 +300,72 @@ impl<ApiData> Pipeline<ApiData> where ApiData: for<'de> serde::Deserialize<'de>
         let _ = std::fs::create_dir_all(log_file_path.parent().unwrap());
         let _ = std::fs::create_dir_all(log_error_path.parent().unwrap());
 
+        let checkpoints = Checkpoints::new(&scratch_dir);
+        let logger = Logger::new(pipeline_type, id, &log_file);
+        let progress = ProgressReporter::new(
+            &url,
+            api.clone(),
+            Duration::from_secs(locations.progress_interval)
+        );
+
+        let tools = shell_tools();
+        let storage = storage_backend(
+            &locations.storage,
+            &locations.private_key_location,
+            tools.clone()
+        )?;
+
         let pipeline: Pipeline<ApiData> = Pipeline {
             id: id.to_owned(),
             base: locations.base.to_owned(),
             scratch_dir,
-            log_file,
             log_error_file,
             api_url,
             bucket_url,
-            api_key: locations.api_key.to_owned(),
+            api,
+            checkpoints,
+            progress,
+            logger,
+            tools,
+            storage,
+            storage_config: locations.storage.clone(),
+            private_key_location: locations.private_key_location.clone(),
+            estimator: locations.estimator.clone(),
+            usage: locations.usage.clone(),
             data,
         };
 
         Ok(pipeline)
     }
 
-    pub fn add_log(&self, msg: &str) -> Result<()> {
-        let date = Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
-        let message = format!("[{}] {}", date, msg).to_string();
+    // gcs storage goes through the tools, so it's swapped too
+    pub fn with_tools(mut self, tools: Arc<dyn Tools>) -> Pipeline<ApiData> {
+        if let StorageConfig::Gcs { region, link_expiry_days } = &self.storage_config {
+            let signer = UrlSigner::new(&self.private_key_location, region, *link_expiry_days);
+            self.storage = Arc::new(GcsStorage::new(tools.clone(), signer));
+        }
+        self.tools = tools;
+        self
+    }
 
-        println!("{}", &message);
+    // for the "link expires" line of emails
+    pub fn link_expiry_days(&self) -> Option<u64> {
+        self.storage_config.link_expiry_days()
+    }
 
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .append(true)
-            .open(&self.log_file)?;
+    pub fn with_storage(mut self, storage: Arc<dyn StorageBackend>) -> Pipeline<ApiData> {
+        self.storage = storage;
+        self
+    }
 
-        if let Err(e) = writeln!(file, "{:?}", &message) {
-            eprintln!("Couldn't write to file: {}", e);
-        }
+    pub fn add_log(&self, msg: &str) -> Result<()> {
+        self.logger.log(Level::Info, msg).context("Failed to write log.")
+    }
 
-        Ok(())
+    // current stage for the logs and the progress sent to the web API
+    pub fn stage(&self, stage: &str) {
+        self.logger.stage(stage);
+        self.progress.stage(stage);
     }
 
     pub async fn add_error(&self, subject: &str, msg: &str, to_email: &str) -> Result<()> {
@@ -300,303 +379,565 @@ impl<ApiData> Pipeline<ApiData> where ApiData: for<'de> serde::Deserialize<'de>
             eprintln!("Couldn't write to file: {}", e);
         }
 
-        let _ = self.add_log(&msg);
+        let _ = self.logger.log(Level::Error, msg);
 
         let _ = self
-            .patch_pipeline(json!({"pending": false, "processingError": true})).await
+            .patch_pipeline(&PipelinePatch::processing_error()).await
             .context("Failed to patch pipeline.")?;
 
-        let _ = send_email(subject, msg, to_email, true).await?;
+        self.email(subject, msg, to_email, true).await?;
 
         Ok(())
     }
 
-    pub async fn patch_pipeline(&self, data: Value) -> Result<()> {
-        Client::new()
-            .patch(format!("{}/{}", &self.api_url, &self.id))
-            .json(&data)
-            .header("x-api-key", &self.api_key)
-            .send().await
-            .context("Failed to patch pipeline.")?;
+    // runs a stage once, with --resume a stage finished in a previous run is skipped
+    // and its saved output returned instead
+    pub async fn checkpoint<R, F>(&self, stage: &str, run: F) -> Result<R>
+        where R: Serialize + DeserializeOwned, F: AsyncFnOnce() -> Result<R>
+    {
+        if let Some(output) = self.checkpoints.load(stage) {
+            self.add_log(&format!("Skipping {}, finished in a previous run.", stage))?;
+            return Ok(output);
+        }
 
-        Ok(())
+        self.stage(stage);
+        let output = run().await?;
+        self.checkpoints.save(stage, &output).context("Failed to save checkpoint.")?;
+        Ok(output)
     }
 
-    pub async fn patch_pending(&self) -> Result<()> {
-        let data = json!({"pending": true, "submit": false});
+    pub fn run_command(&self, cmd: &str, current_dir: &str) -> Result<String> {
+        self.tools.run(cmd, current_dir)
+    }
 
-        let url = format!("{}/{}", &self.api_url, &self.id);
+    pub async fn email(
+        &self,
+        subject: &str,
+        body: &str,
+        to_email: &str,
+        include_admin: bool
+    ) -> Result<()> {
+        self.tools.email(Email {
+            subject: subject.to_owned(),
+            body: body.to_owned(),
+            to: to_email.to_owned(),
+            include_admin,
+        }).await
+    }
 
-        Client::new()
-            .patch(url)
-            .json(&data)
-            .header("x-api-key", &self.api_key)
-            .send().await
-            .context("Failed to patch pipeline as pending.")?;
+    pub async fn patch_pipeline(&self, patch: &PipelinePatch) -> Result<()> {
+        self.api.patch(&format!("{}/{}", &self.api_url, &self.id), patch).await
+    }
 
-        Ok(())
+    pub async fn patch_pending(&self) -> Result<()> {
+        self.patch_pipeline(&PipelinePatch::pending()).await.context(
+            "Failed to patch pipeline as pending."
+        )
     }
 
-    pub fn bucket_download(
+    pub async fn bucket_download(
         &self,
         from: &str,
         to_local: &str,
         download_recursive: bool
     ) -> Result<()> {
         let from_bucket = format!("{}/{}/{}", &self.bucket_url, &self.id, from);
-        download(&from_bucket, to_local, download_recursive).context("Failed")?;
-        Ok(())
+        let stats = transfer
+            ::download(self.storage.clone(), &from_bucket, to_local, download_recursive).await
+            .context("Failed")?;
+        self.add_log(&format!("Downloaded {} from {}", stats, &from_bucket))
     }
 
-    pub fn bucket_upload(&self, from_local: &str, to: &str) -> Result<()> {
+    pub async fn bucket_upload(&self, from_local: &str, to: &str) -> Result<()> {
         let to_bucket = format!("{}/{}/{}", &self.bucket_url, &self.id, to);
-        upload(from_local, &to_bucket).context("Failed to upload file")?;
-        Ok(())
+        let stats = transfer
+            ::upload(self.storage.clone(), from_local, &to_bucket).await
+            .context("Failed to upload file")?;
+        self.add_log(&format!("Uploaded {} to {}", stats, &to_bucket))
     }
 
-    pub fn bucket_signed_url(&self, location: &str) -> Result<String> {
+    pub async fn bucket_signed_url(&self, location: &str) -> Result<String> {
         let bucket_location = format!("{}/{}/{}", &self.bucket_url, &self.id, location);
         println!("Bucket location for signed url: {}", &bucket_location);
-        let signedurl = get_signed_url(&bucket_location).context(
+        let signedurl = self.storage.signed_url(&bucket_location).await.context(
             "Failed to generate signed URL for uploaded file."
         )?;
         Ok(signedurl)
     }
+
+    // bytes stored for this submission in the bucket, 0 if the storage can't say
+    pub async fn bucket_size(&self) -> u64 {
+        let location = format!("{}/{}", &self.bucket_url, &self.id);
+        self.storage.du(&location).await.unwrap_or_else(|e| {
+            let _ = self.add_log(&format!("Failed to get size of {}: {:?}", &location, e));
+            0
+        })
+    }
+
+    // logged so the estimator coefficients can be tuned against real runs
+    fn estimate(&self, pipeline_type: PipelineType, input: InputSize) -> Estimate {
+        let coefficients = &self.estimator[pipeline_type];
+        let mut resources = coefficients.estimate(&input);
+
+        let _ = self.add_log(
+            &format!(
+                "Estimated {} cores, {} MB and {} minutes for {} files, {} bytes and {} sequences",
+                resources.cores,
+                resources.memory,
+                resources.time,
+                input.files,
+                input.bytes,
+                input.sequences
+            )
+        );
+
+        // past usage of similar inputs beats the formula once there is enough of it
+        let history = UsageStore::new(&self.base).load().unwrap_or_else(|e| {
+            println!("Error loading job usage: {:?}", e);
+            vec![]
+        });
+
+        if let Some(past) = self.usage.from_history(&history, pipeline_type, &input) {
+            resources.memory = past.memory.min(coefficients.max_memory);
+            resources.time = past.time.min(coefficients.max_time);
+
+            let _ = self.add_log(
+                &format!(
+                    "Using {} MB and {} minutes from {} similar jobs",
+                    resources.memory,
+                    resources.time,
+                    past.samples
+                )
+            );
+        }
+
+        Estimate { input, resources }
+    }
 }
 
-impl Pipeline<TcsAPI> {
-    pub fn is_dr(&self) -> bool {
-        self.data.primers.as_ref().map_or(true, |primers| primers.is_empty())
+/*
+    One submission type, implemented on each API's data
+    process_queue dispatches any implementor the same way and run_pipeline is the entry point of
+    every pipeline binary, so a new assay needs an API struct, this impl, its processing under
+    pipelines/ and an entry in visit_job_types
+*/
+pub trait PipelineJob: for<'de> Deserialize<'de> + Sized {
+    const PIPELINE_TYPE: PipelineType;
+    // used in emails, ex) "TCS/DR"
+    const NAME: &'static str;
+    // built from src/bin/{BIN}, process_queue submits it
+    const BIN: &'static str;
+    // the binary takes --cores and sizes the rayon pool with it
+    const THREADED: bool = false;
+
+    fn email(&self) -> &str;
+    fn job_id(&self) -> String;
+    // subject and body of the receipt email
+    fn receipt(&self) -> (String, String);
+    fn input_size(pipeline: &Pipeline<Self>) -> impl Future<Output = InputSize> + Send;
+    fn process(pipeline: &Pipeline<Self>, locations: Locations) -> impl Future<Output = Result<()>>;
+
+    // what the scheduler is asked for, from the input size and past usage
+    fn resources(pipeline: &Pipeline<Self>) -> impl Future<Output = Estimate> {
+        async { pipeline.estimate(Self::PIPELINE_TYPE, Self::input_size(pipeline).await) }
     }
-    pub fn pool_name(&self) -> String {
-        let is_dr = self.is_dr();
-        let temp_pool_name = self.data.pool_name.as_deref().unwrap_or("");
-        let pool_name = if is_dr || temp_pool_name.is_empty() {
-            "TCSDR".to_string()
-        } else {
-            temp_pool_name.to_owned()
-        };
-        pool_name
+
+    fn stale_message(time_limit_in_hours: i64) -> String {
+        format!(
+            "Pipeline has been pending for over {} hours and has been cancelled.",
+            time_limit_in_hours
+        )
     }
+}
+
+impl<T: PipelineJob> Pipeline<T> {
     pub fn job_id(&self) -> String {
-        let is_dr = self.is_dr();
-        let pool_name = self.pool_name();
-        let job_id: String = format!("{}_{}", if is_dr { "dr" } else { "tcs" }, &pool_name);
-        job_id
+        self.data.job_id()
     }
+
     pub async fn send_receipt(&self) -> Result<()> {
-        let is_dr = self.is_dr();
-        let job_id = self.job_id();
-        let subject = &format!("{} Submission #{}", if is_dr { "DR" } else { "TCS" }, &job_id);
-        let msg: String = generate_tcs_receipt(&self.data);
-        let _ = send_email(&subject, &msg, &self.data.email, true).await.context(
+        let (subject, body) = self.data.receipt();
+        self.email(&subject, &body, self.data.email(), true).await.context(
             "Failed to send receipt email."
         )?;
         Ok(())
     }
-    pub fn cores_and_memory(&self, upload_count: &Option<u8>) -> (u8, u32) {
-        // run max 10 single-threaded pairs per submission
-        // allocate 25gb memory per pair
 
-        let mut cores = upload_count.unwrap_or(0) / 2;
-        if cores < 1 {
-            cores = 1;
-        }
-        cores = std::cmp::min(cores, 9);
+    // the archive process uploaded, {job_id}.zip or .tar.gz, the newest if there are both
+    // None once retention has deleted it
+    pub async fn results_archive(&self) -> Result<Option<String>> {
+        let prefix = format!("{}/{}/", &self.bucket_url, &self.id);
+        let job_id = self.job_id();
+        let names = [format!("{}.zip", &job_id), format!("{}.tar.gz", &job_id)];
 
-        let memory: u32 = 25000 * (cores as u32);
+        let archive = self.storage
+            .list(&format!("{}{}.", &prefix, &job_id)).await
+            .with_context(|| format!("Failed to list {}", &prefix))?
+            .into_iter()
+            .filter(|object| names.iter().any(|name| object.location[prefix.len()..] == *name))
+            .max_by_key(|object| object.updated);
 
-        (cores, memory)
+        Ok(archive.map(|archive| archive.location[prefix.len()..].to_owned()))
+    }
+
+    // emails the user a new link to results already in the bucket, clears the relink flag
+    // results that have expired are an error, the user is still told they're gone
+    pub async fn relink(&self) -> Result<()> {
+        let Some(archive) = self.results_archive().await? else {
+            self.email(
+                &format!("{} Results Expired #{}", T::NAME, self.job_id()),
+                &expired_results_email_template(&format!("ID: {}", &self.id)),
+                self.data.email(),
+                false
+            ).await.context("Failed to email that the results expired.")?;
+
+            return Err(
+                anyhow::anyhow!(
+                    "No results archive for #{} in {}/{}/, it may have expired.",
+                    &self.id,
+                    &self.bucket_url,
+                    &self.id
+                )
+            );
+        };
+        let signed_url = self.bucket_signed_url(&archive).await?;
+
+        self.email(
+            &format!("{} Results Link Renewed #{}", T::NAME, self.job_id()),
+            &renewed_link_email_template(signed_url, self.link_expiry_days()),
+            self.data.email(),
+            false
+        ).await.context("Failed to email renewed link.")?;
+        self.add_log(&format!("Emailed a renewed link to {}", &archive))?;
+
+        self.patch_pipeline(&PipelinePatch::relinked()).await.context(
+            "Failed to patch pipeline as relinked."
+        )
     }
 }
 
-impl Pipeline<CoreceptorAPI> {
-    pub fn job_id(&self) -> String {
-        let job_id: String = if self.data.job_id.is_empty() {
-            format!("coreceptor_{}", &self.data.id)
+fn shell_tools() -> Arc<dyn Tools> {
+    Arc::new(ShellTools)
+}
+
+fn gcs_storage() -> Arc<dyn StorageBackend> {
+    let signer = UrlSigner::new("", "us-east1", 7);
+    Arc::new(GcsStorage::new(shell_tools(), signer))
+}
+
+// job ID entered by the user, or {prefix}_{id}
+fn job_id_or(job_id: &str, prefix: &str, id: &str) -> String {
+    if job_id.is_empty() { format!("{}_{}", prefix, id) } else { job_id.to_owned() }
+}
+
+// bucket uploads, or a local HTSF directory when one was given
+async fn uploads_or_htsf<T>(
+    pipeline: &Pipeline<T>,
+    htsf: &Option<String>,
+    uploads: usize
+) -> InputSize
+    where T: for<'de> Deserialize<'de>
+{
+    match htsf.as_deref() {
+        Some(htsf) if !htsf.is_empty() => dir_size(Path::new(htsf)),
+        _ =>
+            InputSize {
+                bytes: pipeline.bucket_size().await,
+                files: uploads as u32,
+                sequences: 0,
+            },
+    }
+}
+
+impl TcsAPI {
+    pub fn is_dr(&self) -> bool {
+        self.primers.as_ref().is_none_or(|primers| primers.is_empty())
+    }
+    pub fn pool_name(&self) -> String {
+        let temp_pool_name = self.pool_name.as_deref().unwrap_or("");
+        if self.is_dr() || temp_pool_name.is_empty() {
+            "TCSDR".to_string()
         } else {
-            self.data.job_id.to_owned()
-        };
-        job_id
+            temp_pool_name.to_owned()
+        }
     }
-    pub async fn send_receipt(&self) -> Result<()> {
-        let job_id = self.job_id();
+}
 
+impl Pipeline<TcsAPI> {
+    pub fn is_dr(&self) -> bool {
+        self.data.is_dr()
+    }
+    pub fn pool_name(&self) -> String {
+        self.data.pool_name()
+    }
+}
+
+impl PipelineJob for TcsAPI {
+    const PIPELINE_TYPE: PipelineType = PipelineType::Tcs;
+    const NAME: &'static str = "TCS/DR";
+    const BIN: &'static str = BinNames::TCSDR;
+    const THREADED: bool = true;
+
+    fn email(&self) -> &str {
+        &self.email
+    }
+    fn job_id(&self) -> String {
+        format!("{}_{}", if self.is_dr() { "dr" } else { "tcs" }, self.pool_name())
+    }
+    fn receipt(&self) -> (String, String) {
+        let subject = format!(
+            "{} Submission #{}",
+            if self.is_dr() { "DR" } else { "TCS" },
+            self.job_id()
+        );
+        (subject, generate_tcs_receipt(self))
+    }
+    async fn input_size(pipeline: &Pipeline<Self>) -> InputSize {
+        // one single-threaded pair per core, memory from the size of the pool
+        let uploads = pipeline.data.uploads.as_ref().map_or(0, |uploads| uploads.len());
+        uploads_or_htsf(pipeline, &pipeline.data.htsf, uploads).await
+    }
+    async fn process(pipeline: &Pipeline<Self>, locations: Locations) -> Result<()> {
+        pipelines::tcsdr::process::process(pipeline, locations).await
+    }
+}
+
+impl PipelineJob for CoreceptorAPI {
+    const PIPELINE_TYPE: PipelineType = PipelineType::Coreceptor;
+    const NAME: &'static str = "Coreceptor";
+    const BIN: &'static str = BinNames::CORECEPTOR;
+
+    fn email(&self) -> &str {
+        &self.email
+    }
+    fn job_id(&self) -> String {
+        job_id_or(&self.job_id, "coreceptor", &self.id)
+    }
+    fn receipt(&self) -> (String, String) {
         let sequences_html = format!(
             "<u>Sequences</u></br>{}",
             string_map_to_string(
-                self.data.sequences
+                self.sequences
                     .lines()
                     .filter(|line| line.starts_with('>'))
                     .map(|line| line.trim_start_matches('>')),
                 |s, line| s.push_str(line)
             )
         );
-        let receipt_body = receipt_email_template(&sequences_html);
-
-        send_email(
-            &format!("Coreceptor Submission #{}", &job_id),
-            &receipt_body,
-            &self.data.email,
-            true
-        ).await.context("Failed to send receipt email.")?;
 
-        Ok(())
+        (
+            format!("Coreceptor Submission #{}", self.job_id()),
+            receipt_email_template(&sequences_html),
+        )
     }
-    pub fn cores_and_memory(&self) -> (u8, u32) {
+    async fn input_size(pipeline: &Pipeline<Self>) -> InputSize {
         // run single-threaded, don't overload g2p
-        // allocate 5gb memory per job
-
-        let cores = 1;
-        let memory: u32 = 5000;
-
-        (cores, memory)
+        sequence_size(&pipeline.data.sequences)
+    }
+    async fn process(pipeline: &Pipeline<Self>, locations: Locations) -> Result<()> {
+        pipelines::coreceptor::process::process(pipeline, locations).await
     }
 }
 
-impl Pipeline<OgvAPI> {
-    pub fn job_id(&self) -> String {
-        let job_id: String = if self.data.job_id.is_empty() {
-            format!("ogv_{}", &self.data.id)
-        } else {
-            self.data.job_id.to_owned()
-        };
-        job_id
-    }
-    pub async fn send_receipt(&self) -> Result<()> {
-        let job_id = self.job_id();
-        let receipt_body = generate_ogv_receipt(&self.data);
-        send_email(
-            &format!("OGV Dating Submission #{}", &job_id),
-            &receipt_body,
-            &self.data.email,
-            true
-        ).await.context("Failed to send receipt email.")?;
+impl PipelineJob for OgvAPI {
+    const PIPELINE_TYPE: PipelineType = PipelineType::Ogv;
+    const NAME: &'static str = "OGV";
+    const BIN: &'static str = BinNames::OGV;
 
-        Ok(())
+    fn email(&self) -> &str {
+        &self.email
     }
-    pub fn cores_and_memory(&self) -> (u8, u32) {
-        // allocate 5 GiB per core, capped at 20 cores
-        let cores = std::cmp::min(self.data.uploads.len() as u8, 20) * 2;
-
-        let memory: u32 = (cores as u32) * 10000;
-
-        (cores, memory)
+    fn job_id(&self) -> String {
+        job_id_or(&self.job_id, "ogv", &self.id)
+    }
+    fn receipt(&self) -> (String, String) {
+        (format!("OGV Dating Submission #{}", self.job_id()), generate_ogv_receipt(self))
+    }
+    async fn input_size(pipeline: &Pipeline<Self>) -> InputSize {
+        InputSize {
+            bytes: pipeline.bucket_size().await,
+            files: pipeline.data.uploads.len() as u32,
+            sequences: 0,
+        }
+    }
+    async fn process(pipeline: &Pipeline<Self>, locations: Locations) -> Result<()> {
+        pipelines::ogv::process::process(pipeline, locations).await
     }
 }
 
-impl Pipeline<IntactAPI> {
-    pub fn job_id(&self) -> String {
-        let job_id: String = if self.data.job_id.is_empty() {
-            format!("intactness_{}", &self.data.id)
-        } else {
-            self.data.job_id.to_owned()
-        };
-        job_id
-    }
-    pub async fn send_receipt(&self) -> Result<()> {
-        let job_id = self.job_id();
+impl PipelineJob for IntactAPI {
+    const PIPELINE_TYPE: PipelineType = PipelineType::Intact;
+    const NAME: &'static str = "Intactness";
+    const BIN: &'static str = BinNames::INTACTNESS;
+    const THREADED: bool = true;
 
+    fn email(&self) -> &str {
+        &self.email
+    }
+    fn job_id(&self) -> String {
+        job_id_or(&self.job_id, "intactness", &self.id)
+    }
+    fn receipt(&self) -> (String, String) {
         let sequences_html = format!(
             "<u>Sequences</u></br>{}",
-            &self.data.sequences
+            &self.sequences
                 .split("\n")
                 .filter(|line| line.starts_with(">"))
                 .map(|l| l.replace(">", ""))
                 .collect::<Vec<String>>()
                 .join("</br>")
         );
-        let receipt_body = receipt_email_template(&sequences_html);
-
-        send_email(
-            &format!("Intactness Submission #{}", &job_id),
-            &receipt_body,
-            &self.data.email,
-            true
-        ).await.context("Failed to send receipt email.")?;
 
-        Ok(())
+        (
+            format!("Intactness Submission #{}", self.job_id()),
+            receipt_email_template(&sequences_html),
+        )
     }
-    pub fn cores_and_memory(&self, upload_count: Option<u8>) -> (u8, u32) {
-        // run up to 9 threads, 20gb per thread
-
-        let paired_jobs = upload_count.unwrap_or(0) / 2;
-
-        let mut cores = paired_jobs;
-        if cores < 1 {
-            cores = 1;
-        }
-        cores = std::cmp::min(cores, 9);
-
-        let memory: u32 = 20000 * (cores as u32);
-
-        (cores, memory)
+    async fn input_size(pipeline: &Pipeline<Self>) -> InputSize {
+        sequence_size(&pipeline.data.sequences)
+    }
+    async fn process(pipeline: &Pipeline<Self>, locations: Locations) -> Result<()> {
+        pipelines::intactness::process::process(pipeline, locations).await
     }
 }
 
-impl Pipeline<SplicingAPI> {
-    pub fn job_id(&self) -> String {
-        match self.data.pool_name.as_deref() {
+impl PipelineJob for SplicingAPI {
+    const PIPELINE_TYPE: PipelineType = PipelineType::Splicing;
+    const NAME: &'static str = "Splicing";
+    const BIN: &'static str = BinNames::SPLICING;
+
+    fn email(&self) -> &str {
+        &self.email
+    }
+    fn job_id(&self) -> String {
+        match self.pool_name.as_deref() {
             Some(pool_name) if !pool_name.is_empty() => pool_name.to_owned(),
-            _ => format!("splicing_{}", &self.data.id),
+            _ => format!("splicing_{}", &self.id),
         }
     }
-    pub async fn send_receipt(&self) -> Result<()> {
-        let job_id = self.job_id();
-        let receipt_body = generate_splicing_receipt(&self.data);
-        send_email(
-            &format!("Splicing Submission #{}", &job_id),
-            &receipt_body,
-            &self.data.email,
-            true
-        ).await.context("Failed to send receipt email.")?;
-
-        Ok(())
+    fn receipt(&self) -> (String, String) {
+        (format!("Splicing Submission #{}", self.job_id()), generate_splicing_receipt(self))
     }
-    pub fn cores_and_memory(&self) -> (u8, u32) {
+    async fn input_size(pipeline: &Pipeline<Self>) -> InputSize {
         // removed splicing.par_iter, each library will run sequentially
         // allocated cores are given to virust-splicing
-        // TODO: memory not calculated from max file size.  Can still error OoM
+        let uploads = pipeline.data.uploads.as_ref().map_or(0, |uploads| uploads.len());
+        uploads_or_htsf(pipeline, &pipeline.data.htsf, uploads).await
+    }
+    async fn process(pipeline: &Pipeline<Self>, locations: Locations) -> Result<()> {
+        pipelines::splicing::process::process(pipeline, locations).await
+    }
+}
 
-        let cores = 50;
-        let memory: u32 = 20000;
+impl PipelineJob for LocatorAPI {
+    const PIPELINE_TYPE: PipelineType = PipelineType::Locator;
+    const NAME: &'static str = "Locator";
+    const BIN: &'static str = BinNames::LOCATOR;
 
-        (cores, memory)
+    fn email(&self) -> &str {
+        &self.email
+    }
+    fn job_id(&self) -> String {
+        job_id_or(&self.job_id, "locator", &self.id)
+    }
+    fn receipt(&self) -> (String, String) {
+        (format!("Locator Submission #{}", self.job_id()), generate_locator_receipt(self))
+    }
+    async fn input_size(pipeline: &Pipeline<Self>) -> InputSize {
+        // threading happens in viral_seq::Locator
+        InputSize {
+            bytes: pipeline.bucket_size().await,
+            files: pipeline.data.uploads.len() as u32,
+            sequences: 0,
+        }
+    }
+    async fn process(pipeline: &Pipeline<Self>, locations: Locations) -> Result<()> {
+        pipelines::locator::process::process(pipeline, locations).await
     }
 }
 
-impl Pipeline<LocatorAPI> {
-    pub fn job_id(&self) -> String {
-        let tmp_job_id = self.data.job_id.clone();
-        let job_id: String = if tmp_job_id.is_empty() {
-            format!("locator_{}", &self.data.id)
-        } else {
-            tmp_job_id
-        };
-        job_id
+// main of every pipeline binary: load the submission, cancel it if stale, otherwise process it
+// and report a failure to the user
+pub async fn run_pipeline<T: PipelineJob>() {
+    let EnvVars { id, is_stale, cores, resume, .. } = load_env_vars();
+
+    let locations: Locations = load_locations().unwrap_or_else(|e| {
+        println!("Error loading environment: {:?}", e);
+        std::process::exit(1);
+    });
+
+    let mut pipeline: Pipeline<T> = match
+        Pipeline::with_locations(&id, T::PIPELINE_TYPE, &locations).await
+    {
+        Ok(p) => p,
+        Err(e) => {
+            println!("Error creating pipeline: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // process_queue cancels stale jobs through the binary because the pipeline is set up here
+    if is_stale {
+        pipeline
+            .add_error(
+                &format!("{} Stale Job: {}", T::NAME, &id),
+                &T::stale_message(locations.stale_hours[T::PIPELINE_TYPE]),
+                pipeline.data.email()
+            ).await
+            .unwrap_or_else(|e| {
+                println!("Failed to add error: {:?}", e);
+                std::process::exit(1);
+            });
+        return;
     }
-    pub async fn send_receipt(&self) -> Result<()> {
-        let job_id = self.job_id();
-        let receipt_body = generate_locator_receipt(&self.data);
-        send_email(
-            &format!("Locator Submission #{}", &job_id),
-            &receipt_body,
-            &self.data.email,
-            true
-        ).await.context("Failed to send receipt email.")?;
 
-        Ok(())
+    if let Err(e) = pipeline.checkpoints.start(resume) {
+        println!("Error starting checkpoints: {:?}", e);
+        std::process::exit(1);
     }
-    pub fn cores_and_memory(&self) -> (u8, u32) {
-        // set cores at 10 , run files sequentially.  Threading happens in viral_seq::Locator
 
-        let cores = 10;
-        let memory: u32 = 10000;
+    pipeline.progress.start();
+    logging::init(pipeline.logger.clone());
+
+    // set thread count for thread pool in processing
+    if T::THREADED {
+        rayon::ThreadPoolBuilder::new().num_threads(cores).build_global().unwrap();
+    }
 
-        (cores, memory)
+    let processed = T::process(&pipeline, locations).await;
+    pipeline.progress.finish().await;
+
+    if let Err(e) = processed {
+        pipeline
+            .add_error(
+                &format!("{} Processing Error", T::NAME),
+                &format!("Failed to process {} pipeline #{}.\n\n{:?}", T::NAME, &pipeline.id, e),
+                pipeline.data.email()
+            ).await
+            .unwrap_or_else(|e| {
+                println!("Failed to add error: {:?}", e);
+                std::process::exit(1);
+            });
     }
 }
 
+/*
+    Every pipeline, the one list a new assay is added to
+    process_queue and relink visit each type instead of matching on PipelineType
+*/
+pub trait JobTypeVisitor {
+    fn visit<T: PipelineJob>(&mut self) -> impl Future<Output = ()>;
+}
+
+pub async fn visit_job_types(visitor: &mut impl JobTypeVisitor) {
+    visitor.visit::<OgvAPI>().await;
+    visitor.visit::<IntactAPI>().await;
+    visitor.visit::<TcsAPI>().await;
+    visitor.visit::<SplicingAPI>().await;
+    visitor.visit::<LocatorAPI>().await;
+    visitor.visit::<CoreceptorAPI>().await;
+}
+
 pub fn pipeline_is_stale<'a>(
     pending: &bool,
     date: &'a str,
@@ -607,9 +948,14 @@ pub fn pipeline_is_stale<'a>(
     }
 
     // parse date as saved in MongoDB
-    let created_at = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.fZ")
-        .context("Failed to parse created_at.")
-        .unwrap();
+    // an unreadable date is never treated as stale
+    let created_at = match NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.fZ") {
+        Ok(created_at) => created_at,
+        Err(e) => {
+            println!("Failed to parse created_at {:?}: {:?}", date, e);
+            return (false, String::from(""));
+        }
+    };
     let now = Utc::now().naive_utc();
     let diff = now - created_at;
     let is_stale = diff.num_hours() > time_limit_in_hours;
@@ -617,85 +963,46 @@ pub fn pipeline_is_stale<'a>(
     (is_stale, is_stale_cmd)
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use crate::load_locations::load_locations;
-
-//     use super::*;
-//     use crate::load_locations::PipelineType;
-
-//     #[test]
-//     fn it_implements_ogv() {
-//         let id = "123".to_string();
-//         let locations: Locations = load_locations().unwrap();
-
-//         let data: OgvAPI = OgvAPI {
-//             id: id.to_owned(),
-//             created_at: "2021-01-01".to_string(),
-//             job_id: "results-named".to_string(),
-//             results_format: "zip".to_string(),
-//             uploads: vec![OgvUpload {
-//                 file_name: "file".to_string(),
-//                 lib_name: "lib".to_string(),
-//             }],
-//             conversion: HashMap::new(),
-//             email: "".to_string(),
-//             submit: true,
-//             pending: false,
-//             processing_error: false,
-//         };
-//         let pipeline: Pipeline<OgvAPI> = Pipeline::new(&data.id, PipelineType::Ogv);
-
-//         assert_eq!(&pipeline.id, &id);
-//         assert_eq!(pipeline.data.uploads.len(), 1);
-//     }
-
-//     #[test]
-//     fn test_it_creates_a_signed_url() {
-//         let locations: Locations = load_locations().unwrap();
-//         let data: OgvAPI = OgvAPI {
-//             id: "630259b6b906884861e0a59d".to_string(),
-//             created_at: "2021-01-01".to_string(),
-//             job_id: "jobid".to_string(),
-//             results_format: "zip".to_string(),
-//             uploads: vec![OgvUpload {
-//                 file_name: "file".to_string(),
-//                 lib_name: "lib".to_string(),
-//             }],
-//             conversion: HashMap::new(),
-//             email: "".to_string(),
-//             submit: true,
-//             pending: false,
-//             processing_error: false,
-//         };
-
-//         let pipeline: Pipeline<OgvAPI> = Pipeline::new(
-//             data.id.to_owned(),
-//             data,
-//             &locations,
-//             PipelineType::Ogv
-//         );
-
-//         let signed_url = pipeline.bucket_signed_url("ogv-results_jobid.zip").unwrap();
-//         assert_eq!(signed_url.contains("https://storage.googleapis.com"), true);
-//     }
-
-//     #[test]
-//     fn test_it_parses_signed_url() {
-//         let sample =
-//             "URL     HTTP Method     Expiration      Signed URL\ngs://bucket/file      GET     2022-10-12 07:25:31     https://storage.googleapis.com/user/file?x-goog-signature=...&x-goog-algorithm=...&x-goog-credential=....&x-goog-date=...&x-goog-signedheaders=...";
-
-//         let signed_url: String = sample
-//             .split("https://")
-//             .collect::<Vec<&str>>()
-//             .pop()
-//             .expect("")
-//             .trim()
-//             .to_string();
-
-//         assert_eq!(
-//             format!("https://{}", signed_url),
-//             "https://storage.googleapis.com/user/file?x-goog-signature=...&x-goog-algorithm=...&x-goog-credential=....&x-goog-date=...&x-goog-signedheaders=..."
-//         );
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use crate::{ mock_api::MockApi, storage::StorageConfig, tools::FakeTools };
+
+    #[tokio::test]
+    async fn test_with_locations() {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("pipeline_{}", std::process::id()));
+        let mut locations = api.locations(&dir);
+        locations.storage = StorageConfig::Gcs {
+            region: "us-east1".to_string(),
+            link_expiry_days: 7,
+        };
+        let mut submission = api.submission(PipelineType::Ogv, "abc123");
+        submission["jobID"] = "results-named".into();
+        submission["uploads"] = json!([{ "fileName": "file", "libName": "lib" }]);
+        api.serve("/api/ogv/abc123", submission);
+
+        let pipeline = Pipeline::<OgvAPI>
+            ::with_locations("abc123", PipelineType::Ogv, &locations).await
+            .unwrap()
+            .with_tools(Arc::new(FakeTools::new("ogv")));
+
+        assert_eq!(pipeline.id, "abc123");
+        assert_eq!(pipeline.job_id(), "results-named");
+        assert_eq!(pipeline.data.uploads.len(), 1);
+        assert!(dir.join("scratch/ogv/abc123").exists());
+
+        let signed_url = pipeline.bucket_signed_url("results-named.zip").await.unwrap();
+        let link = "https://storage.googleapis.com/bucket/ogv-dating/abc123/results-named.zip?";
+        assert!(signed_url.starts_with(link));
+
+        pipeline.patch_pending().await.unwrap();
+        assert_eq!(
+            api.patches("/api/ogv/abc123"),
+            vec![json!({ "pending": true, "submit": false })]
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{ Result, Context };
+use crate::{
+    api_client::PipelinePatch,
+    compress::compress_dir,
+    email_templates::results_email_template,
+    load_env_vars::{ load_env_vars, EnvVars },
+    load_locations::Locations,
+    pipeline::{ CoreceptorAPI, Pipeline },
+};
+
+pub async fn process(pipeline: &Pipeline<CoreceptorAPI>, locations: Locations) -> Result<()> {
+    pipeline.add_log(&format!("Initializing Coreceptor pipeline #{}", &pipeline.id))?;
+
+    let EnvVars { is_dev, .. } = load_env_vars();
+
+    let job_id: String = pipeline.job_id();
+
+    let sequence_file = format!("{}/sequences.fasta", &pipeline.scratch_dir);
+
+    // let download_to: &str = &format!("{}/inputs", &pipeline.scratch_dir);
+    let results_location = format!("{}/{}", &pipeline.scratch_dir, &job_id);
+    let output_csv_location = format!("{}/{}.csv", &results_location, &job_id);
+    let run_pipeline_command: String = format!(
+        "conda run -n coreceptor python3 {}/coreceptor.py {} {}",
+        &locations.coreceptor_base_path,
+        &sequence_file,
+        &job_id
+    );
+
+    pipeline.checkpoint("coreceptor", async || {
+        // write pipeline.data.sequences to a file
+        if !std::path::Path::new(&sequence_file).exists() {
+            std::fs
+                ::write(&sequence_file, &pipeline.data.sequences)
+                .context("Failed to write sequences to file.")?;
+        }
+
+        // coreceptor.py run
+        if !is_dev {
+            pipeline.add_log(
+                format!(
+                    "Running Coreceptor command: {:?}\nExec Location: {}",
+                    &run_pipeline_command,
+                    &results_location
+                ).as_str()
+            )?;
+            pipeline.run_command(&run_pipeline_command, &pipeline.scratch_dir).expect(
+                "Failed in coreceptor.py run."
+            );
+        } else {
+            // can't run Selenium on Mac, so...
+            // create file at output_csv_location and write "test" to it
+            std::fs::write(&output_csv_location, "test").context("Failed to write test to file.")?;
+        }
+
+        let existing_csv_location = format!("{}/{}.csv", &pipeline.scratch_dir, &job_id);
+        if std::path::Path::new(&existing_csv_location).exists() {
+            let destination = format!("{}/{}.csv", &results_location, &job_id);
+            std::fs
+                ::create_dir_all(&results_location)
+                .context("Failed to create results directory.")?;
+            std::fs
+                ::rename(&existing_csv_location, &destination)
+                .context("Failed to move existing CSV file to results location.")?;
+        }
+
+        if !std::path::Path::new(&output_csv_location).exists() {
+            return Err(anyhow::anyhow!("Failed to create .csv file."));
+        }
+
+        Ok(())
+    }).await?;
+
+    // compress results
+    let (location, compressed_filename) = pipeline.checkpoint("compress", async || {
+        pipeline.add_log(
+            &format!(
+                "Compressing results\nInput: {}\nOutput: {}",
+                &results_location,
+                &pipeline.scratch_dir
+            )
+        )?;
+        compress_dir(
+            &pipeline.data.results_format,
+            &job_id,
+            &results_location,
+            &pipeline.scratch_dir
+        ).context("Failed to compress files.")
+    }).await?;
+
+    // upload compressed results, the link is signed when emailing so a resumed run gets a fresh one
+    let archive = pipeline.checkpoint("upload", async || {
+        pipeline
+            .bucket_upload(&location.display().to_string(), &compressed_filename).await
+            .context("Failed to upload files to bucket.")?;
+        Ok(compressed_filename.clone())
+    }).await?;
+
+    // generate and send receipt
+    pipeline.checkpoint("email", async || {
+        pipeline.add_log("Emailing results.")?;
+        let signed_url = pipeline
+            .bucket_signed_url(&archive).await
+            .context("Failed to generate a signed url.")?;
+        let results_body = results_email_template(signed_url, pipeline.link_expiry_days(), "");
+        pipeline.email(
+            &format!("Coreceptor Results #{}", &job_id),
+            &results_body,
+            &pipeline.data.email,
+            false
+        ).await
+    }).await?;
+
+    // patch as completed
+    pipeline
+        .patch_pipeline(&PipelinePatch::completed()).await
+        .context("Failed to patch pipeline as completed.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{
+        load_locations::PipelineType,
+        mock_api::MockApi,
+        tools::{ FakeTools, Replay },
+    };
+
+    #[tokio::test]
+    async fn test_process() {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("coreceptor_{}", std::process::id()));
+        let locations = api.locations(&dir);
+        let mut submission = api.submission(PipelineType::Coreceptor, "abc123");
+        submission["email"] = "user@uni.edu".into();
+        api.serve("/api/coreceptor/abc123", submission);
+
+        let tools = Arc::new(
+            FakeTools::new("coreceptor").on(
+                "coreceptor.py",
+                Replay::new().copy("results.csv", "coreceptor_abc123.csv")
+            )
+        );
+        let pipeline = Pipeline::<CoreceptorAPI>
+            ::with_locations("abc123", PipelineType::Coreceptor, &locations).await
+            .unwrap()
+            .with_tools(tools.clone());
+
+        process(&pipeline, locations).await.unwrap();
+
+        let results = dir.join("scratch/coreceptor/abc123/coreceptor_abc123/coreceptor_abc123.csv");
+        assert!(results.exists());
+
+        let emails = tools.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Coreceptor Results #coreceptor_abc123");
+        assert_eq!(emails[0].to, "user@uni.edu");
+        let link = "https://storage.googleapis.com/bucket/coreceptor/abc123/";
+        assert!(emails[0].body.contains(link));
+        assert!(emails[0].body.contains("X-Goog-Signature="));
+
+        // a resumed run signs a fresh link from the archive name
+        let upload = dir.join("scratch/coreceptor/abc123/.checkpoints/upload.json");
+        assert_eq!(std::fs::read_to_string(upload).unwrap(), "\"coreceptor_abc123.zip\"");
+
+        assert_eq!(
+            api.patches("/api/coreceptor/abc123").last().unwrap(),
+            &serde_json::json!({ "pending": false, "submit": false })
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod process;
+mod split_sequences;
@@ -0,0 +1,261 @@
+use anyhow::{ Result, Context };
+use crate::{
+    api_client::PipelinePatch,
+    compress::compress_dir,
+    email_templates::results_email_template,
+    load_locations::Locations,
+    logging::library_span,
+    pipeline::{ IntactAPI, Pipeline },
+};
+use std::{
+    ffi::OsStr,
+    fs::{ File, OpenOptions },
+    io::{ BufRead, BufReader, BufWriter, Write },
+    path::Path,
+};
+use rayon::prelude::*;
+use super::split_sequences::split_sequences;
+
+const RESULTS_HEADER: &str =
+    "Contig ID,Sample ID,Multi-Contig Sample?,Multi-HIV Sample?,Contig Length,Aligned Length,Aligned coverage of Contig,Ref Seq ID,Aligned Start at Ref,Ref Strand,Is HIV?,Primer,Primer Seq,Large Deletion?,Internal Inversion?,Hypermut?,Hypermut pval,PSC?,gag,pol,env,5' Defect,5' Gaps,5' Inserts,Gag Start Codon Missing?,Gag Start Seq,Final Call,Comments,Contig Sequence";
+
+pub async fn process(pipeline: &Pipeline<IntactAPI>, locations: Locations) -> Result<()> {
+    pipeline.add_log(&format!("Initializing Intactness pipeline #{}", &pipeline.id))?;
+
+    let results_location = format!("{}", &pipeline.scratch_dir);
+
+    let job_id: String = pipeline.job_id();
+
+    let seq_paths = split_sequences(&pipeline.data.sequences, &pipeline.scratch_dir)?;
+
+    pipeline.checkpoint("intactness", async || {
+        pipeline.progress.libraries(seq_paths.len());
+
+        seq_paths
+            .par_iter()
+            .enumerate()
+            .for_each(|(i, pathbuf)| {
+                let date_now = chrono::Utc::now().to_rfc2822();
+                let lib_name = pathbuf.file_name().unwrap_or(OsStr::new("")).to_str().unwrap_or("");
+
+                if lib_name.is_empty() {
+                    pipeline.progress.library_finished();
+                    return;
+                }
+
+                let _span = library_span(lib_name);
+                let lib_stage = format!("intactness-{}", lib_name);
+
+                if pipeline.checkpoints.done(&lib_stage) {
+                    let _ = pipeline.add_log(
+                        &format!("Skipping job #{}: {}, finished in a previous run.", &i, lib_name)
+                    );
+                    pipeline.progress.library_finished();
+                    return;
+                }
+
+                let _ = pipeline.add_log(
+                    &format!("Initializing job #{}: {} at [{}]", &i, lib_name, &date_now)
+                );
+
+                let run_pipeline_command = &format!(
+                    "conda run -n intactness --cwd {} python3 -m intactness -in {}/seqs.fasta -email {}",
+                    &locations.intactness_base_path,
+                    pathbuf.display(),
+                    &pipeline.data.email
+                );
+
+                // run cmd
+                let _ = pipeline.add_log(
+                    format!(
+                        "Running Intactness command: {:?}\nExec Location: {}",
+                        &run_pipeline_command,
+                        &pipeline.scratch_dir
+                    ).as_str()
+                );
+                if pipeline.run_command(run_pipeline_command, &pipeline.scratch_dir).is_ok() {
+                    let _ = pipeline.checkpoints.save(&lib_stage, &());
+                }
+                pipeline.progress.library_finished();
+            });
+
+        Ok(())
+    }).await?;
+
+    let summary_file_location = format!("{}/summary.csv", &pipeline.scratch_dir);
+    let archive_output_dir = Path::new(&pipeline.scratch_dir)
+        .parent()
+        .context("Failed to resolve archive output directory for intactness.")?
+        .to_path_buf();
+    let mut summary_errors: Vec<String> = vec![];
+    let mut summary_results: Vec<String> = vec![];
+
+    for pathbuf in seq_paths {
+        let summary_location = pathbuf.join("intactness").join("summary.csv");
+
+        let lib_name = pathbuf.file_name().unwrap_or(OsStr::new("")).to_str().unwrap_or("");
+
+        if lib_name.is_empty() {
+            summary_errors.push(format!("Failed to get lib name for path: {}", pathbuf.display()));
+            continue;
+        }
+
+        if pathbuf.join("no_seqs_found.txt").exists() {
+            summary_errors.push(
+                format!("All sequences were filtered out during Blast. No results will be generated for {}.", lib_name)
+            );
+        } else if pathbuf.join("no_gaps.txt").exists() {
+            summary_errors.push(
+                format!("No gapped position found given a position on the reference genome. No results will be generated for {}.", lib_name)
+            );
+        } else if !summary_location.exists() {
+            summary_errors.push(
+                format!("No summary file found for {}. No results were generated.", lib_name)
+            );
+        } else {
+            let summary_file = BufReader::new(
+                File::open(&summary_location).context(
+                    format!("Lib created empty summary: {}", lib_name)
+                )?
+            );
+            // get the second line of summary_file
+
+            match summary_file.lines().nth(1) {
+                Some(Ok(line)) => {
+                    summary_results.push(line);
+                }
+                Some(Err(e)) => {
+                    summary_errors.push(
+                        format!("Failed to read summary file for {}: {}", lib_name, e)
+                    );
+                }
+                None => {
+                    summary_errors.push(format!("No summary lines found for {}", lib_name));
+                }
+            }
+        }
+    }
+
+    // write summary file
+    let mut summary_file = BufWriter::new(
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&summary_file_location)
+            .context("Failed to create/open summary file.")?
+    );
+
+    summary_file.write_all(
+        format!(
+            "{}\n{}\n{}",
+            RESULTS_HEADER,
+            summary_results.join("\n"),
+            summary_errors.join("\n")
+        ).as_bytes()
+    )?;
+    summary_file.flush()?;
+
+    // compress results
+    let (location, compressed_filename) = pipeline.checkpoint("compress", async || {
+        pipeline.add_log(
+            &format!(
+                "Compressing results\nInput: {}\nOutput: {}",
+                &results_location,
+                archive_output_dir.display()
+            )
+        )?;
+        compress_dir(
+            &pipeline.data.results_format,
+            &job_id,
+            &results_location,
+            archive_output_dir.to_str().context("Invalid archive output directory.")?
+        ).context("Failed to compress files.")
+    }).await?;
+
+    // upload compressed results, the link is signed when emailing so a resumed run gets a fresh one
+    let archive = pipeline.checkpoint("upload", async || {
+        pipeline
+            .bucket_upload(&location.display().to_string(), &compressed_filename).await
+            .context("Failed to upload files to bucket.")?;
+        Ok(compressed_filename.clone())
+    }).await?;
+
+    // generate and send receipt
+    pipeline.checkpoint("email", async || {
+        pipeline.add_log("Emailing results.")?;
+        let signed_url = pipeline
+            .bucket_signed_url(&archive).await
+            .context("Failed to generate a signed url.")?;
+        let body = results_email_template(signed_url, pipeline.link_expiry_days(), "");
+        pipeline.email(
+            &format!("Intactness Results #{}", &job_id),
+            &body,
+            &pipeline.data.email,
+            false
+        ).await
+    }).await?;
+
+    // patch as completed
+    pipeline
+        .patch_pipeline(&PipelinePatch::completed()).await
+        .context("Failed to patch pipeline as completed.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{
+        load_locations::PipelineType,
+        mock_api::MockApi,
+        tools::{ FakeTools, Replay },
+    };
+
+    #[tokio::test]
+    async fn test_process() {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("intactness_{}", std::process::id()));
+        let locations = api.locations(&dir);
+        let mut submission = api.submission(PipelineType::Intact, "abc123");
+        submission["email"] = "user@uni.edu".into();
+        submission["sequences"] = ">lib1\nACGTACGT\n>lib2\nACGTACGT\n".into();
+        api.serve("/api/intactness/abc123", submission);
+
+        let tools = Arc::new(
+            FakeTools::new("intactness")
+                .on(
+                    "lib1/seqs.fasta",
+                    Replay::new().copy("summary.csv", "lib1/intactness/summary.csv")
+                )
+                .on(
+                    "lib2/seqs.fasta",
+                    Replay::new().copy("no_seqs_found.txt", "lib2/no_seqs_found.txt")
+                )
+        );
+        let pipeline = Pipeline::<IntactAPI>
+            ::with_locations("abc123", PipelineType::Intact, &locations).await
+            .unwrap()
+            .with_tools(tools.clone());
+
+        process(&pipeline, locations).await.unwrap();
+
+        let summary_location = format!("{}/summary.csv", &pipeline.scratch_dir);
+        let summary = std::fs::read_to_string(summary_location).unwrap();
+        let lines: Vec<&str> = summary.lines().collect();
+        assert_eq!(lines[0], RESULTS_HEADER);
+        assert_eq!(lines[1], "lib1,lib1,Intact");
+        assert_eq!(
+            lines[2],
+            "All sequences were filtered out during Blast. No results will be generated for lib2."
+        );
+
+        let emails = tools.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Intactness Results #intactness_abc123");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,199 @@
+use std::fs::{ File };
+use std::io::{ Write };
+use std::path::PathBuf;
+use glob::glob;
+use anyhow::{ Result, Context };
+use crate::api_client::PipelinePatch;
+use crate::compress::compress_dir;
+use crate::email_templates::results_email_template;
+use crate::logging::library_span;
+use crate::pipeline::LocatorAPI;
+use crate::{ pipeline::{ Pipeline }, load_locations::Locations };
+
+pub async fn process(pipeline: &Pipeline<LocatorAPI>, locations: Locations) -> Result<()> {
+    pipeline.add_log(&format!("Initializing Locator pipeline #{}", &pipeline.id))?;
+
+    // set up variables
+    let job_id: String = pipeline.job_id();
+    let work_dir = PathBuf::from(&pipeline.scratch_dir).join("work");
+    let results_dir = PathBuf::from(&pipeline.scratch_dir).join(&job_id);
+
+    if !work_dir.exists() {
+        std::fs::create_dir_all(&work_dir).context("Failed to create work directory.")?;
+    }
+    if !results_dir.exists() {
+        std::fs::create_dir_all(&results_dir).context("Failed to create results directory.")?;
+    }
+
+    // download from bucket
+    pipeline.checkpoint("download", async || {
+        pipeline.add_log(&format!("Downloading from bucket to {}", &work_dir.display()))?;
+        pipeline
+            .bucket_download("*", &work_dir.display().to_string(), true).await
+            .context("Failed to download bucket files")
+    }).await?;
+
+    // track viral_seq error files for admin to review
+    let viral_seq_errors: Vec<String> = pipeline.checkpoint("locator", async || {
+        // glob download_to for each .fasta file
+        let jobs: Vec<PathBuf> = glob(&format!("{}/*.fasta", &work_dir.display().to_string()))
+            .unwrap()
+            .map(|f| f.unwrap())
+            .collect();
+
+        let mut viral_seq_errors: Vec<String> = vec![];
+        pipeline.progress.libraries(jobs.len());
+
+        // thread jobs, run each fasta file using locator
+        jobs.into_iter()
+            .enumerate()
+            .for_each(|(i, pathbuf)| {
+                let date_now = chrono::Utc::now().to_rfc2822();
+                let file_name = pathbuf.file_name().unwrap_or_default().to_string_lossy();
+                let _span = library_span(&file_name);
+
+                let _ = pipeline.add_log(
+                    &format!("Initializing job #{}: {} at [{}]", &i, pathbuf.display(), &date_now)
+                );
+
+                // locator does not exit code !0 when errors occur, check stderr instead
+                let cmd = format!("conda run -n locator locator -i {}", pathbuf.display());
+                let output = pipeline.tools.output(&cmd, &pipeline.scratch_dir);
+
+                if let Ok(ref result) = output {
+                    let std_err = &result.stderr;
+
+                    if !std_err.is_empty() {
+                        let error_filename = format!("{}.error", file_name);
+                        let error_path = std::path::Path::new(&work_dir).join(&error_filename);
+                        let error_file = File::create(&error_path).ok();
+                        if let Some(mut file) = error_file {
+                            let _ = writeln!(file, "{}", std_err);
+                            viral_seq_errors.push(error_filename);
+                        }
+                    }
+                }
+
+                pipeline.progress.library_finished();
+            });
+
+        Ok(viral_seq_errors)
+    }).await?;
+
+    // compress results
+    let (location, compressed_filename) = pipeline.checkpoint("compress", async || {
+        pipeline.add_log(
+            &format!(
+                "Compressing results\nInput: {}\nOutput: {}",
+                &work_dir.display().to_string(),
+                &results_dir.display().to_string()
+            )
+        )?;
+        compress_dir(
+            &pipeline.data.results_format,
+            &job_id,
+            &work_dir.display().to_string(),
+            &results_dir.display().to_string()
+        ).context("Failed to compress files.")
+    }).await?;
+
+    // upload compressed results, the link is signed when emailing so a resumed run gets a fresh one
+    let archive = pipeline.checkpoint("upload", async || {
+        pipeline
+            .bucket_upload(&location.display().to_string(), &compressed_filename).await
+            .context("Failed to upload files to bucket.")?;
+        Ok(compressed_filename.clone())
+    }).await?;
+
+    // generate and send receipt
+    pipeline.checkpoint("email", async || {
+        pipeline.add_log("Emailing results.")?;
+        let signed_url = pipeline
+            .bucket_signed_url(&archive).await
+            .context("Failed to generate a signed url.")?;
+        let results_body = results_email_template(signed_url, pipeline.link_expiry_days(), "");
+        pipeline.email(
+            &format!("Locator Results #{}", &job_id),
+            &results_body,
+            &pipeline.data.email,
+            false
+        ).await
+    }).await?;
+
+    // patch as completed
+    pipeline
+        .patch_pipeline(&PipelinePatch::completed()).await
+        .context("Failed to patch pipeline as completed.")?;
+
+    // alert admin of locator errors
+    if viral_seq_errors.len() > 0 {
+        let error_string = "Error files:\n\n".to_owned() + &viral_seq_errors.join("\n");
+
+        println!("Emailing error_string to admin:\n{}", &error_string);
+
+        pipeline.email(
+            &format!("Locator Contains Error file: ID: {}", &pipeline.data.id),
+            &error_string,
+            &locations.admin_email,
+            false
+        ).await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{
+        load_locations::PipelineType,
+        mock_api::MockApi,
+        tools::{ FakeTools, Replay },
+    };
+
+    #[test]
+    fn test() {
+        //
+    }
+
+    #[tokio::test]
+    async fn test_process() {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("locator_{}", std::process::id()));
+        let locations = api.locations(&dir);
+        let mut submission = api.submission(PipelineType::Locator, "abc123");
+        submission["email"] = "user@uni.edu".into();
+        api.serve("/api/locator/abc123", submission);
+
+        // locator reports bad input on stderr and still exits 0
+        let tools = Arc::new(
+            FakeTools::new("locator")
+                .bucket("gs://bucket/locator/abc123", "uploads")
+                .on("seq2.fasta", Replay::new().stderr("Invalid sequence: seq2"))
+        );
+        let pipeline = Pipeline::<LocatorAPI>
+            ::with_locations("abc123", PipelineType::Locator, &locations).await
+            .unwrap()
+            .with_tools(tools.clone());
+
+        process(&pipeline, locations).await.unwrap();
+
+        let work_dir = dir.join("scratch/locator/abc123/work");
+        assert!(!work_dir.join("seq1.fasta.error").exists());
+        assert_eq!(
+            std::fs::read_to_string(work_dir.join("seq2.fasta.error")).unwrap(),
+            "Invalid sequence: seq2\n"
+        );
+
+        let emails = tools.emails();
+        assert_eq!(emails.len(), 2);
+        assert_eq!(emails[0].subject, "Locator Results #locator_abc123");
+        assert_eq!(emails[0].to, "user@uni.edu");
+        assert_eq!(emails[1].subject, "Locator Contains Error file: ID: abc123");
+        assert_eq!(emails[1].to, "admin@uni.edu");
+        assert_eq!(emails[1].body, "Error files:\n\nseq2.fasta.error");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,11 @@
+/*
+    Processing of each pipeline, run by its binary through PipelineJob::process
+    One directory per binary, process.rs holds the stages and the rest are its helpers
+*/
+
+pub mod ogv;
+pub mod tcsdr;
+pub mod intactness;
+pub mod coreceptor;
+pub mod splicing;
+pub mod locator;
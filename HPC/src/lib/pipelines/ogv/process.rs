@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fs::{ File, OpenOptions };
+use std::io::{ BufWriter, Write };
+use anyhow::{ Result, Context };
+use crate::api_client::PipelinePatch;
+use crate::compress::compress_dir;
+use crate::email_templates::results_email_template;
+use crate::pipeline::{ OgvConversion, OgvUpload };
+use crate::scheduler::JobResources;
+use crate::{ pipeline::{ OgvAPI, Pipeline, PipelineJob }, load_locations::Locations };
+
+pub async fn process(pipeline: &Pipeline<OgvAPI>, locations: Locations) -> Result<()> {
+    pipeline.add_log(&format!("Initializing OGV pipeline #{}", &pipeline.id))?;
+
+    // set up variables
+    let download_to: &str = &format!("{}/data", &pipeline.scratch_dir);
+    let samples_file_location: String = format!("{}/samples.json", &pipeline.scratch_dir);
+    let results_location: String = format!("{}/results", &pipeline.scratch_dir);
+    let summary_location: String = format!("{}/summary.csv", &results_location);
+    let conversion_location: String = format!("{}/conversion.json", &pipeline.scratch_dir);
+    let job_id: String = pipeline.job_id();
+    let run_summary_command: String = format!(
+        "conda run -n ogv python3 {}/scripts/result-summary.py -d {} -j {} -o {}",
+        &locations.ogv_base_path,
+        format!("{}/results/dating/", &pipeline.scratch_dir),
+        &conversion_location,
+        &summary_location
+    );
+
+    let JobResources { cores, memory, .. } = OgvAPI::resources(pipeline).await.resources;
+    let run_pipeline_command: String = format!(
+        "conda run -n ogv snakemake --cores {} --resources mem_mb={} --config job_dir='{}/' --configfile {} --directory {}/ --keep-going --snakefile {}/Snakefile",
+        &cores,
+        &memory,
+        &pipeline.scratch_dir,
+        samples_file_location,
+        &locations.ogv_base_path,
+        &locations.ogv_base_path
+    );
+
+    // download from bucket
+    pipeline.checkpoint("download", async || {
+        pipeline.add_log(&format!("Downloading from bucket to {}", &download_to))?;
+        pipeline
+            .bucket_download("*", download_to, true).await
+            .context("Failed to download bucket files")
+    }).await?;
+
+    // create samples file
+    pipeline.add_log(&format!("Creating Samples file: {}", &samples_file_location))?;
+    generate_samples_file(&pipeline.data.uploads, &samples_file_location).context(
+        "Failed to generate samples file."
+    )?;
+
+    //run OGV
+    pipeline.checkpoint("ogv", async || {
+        pipeline.add_log(
+            format!(
+                "Running OGV command: {:?}\nExec Location: {}",
+                &run_pipeline_command,
+                &pipeline.scratch_dir
+            ).as_str()
+        )?;
+        pipeline.run_command(&run_pipeline_command, &pipeline.scratch_dir).expect(
+            "Failed in Snakemake run."
+        );
+        Ok(())
+    }).await?;
+
+    pipeline.checkpoint("summary", async || {
+        // create conversion file
+        pipeline.add_log(&format!("Creating conversion file: {}", &conversion_location))?;
+        write_conversion_to_file(&pipeline.data.conversion, &conversion_location).context(
+            "Failed to create conversion file."
+        )?;
+
+        // run summary
+        pipeline.add_log(
+            format!(
+                "Exec: {:?}\nExec Location: {}",
+                &run_summary_command,
+                &pipeline.scratch_dir
+            ).as_str()
+        )?;
+        pipeline.run_command(&run_summary_command, &pipeline.scratch_dir).context(
+            "Failed to run result-summary.py"
+        )?;
+
+        // ensure processing results were created
+        if !std::path::Path::new(&summary_location).exists() {
+            return Err(anyhow::anyhow!("Failed to create summary file."));
+        }
+
+        // check for runtime errors
+        let error_file_location = format!("{}/error", &pipeline.scratch_dir);
+        if std::path::Path::new(&error_file_location).exists() {
+            let error_file = std::fs
+                ::read_to_string(&error_file_location)
+                .context("Failed to read error file")?;
+            return Err(anyhow::anyhow!(error_file));
+        }
+
+        Ok(())
+    }).await?;
+
+    // compress results
+    let (location, compressed_filename) = pipeline.checkpoint("compress", async || {
+        pipeline.add_log(
+            &format!(
+                "Compressing results\nInput: {}\nOutput: {}",
+                &results_location,
+                &pipeline.scratch_dir
+            )
+        )?;
+        compress_dir(
+            &pipeline.data.results_format,
+            &job_id,
+            &results_location,
+            &pipeline.scratch_dir
+        ).context("Failed to compress files.")
+    }).await?;
+
+    // upload compressed results, the link is signed when emailing so a resumed run gets a fresh one
+    let archive = pipeline.checkpoint("upload", async || {
+        pipeline
+            .bucket_upload(&location.display().to_string(), &compressed_filename).await
+            .context("Failed to upload files to bucket.")?;
+        Ok(compressed_filename.clone())
+    }).await?;
+
+    // generate and send receipt
+    pipeline.checkpoint("email", async || {
+        pipeline.add_log("Emailing results.")?;
+        let signed_url = pipeline
+            .bucket_signed_url(&archive).await
+            .context("Failed to generate a signed url.")?;
+        let results_body = results_email_template(signed_url, pipeline.link_expiry_days(), "");
+        pipeline.email(
+            &format!("OGV Dating Results #{}", &job_id),
+            &results_body,
+            &pipeline.data.email,
+            false
+        ).await
+    }).await?;
+
+    // patch as completed
+    pipeline
+        .patch_pipeline(&PipelinePatch::completed()).await
+        .context("Failed to patch pipeline as completed.")?;
+
+    Ok(())
+}
+
+fn generate_samples_file(uploads: &[OgvUpload], samples_file_location: &str) -> Result<()> {
+    #[derive(serde::Serialize)]
+    struct Samples {
+        samples: Vec<String>,
+    }
+
+    let mut samples_json: Samples = Samples { samples: Vec::new() };
+
+    for upload in uploads {
+        samples_json.samples.push(format!("{}/{}", &upload.lib_name, &upload.file_name));
+    }
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(&samples_file_location)
+        .context("Failed to create/open samples file.")?;
+
+    writeln!(file, "{}", serde_json::to_string(&samples_json)?).context(
+        "Failed to convert samples to string and write to file."
+    )?;
+
+    Ok(())
+}
+
+fn write_conversion_to_file(conversion: &OgvConversion, conversion_location: &str) -> Result<bool> {
+    #[derive(serde::Serialize)]
+    #[allow(non_snake_case)]
+    struct ConversionJSON {
+        Start2ART: u16,
+        colors: Vec<i32>,
+    }
+    type ConversionFile = HashMap<String, ConversionJSON>;
+
+    let conversion_json: ConversionFile = HashMap::from(
+        conversion
+            .iter()
+            .map(|(k, v)| { (k.clone(), ConversionJSON { Start2ART: *v, colors: Vec::new() }) })
+            .collect::<ConversionFile>()
+    );
+
+    //write conversion to file
+    let file = File::create(&conversion_location)?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, &conversion_json)?;
+    writer.flush()?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use crate::{
+        email_templates::generate_ogv_receipt,
+        load_locations::PipelineType,
+        mock_api::{ submission, MockApi },
+        tools::{ FakeTools, Replay },
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_generate_receipt_email() {
+        let mut submission = submission(PipelineType::Ogv, "abc123");
+        submission["conversion"] = serde_json::json!({ "Start2Art": 1 });
+        submission["uploads"] = serde_json::json!([
+            { "libName": "lib1", "fileName": "file1" },
+            { "libName": "lib2", "fileName": "file2" }
+        ]);
+        let data: OgvAPI = serde_json::from_value(submission).unwrap();
+
+        let receipt = generate_ogv_receipt(&data);
+
+        assert_eq!(receipt.contains("Start2Art: 1"), true);
+        assert_eq!(receipt.contains("lib1: file1"), true);
+        assert_eq!(receipt.contains("lib2: file2"), true);
+    }
+
+    #[test]
+    fn test_generate_samples_file() {
+        let uploads = vec![
+            OgvUpload {
+                lib_name: "lib1".to_string(),
+                file_name: "file1".to_string(),
+            },
+            OgvUpload {
+                lib_name: "lib2".to_string(),
+                file_name: "file2".to_string(),
+            }
+        ];
+
+        let samples_file_location = "samples.json";
+        let _ = generate_samples_file(&uploads, samples_file_location);
+
+        let file = std::fs::read_to_string(samples_file_location).unwrap();
+        let samples_json: Value = serde_json::from_str(&file).unwrap();
+
+        assert_eq!(samples_json["samples"][0], "lib1/file1");
+        assert_eq!(samples_json["samples"][1], "lib2/file2");
+
+        let _ = std::fs::remove_file(samples_file_location);
+    }
+
+    #[test]
+    fn test_write_conversion_to_file() {
+        let mut conversion: OgvConversion = HashMap::new();
+        conversion.insert("CAP188".to_string(), 123);
+        let conversion_location = "./conversion.json";
+        let wrote_conversion = write_conversion_to_file(&conversion, conversion_location);
+        assert_eq!(wrote_conversion.unwrap(), true);
+
+        //delete file at conversion_location
+        let _ = std::fs::remove_file(conversion_location);
+    }
+
+    async fn run(name: &str, tools: FakeTools) -> (Result<()>, Arc<FakeTools>, PathBuf) {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("ogv_{}_{}", name, std::process::id()));
+        let locations = api.locations(&dir);
+        let mut submission = api.submission(PipelineType::Ogv, "abc123");
+        submission["email"] = "user@uni.edu".into();
+        submission["uploads"] = serde_json::json!([
+            { "libName": "lib1", "fileName": "file1.fasta" }
+        ]);
+        submission["conversion"] = serde_json::json!({ "CAP188": 120 });
+        api.serve("/api/ogv/abc123", submission);
+
+        let tools = Arc::new(
+            tools
+                .bucket("gs://bucket/ogv-dating/abc123", "data")
+                .on("result-summary.py", Replay::new().copy("results", "results"))
+        );
+        let pipeline = Pipeline::<OgvAPI>
+            ::with_locations("abc123", PipelineType::Ogv, &locations).await
+            .unwrap()
+            .with_tools(tools.clone());
+
+        let result = process(&pipeline, locations).await;
+        (result, tools, dir)
+    }
+
+    #[tokio::test]
+    async fn test_process() {
+        let (result, tools, dir) = run("ok", FakeTools::new("ogv")).await;
+        result.unwrap();
+
+        let scratch_dir = dir.join("scratch/ogv/abc123");
+        assert!(scratch_dir.join("data/lib1/file1.fasta").exists());
+        assert!(scratch_dir.join("results/summary.csv").exists());
+
+        let conversion = std::fs::read_to_string(scratch_dir.join("conversion.json")).unwrap();
+        assert_eq!(conversion, r#"{"CAP188":{"Start2ART":120,"colors":[]}}"#);
+
+        let emails = tools.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "OGV Dating Results #ogv_abc123");
+        let link = "https://storage.googleapis.com/bucket/ogv-dating/abc123/";
+        assert!(emails[0].body.contains(link));
+        assert!(emails[0].body.contains("X-Goog-Signature="));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_error() {
+        // the summary still gets written when a sample fails
+        let tools = FakeTools::new("ogv").on("snakemake", Replay::new().copy("error", "error"));
+        let (result, tools, dir) = run("error", tools).await;
+
+        let error = result.unwrap_err().to_string();
+        assert_eq!(error.trim(), "Dating failed for lib1: not enough sequences.");
+        assert!(tools.emails().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
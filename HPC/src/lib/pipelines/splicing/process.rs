@@ -0,0 +1,273 @@
+use std::{ collections::HashMap, path::Path, sync::{ Arc, Mutex } };
+use anyhow::{ Result, Context };
+use crate::{
+    api_client::PipelinePatch,
+    bin_locations::{ ProjectBinNames, project_root_bin_location },
+    compress::compress_dir,
+    email_templates::results_email_template,
+    load_locations::Locations,
+    logging::library_span,
+    pipeline::{ Pipeline, SplicingAPI },
+};
+// use rayon::prelude::*;
+use super::sort_files::{ sort_files, RFiles };
+
+/*
+
+FILE STRUCTURE
+=================
+temp_downloads
+libs
+  ├── lib_1
+  │   ├── lib_1_r1.fastq
+  │   ├── lib_1_r2.fastq
+  ...
+results
+
+*/
+
+pub async fn process(pipeline: &Pipeline<SplicingAPI>, _locations: Locations) -> Result<()> {
+    pipeline.add_log(&format!("Initializing Splicing pipeline #{}", &pipeline.id))?;
+
+    let splicing_bin_location = project_root_bin_location(ProjectBinNames::SPLICING).unwrap();
+
+    let conda_command = "conda run -n splicing";
+
+    // set up variables
+    let job_id = pipeline.job_id();
+    let htsf_location = pipeline.data.htsf.as_deref().unwrap_or("").to_owned();
+    let samples_dir = format!("{}/libs", &pipeline.scratch_dir);
+    let results_location = format!("{}/{}", &pipeline.scratch_dir, &job_id);
+
+    //create directories
+    if !Path::new(&samples_dir).exists() {
+        std::fs::create_dir(&samples_dir).context("Failed to create DR directory.")?;
+    }
+
+    if !Path::new(&results_location).exists() {
+        std::fs::create_dir(&results_location).context("Failed to create results directory.")?;
+    }
+
+    // sort files for HTSF / Uploaded
+    let jobs: HashMap<String, RFiles> = pipeline.checkpoint("download", async || {
+        if !htsf_location.is_empty() {
+            pipeline.add_log(
+                &format!("Transferring results from HTSF location: {}", &htsf_location)
+            )?;
+
+            if !Path::new(&htsf_location).exists() {
+                return Err(anyhow::anyhow!("HTSF location does not exist: {}", &htsf_location));
+            }
+
+            return sort_files(&htsf_location, &samples_dir).context("Failed to sort input files.");
+        }
+
+        let from: String = String::from("*");
+
+        let temp_downloads_dir = format!("{}/temp_downloads", &pipeline.scratch_dir);
+
+        pipeline.add_log("Downloading samples from bucket.")?;
+        pipeline
+            .bucket_download(&from, &temp_downloads_dir, true).await
+            .context("Failed to download bucket files.")?;
+
+        let jobs = sort_files(&temp_downloads_dir, &samples_dir).context(
+            "Failed to sort input files."
+        )?;
+
+        if Path::new(&temp_downloads_dir).exists() {
+            std::fs::remove_dir_all(&temp_downloads_dir)?;
+        }
+
+        Ok(jobs)
+    }).await?;
+
+    // run Splicing
+    pipeline.stage("splicing");
+    pipeline.progress.libraries(jobs.len());
+
+    let errors = Arc::new(Mutex::new(Vec::<String>::new()));
+
+    // jobs.into_par_iter().for_each(|(lib_name, rfiles)| {
+    jobs.into_iter().for_each(|(lib_name, rfiles)| {
+        let date_now = chrono::Utc::now().to_rfc2822();
+
+        let _span = library_span(&lib_name);
+        let lib_stage = format!("splicing-{}", lib_name);
+
+        if pipeline.checkpoints.done(&lib_stage) {
+            let _ = pipeline.add_log(
+                &format!("Skipping job #{}, finished in a previous run.", lib_name)
+            );
+            pipeline.progress.library_finished();
+            return;
+        }
+
+        let _ = pipeline.add_log(&format!("Initializing job #{}: at [{}]", lib_name, &date_now));
+
+        let r1_file = rfiles.r1.unwrap_or_default();
+        let r2_file = rfiles.r2.unwrap_or_default();
+        let assay = pipeline.data.assay.replace('-', "_");
+
+        let command = format!(
+            // "{} {} --query {} --distance {} --assay {} --file1 {} --file2 {}",
+            "{} {}  {}  {}  {}  {}  {}",
+            conda_command,
+            splicing_bin_location,
+            pipeline.data.strain,
+            pipeline.data.distance,
+            assay,
+            // &pipeline.data.assay,
+            r1_file,
+            r2_file
+        );
+
+        let process_output = pipeline.run_command(&command, "");
+
+        let output_file = Path::new(&r1_file).parent().unwrap().join("output.tsv");
+
+        if let Err(e) = process_output {
+            println!("ERROR LOG: {:?}", &e);
+
+            errors
+                .lock()
+                .unwrap()
+                .push(format!("Failed to process lib#{}:\n{}", lib_name, &e.to_string()));
+        } else if !output_file.exists() {
+            let s = &process_output.unwrap_or("default".to_string());
+
+            println!("FAILED TO CREATE OUTPUT: {:?}", &s);
+
+            errors.lock().unwrap().push(format!("Failed to process lib#{}:\n{}", lib_name, &s));
+        } else {
+            // move output file to results location
+            let output_file_name = format!("{}_output.tsv", &lib_name);
+            let output_file_destination = Path::new(&results_location).join(&output_file_name);
+            let _ = std::fs
+                ::copy(&output_file, &output_file_destination)
+                .context("Failed to copy output file.");
+
+            // move html output to results location
+            let output_file = Path::new(&r1_file).parent().unwrap().join("output.html");
+            let output_file_name = format!("{}_output.html", &lib_name);
+            let output_file_destination = Path::new(&results_location).join(&output_file_name);
+            let _ = std::fs
+                ::copy(&output_file, &output_file_destination)
+                .context("Failed to copy output file.");
+
+            let _ = pipeline.checkpoints.save(&lib_stage, &());
+        }
+
+        pipeline.progress.library_finished();
+    });
+
+    // println!("Errors: {:?}", &errors);
+
+    // return Ok(());
+
+    // move files to results location
+    // let move_files_command = format!("mv {}/* {}", &pipeline.scratch_dir, &results_location);
+    // pipeline.run_command(&move_files_command, &pipeline.scratch_dir).context(
+    //     "Failed to move files to results location."
+    // )?;
+
+    let (location, compressed_filename) = pipeline.checkpoint("compress", async || {
+        pipeline.add_log(
+            &format!(
+                "Compressing results\nInput: {}\nOutput: {}",
+                &results_location,
+                &pipeline.scratch_dir
+            )
+        )?;
+        compress_dir(
+            &pipeline.data.results_format,
+            &job_id,
+            &results_location,
+            &pipeline.scratch_dir
+        ).context("Failed to compress files.")
+    }).await?;
+
+    // upload compressed results, the link is signed when emailing so a resumed run gets a fresh one
+    let archive = pipeline.checkpoint("upload", async || {
+        pipeline
+            .bucket_upload(&location.display().to_string(), &compressed_filename).await
+            .context("Failed to upload files to bucket.")?;
+        Ok(compressed_filename.clone())
+    }).await?;
+
+    // generate and send receipt
+    pipeline.checkpoint("email", async || {
+        pipeline.add_log("Emailing results.")?;
+        let signed_url = pipeline
+            .bucket_signed_url(&archive).await
+            .context("Failed to generate a signed url.")?;
+        let results_body = results_email_template(signed_url, pipeline.link_expiry_days(), "");
+        pipeline.email(
+            &format!("Splicing Results #{}", &job_id),
+            &results_body,
+            &pipeline.data.email,
+            false
+        ).await
+    }).await?;
+
+    // patch as completed
+    pipeline
+        .patch_pipeline(&PipelinePatch::completed()).await
+        .context("Failed to patch pipeline as completed.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{
+        load_locations::PipelineType,
+        mock_api::MockApi,
+        tools::{ FakeTools, Replay },
+    };
+
+    #[tokio::test]
+    async fn test_process() {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("splicing_{}", std::process::id()));
+        let locations = api.locations(&dir);
+        let mut submission = api.submission(PipelineType::Splicing, "abc123");
+        submission["email"] = "user@uni.edu".into();
+        api.serve("/api/splicing/abc123", submission);
+
+        let lib1_dir = dir.join("scratch/splicing/abc123/libs/lib1").display().to_string();
+        // lib2 runs but leaves no output
+        let tools = Arc::new(
+            FakeTools::new("splicing")
+                .bucket("gs://bucket/splicing/abc123", "uploads")
+                .on("lib1_r1.fastq", Replay::new().copy("output", &lib1_dir))
+        );
+        let pipeline = Pipeline::<SplicingAPI>
+            ::with_locations("abc123", PipelineType::Splicing, &locations).await
+            .unwrap()
+            .with_tools(tools.clone());
+
+        process(&pipeline, locations).await.unwrap();
+
+        let runs: Vec<String> = tools
+            .calls()
+            .into_iter()
+            .filter(|call| call.starts_with("conda run -n splicing"))
+            .collect();
+        assert_eq!(runs.len(), 2);
+        assert!(runs.iter().all(|run| run.contains("NL43") && run.contains("ALL")));
+
+        let results_dir = dir.join("scratch/splicing/abc123/splicing_abc123");
+        assert!(results_dir.join("lib1_output.tsv").exists());
+        assert!(results_dir.join("lib1_output.html").exists());
+        assert!(!results_dir.join("lib2_output.tsv").exists());
+
+        let emails = tools.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "Splicing Results #splicing_abc123");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
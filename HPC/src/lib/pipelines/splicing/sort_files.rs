@@ -1,5 +1,6 @@
 use anyhow::{ Context, Result };
 use glob::glob;
+use serde::{ Deserialize, Serialize };
 use std::path::PathBuf;
 use std::{ collections::HashMap, path::Path };
 
@@ -13,7 +14,7 @@ use std::{ collections::HashMap, path::Path };
  * @returns: HashMap< "lib_name", { r1: "/path/to/r1_file", r2: "/path/to/r2_file" } >
  */
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RFiles {
     pub r1: Option<String>,
     pub r2: Option<String>,
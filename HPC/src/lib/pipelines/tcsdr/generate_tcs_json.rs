@@ -1,6 +1,6 @@
 use anyhow::{Context, Result};
 use serde::Serialize;
-use utils::pipeline::{Primer, TcsAPI};
+use crate::pipeline::{Primer, TcsAPI};
 
 #[derive(Debug, Serialize)]
 pub struct TcsJson {
@@ -0,0 +1,5 @@
+pub mod process;
+mod validate_file_names;
+mod generate_tcs_json;
+mod sort_files;
+mod downsample_sequence_files;
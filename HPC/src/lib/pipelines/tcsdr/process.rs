@@ -0,0 +1,459 @@
+use anyhow::{ Context, Result };
+use glob::glob;
+use rayon::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+use crate::{
+    api_client::PipelinePatch,
+    compress::compress_dir,
+    email_templates::results_email_template,
+    load_locations::Locations,
+    logging::library_span,
+    pipeline::{ Pipeline, TcsAPI },
+};
+
+use super::{
+    // downsample_sequence_files::{ downsample_sequence_files, MAX_SAMPLES_PER_FILE },
+    generate_tcs_json::generate_tcs_json,
+    sort_files::sort_files,
+};
+
+// TODO replace cargo run with tcs bin after virust-tcs update
+
+pub async fn process(pipeline: &Pipeline<TcsAPI>, locations: Locations) -> Result<()> {
+    pipeline.add_log(&format!("Initializing TCS/DR pipeline #{}", &pipeline.id))?;
+
+    // patch as pending
+    pipeline
+        .patch_pipeline(&PipelinePatch::pending()).await
+        .context("Failed to patch pipeline as pending.")?;
+
+    // set up variables
+    let is_dr: bool = pipeline.is_dr();
+    let pool_name = pipeline.pool_name();
+    let htsf_location = pipeline.data.htsf.as_deref().unwrap_or("").to_owned();
+    let job_id: String = pipeline.job_id();
+    let samples_dir = format!("{}/{}", &pipeline.scratch_dir, pool_name);
+    let log_upload_location = format!(
+        "{}/logs/{}/log.html",
+        &locations.tcs_log_bucket_url,
+        &pipeline.id
+    );
+    let log_local_location = format!("{}_tcs/log.html", &samples_dir);
+
+    // transfer samples
+    pipeline.checkpoint("download", async || {
+        if !Path::new(&samples_dir).exists() {
+            std::fs::create_dir(&samples_dir).context("Failed to create DR directory.")?;
+        }
+
+        if !htsf_location.is_empty() {
+            let htsf_path = Path::new(&htsf_location);
+
+            if !htsf_path.exists() || !htsf_path.is_dir() {
+                return Err(
+                    anyhow::anyhow!("TCS/DR Error:\n\nInvalid HTSF Directory: {}", htsf_location)
+                );
+            }
+
+            let has_fast_file = std::fs
+                ::read_dir(htsf_path)?
+                .filter_map(Result::ok)
+                .any(|entry| {
+                    entry
+                        .file_type()
+                        .map(|ft| ft.is_file())
+                        .unwrap_or(false) && entry.file_name().to_string_lossy().contains(".fast")
+                });
+
+            if !has_fast_file {
+                return Err(
+                    anyhow::anyhow!(
+                        "TCS/DR Error:\n\nHTSF Directory is valid but contained no fast files: {}",
+                        htsf_location
+                    )
+                );
+            }
+
+            pipeline.add_log(
+                &format!("Transferring results from HTSF location: {}", &htsf_location)
+            )?;
+
+            sort_files(&htsf_location, &samples_dir).await.context(
+                "Failed to sort input files by lib name."
+            )?;
+        } else {
+            let from: String = String::from("*");
+
+            pipeline.add_log("Downloading samples from bucket.")?;
+            pipeline
+                .bucket_download(&from, &samples_dir, true).await
+                .context("Failed to download bucket files.")?;
+        }
+
+        Ok(())
+    }).await?;
+
+    // downsample_sequence_files(&samples_dir, MAX_SAMPLES_PER_FILE, |file| {
+    //     pipeline.add_log(
+    //         &format!("Clipped {} to the first {} samples.", file.display(), MAX_SAMPLES_PER_FILE)
+    //     )
+    // })?;
+
+    pipeline.checkpoint("tcs", async || {
+        // thread TCS/DR jobs
+        // filter is_dir to skip compressed results when rerunning jobs
+        let jobs: Vec<PathBuf> = glob(&format!("{}/*", &samples_dir))
+            .expect("failed to glob samples_dir")
+            .filter_map(Result::ok)
+            .filter(|path| path.is_dir())
+            .collect();
+
+        pipeline.progress.libraries(jobs.len());
+
+        jobs.into_par_iter()
+            .enumerate()
+            .for_each(|(i, pathbuf)| {
+                let date_now = chrono::Utc::now().to_rfc2822();
+                let lib_name = pathbuf.file_name().unwrap().to_str().unwrap();
+                let _span = library_span(lib_name);
+                let lib_stage = format!("tcs-{}", lib_name);
+
+                if pipeline.checkpoints.done(&lib_stage) {
+                    let _ = pipeline.add_log(
+                        &format!("Skipping job #{}: {}, finished in a previous run.", &i, lib_name)
+                    );
+                    pipeline.progress.library_finished();
+                    return;
+                }
+
+                // the error of a failed previous run, this run writes its own
+                let error_file = pathbuf.join(".error");
+                if error_file.exists() {
+                    let _ = std::fs::remove_file(&error_file);
+                }
+
+                let _ = pipeline.add_log(
+                    &format!("Initializing job #{}: {} at [{}]", &i, lib_name, &date_now)
+                );
+
+                // run commands, tcs writes a .error file for the errors it catches itself
+                let ran = if is_dr {
+                    let dr_command = format!(
+                        "conda run -n tcsdr tcs -d {} -i {}",
+                        &pipeline.data.dr_version,
+                        &pathbuf.display()
+                    );
+                    let _ = pipeline.add_log(&format!("Running DR command: {}", &dr_command));
+                    pipeline.run_command(&dr_command, &pipeline.scratch_dir)
+                } else {
+                    let json_location = generate_tcs_json(
+                        &pipeline.data,
+                        &pathbuf.display().to_string(),
+                        lib_name
+                    ).unwrap_or("".to_string());
+
+                    if json_location.is_empty() {
+                        //todo create an error file at &pipeline.scratch_dir
+                        print!("TCS JSON location empty.");
+                        pipeline.progress.library_finished();
+                        return;
+                    }
+
+                    if !Path::new(&json_location).exists() {
+                        print!("TCS JSON does not exist.");
+                        pipeline.progress.library_finished();
+                        return;
+                    }
+
+                    let tcs_command = format!("conda run -n tcsdr tcs -p {}", &json_location);
+                    pipeline.run_command(&tcs_command, &pipeline.scratch_dir)
+                };
+
+                // a command that failed before writing .error runs again on --resume
+                if ran.is_ok() && !error_file.exists() {
+                    let _ = pipeline.checkpoints.save(&lib_stage, &());
+                }
+                pipeline.progress.library_finished();
+            });
+
+        // check for TCS errors written to the .error file of each subdirectory
+        let tcs_error_files = glob(&format!("{}/*/.error", &samples_dir))
+            .unwrap()
+            .map(|f| f.unwrap())
+            .collect::<Vec<PathBuf>>();
+
+        if !tcs_error_files.is_empty() {
+            let tcs_error_msg = tcs_error_files
+                .iter()
+                .map(|f| {
+                    std::fs::read_to_string(f).unwrap_or("Failed to read error file.".to_string())
+                })
+                .collect::<Vec<String>>()
+                .join("\n\n");
+
+            return Err(anyhow::anyhow!("TCS/DR Error:\n\n{}", tcs_error_msg));
+        }
+
+        Ok(())
+    }).await?;
+
+    // process concensus
+    pipeline.checkpoint("consensus", async || {
+        let consensus_command = format!("conda run -n tcsdr tcs_log {}", &samples_dir);
+        pipeline
+            .run_command(&consensus_command, &pipeline.scratch_dir)
+            .context("Failed to run consensus.")?;
+        Ok(())
+    }).await?;
+
+    // run SDRM
+    if is_dr {
+        pipeline.checkpoint("sdrm", async || {
+            let temp_sdrm_dir = format!("{}/temp", &pipeline.scratch_dir);
+            let input_sdrm = format!("{}_DRM_analysis", &samples_dir);
+            let sdrm_command = format!(
+                "conda run -n tcsdr tcs_sdrm {} {}",
+                &temp_sdrm_dir,
+                &pipeline.data.dr_version
+            );
+            let sdrm_error_file = format!("{}/.error", &input_sdrm);
+            let cp_command = format!(
+                "cp -R {}/. {}/",
+                &format!("{}_tcs/combined_TCS_per_lib", &samples_dir),
+                &temp_sdrm_dir
+            );
+
+            if !Path::new(&temp_sdrm_dir).exists() {
+                std::fs
+                    ::create_dir(&temp_sdrm_dir)
+                    .context("Failed to create temp SDRM directory.")?;
+            }
+
+            pipeline.run_command(&cp_command, &pipeline.scratch_dir).context(
+                "Failed to copy files to temp SDRM directory."
+            )?;
+
+            pipeline.run_command(&sdrm_command, &temp_sdrm_dir).context("Failed to run SDRM.")?;
+
+            if Path::new(&sdrm_error_file).exists() {
+                let sdrm_error_msg = std::fs
+                    ::read_to_string(&sdrm_error_file)
+                    .unwrap_or("SDRM error and Failed to read SDRM error file.".to_string());
+
+                return Err(anyhow::anyhow!("SDRM Error:\n\n{}", sdrm_error_msg));
+            }
+
+            Ok(())
+        }).await?;
+    }
+
+    // add log to email as link, signed when emailing like the results
+    let log_uploaded: bool = pipeline.checkpoint("report", async || {
+        if !Path::new(&log_local_location).exists() {
+            return Ok(false);
+        }
+        pipeline.storage.upload(&log_local_location, &log_upload_location).await.context("")?;
+        Ok(true)
+    }).await?;
+
+    // compress results
+    let results_location = format!("{}/{}", &pipeline.scratch_dir, &job_id);
+
+    if !Path::new(&results_location).exists() {
+        std::fs::create_dir(&results_location).context("Failed to create results directory.")?;
+    }
+
+    pipeline.checkpoint("collect", async || {
+        // Move scratch contents into results, but skip the results directory itself.
+        for entry in std::fs
+            ::read_dir(&pipeline.scratch_dir)
+            .context("Failed to read scratch directory before compressing results.")? {
+            let entry = entry.context("Failed to read scratch directory entry.")?;
+            let source_path = entry.path();
+
+            if source_path == Path::new(&results_location) || entry.file_name() == ".checkpoints" {
+                continue;
+            }
+
+            let destination_path = Path::new(&results_location).join(entry.file_name());
+            std::fs
+                ::rename(&source_path, &destination_path)
+                .with_context(|| {
+                    format!(
+                        "Failed to move '{}' to '{}'",
+                        source_path.display(),
+                        destination_path.display()
+                    )
+                })?;
+        }
+
+        Ok(())
+    }).await?;
+
+    let (location, compressed_filename) = pipeline.checkpoint("compress", async || {
+        pipeline.add_log(
+            &format!(
+                "Compressing results\nInput: {}\nOutput: {}",
+                &results_location,
+                &pipeline.scratch_dir
+            )
+        )?;
+        compress_dir(
+            &pipeline.data.results_format,
+            &job_id,
+            &results_location,
+            &pipeline.scratch_dir
+        ).context("Failed to compress files.")
+    }).await?;
+
+    // upload compressed results, the link is signed when emailing so a resumed run gets a fresh one
+    let archive = pipeline.checkpoint("upload", async || {
+        pipeline
+            .bucket_upload(&location.display().to_string(), &compressed_filename).await
+            .context("Failed to upload files to bucket.")?;
+        Ok(compressed_filename.clone())
+    }).await?;
+
+    // generate and send receipt
+    pipeline.checkpoint("email", async || {
+        pipeline.add_log("Emailing results.")?;
+        let signed_url = pipeline
+            .bucket_signed_url(&archive).await
+            .context("Failed to generate a signed url.")?;
+
+        // default to failed to generate log message
+        let log_link_html = if log_uploaded {
+            let log_signed_url = pipeline.storage
+                .signed_url(&log_upload_location).await
+                .context("")?;
+            format!(
+                "<br><a href='{}' style='font-size: 18px;'>View Report</a><br>",
+                &log_signed_url
+            )
+        } else {
+            String::from(
+                "<div style='background-color:#d9534f;color:white;padding:12px 16px;border-radius:4px;font-weight:bold;font-size:14px;margin:12px 0;'>⚠️ Failed to generate log file.</div>"
+            )
+        };
+
+        let data_pool_name = pipeline.data.pool_name.as_deref().unwrap_or("");
+        let pool_name_html = if !data_pool_name.is_empty() {
+            format!("Pool Name: {}\n\n", data_pool_name)
+        } else {
+            String::from("\n")
+        };
+
+        let results_body =
+            format!("ID: {}\n{}", &pipeline.data.id, pool_name_html) +
+            &results_email_template(signed_url, pipeline.link_expiry_days(), &log_link_html);
+
+        pipeline.email(
+            &format!("{} Results #{}", if is_dr { "DR" } else { "TCS" }, &job_id),
+            &results_body,
+            &pipeline.data.email,
+            false
+        ).await
+    }).await?;
+
+    // patch as completed
+    pipeline
+        .patch_pipeline(&PipelinePatch::completed()).await
+        .context("Failed to patch pipeline as completed.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::{
+        load_locations::PipelineType,
+        mock_api::MockApi,
+        tools::{ FakeTools, Replay },
+    };
+
+    async fn run(name: &str, tools: FakeTools) -> (Result<()>, Arc<FakeTools>, MockApi, PathBuf) {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("tcsdr_{}_{}", name, std::process::id()));
+        let locations = api.locations(&dir);
+        let mut submission = api.submission(PipelineType::Tcs, "abc123");
+        submission["email"] = "user@uni.edu".into();
+        api.serve("/api/tcsdr/abc123", submission);
+
+        // the bucket holds a directory per library
+        let tools = Arc::new(tools.bucket("gs://bucket/tcs-dr/abc123", "samples"));
+        let pipeline = Pipeline::<TcsAPI>
+            ::with_locations("abc123", PipelineType::Tcs, &locations).await
+            .unwrap()
+            .with_tools(tools.clone());
+
+        let result = process(&pipeline, locations).await;
+        (result, tools, api, dir)
+    }
+
+    #[tokio::test]
+    async fn test_process() {
+        let tools = FakeTools::new("tcsdr").on(
+            "tcs_log",
+            Replay::new().copy("log/log.html", "TCSDR_tcs/log.html")
+        );
+        let (result, tools, api, dir) = run("ok", tools).await;
+        result.unwrap();
+
+        let calls = tools.calls();
+        assert_eq!(calls.iter().filter(|call| call.contains("tcs -d v1 -i")).count(), 2);
+        assert!(calls.iter().any(|call| call.ends_with("gs://bucket/logs/abc123/log.html")));
+        assert!(calls.iter().any(|call| call.contains("tcs_sdrm")));
+
+        let emails = tools.emails();
+        assert_eq!(emails.len(), 1);
+        assert_eq!(emails[0].subject, "DR Results #dr_TCSDR");
+        assert!(emails[0].body.contains("ID: abc123"));
+        let log_link = "<a href='https://storage.googleapis.com/bucket/logs/abc123/log.html?";
+        assert!(emails[0].body.contains(log_link));
+
+        let patches = api.patches("/api/tcsdr/abc123");
+        let completed = serde_json::json!({ "pending": false, "submit": false });
+        assert_eq!(patches[0], serde_json::json!({ "pending": true, "submit": false }));
+        assert_eq!(patches.last(), Some(&completed));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_library_errors() {
+        let tools = FakeTools::new("tcsdr").on(
+            "TCSDR/lib2",
+            Replay::new().copy("error/.error", "TCSDR/lib2/.error")
+        );
+        let (result, tools, _api, dir) = run("error", tools).await;
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.starts_with("TCS/DR Error:"));
+        assert!(error.contains("Lib lib2: no TCS found, check the primer sequences."));
+
+        // nothing after the failed stage ran
+        assert!(!tools.calls().iter().any(|call| call.contains("tcs_log")));
+        assert!(tools.emails().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_failed_command_is_not_checkpointed() {
+        // lib2's files download, then its tcs command can't start
+        let tools = FakeTools::new("tcsdr")
+            .on("lib2_r", Replay::new())
+            .on("TCSDR/lib2", Replay::new().error("conda: command not found"));
+        let (_result, _tools, _api, dir) = run("failed", tools).await;
+
+        // lib2 left no .error behind, --resume still runs it again
+        let checkpoints = dir.join("scratch/tcsdr/abc123/.checkpoints");
+        assert!(checkpoints.join("tcs-lib1.json").exists());
+        assert!(!checkpoints.join("tcs-lib2.json").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -2,7 +2,7 @@ use std::path::{ Path, PathBuf };
 use anyhow::{ Result, Context };
 use glob::glob;
 
-use crate::validate_file_names::{ validate_file_names, FilesResults };
+use super::validate_file_names::{ validate_file_names, FilesResults };
 
 pub async fn sort_files(dir: &str, destination: &str) -> Result<()> {
     if !Path::new(destination).exists() {
@@ -1,5 +1,5 @@
 use std::path::PathBuf;
-use utils::load_locations::{ load_locations, Locations };
+use crate::load_locations::{ load_locations, Locations };
 use anyhow::{ Result, Context };
 use serde::Deserialize;
 use reqwest::{ self, header::CONTENT_TYPE };
@@ -29,7 +29,7 @@ pub struct FilesResults {
 
 #[allow(dead_code)]
 pub async fn validate_file_names(files: Vec<PathBuf>) -> Result<Vec<FilesResults>> {
-    let Locations { ruby_server, .. } = load_locations().unwrap();
+    let Locations { ruby_server, .. } = load_locations()?;
 
     let filenames: Vec<String> = files
         .iter()
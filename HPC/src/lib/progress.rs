@@ -0,0 +1,176 @@
+use std::sync::{ Arc, Mutex };
+use std::time::{ Duration, Instant };
+use chrono::Utc;
+use serde::{ Deserialize, Serialize };
+use tokio::runtime::Handle;
+
+use crate::api_client::{ ApiClient, PipelinePatch };
+
+/*
+    Where a running pipeline is, PATCHed to the web API as `progress` so the site can show it
+        { "stage": "tcs", "librariesDone": 3, "librariesTotal": 8, "startedAt": ...,
+          "stageStartedAt": ..., "finishedAt": null }
+    Updates are sent at most once per `progress_interval` seconds from locations.json,
+    changes made in between go out together when the interval is up
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+    pub stage: String,
+    pub libraries_done: u32,
+    pub libraries_total: u32,
+    pub started_at: Option<String>,
+    pub stage_started_at: Option<String>,
+    pub finished_at: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    progress: Progress,
+    last_sent: Option<Instant>,
+    send_scheduled: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReporter {
+    url: String,
+    api: ApiClient,
+    interval: Duration,
+    // only the pipeline binaries report, process_queue never calls start
+    runtime: Option<Handle>,
+    state: Arc<Mutex<State>>,
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+impl ProgressReporter {
+    pub fn new(url: &str, api: ApiClient, interval: Duration) -> ProgressReporter {
+        ProgressReporter {
+            url: url.to_owned(),
+            api,
+            interval,
+            runtime: None,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.runtime = Handle::try_current().ok();
+        self.update(|progress| {
+            progress.started_at = Some(now());
+        });
+    }
+
+    pub fn stage(&self, stage: &str) {
+        self.update(|progress| {
+            progress.stage = stage.to_owned();
+            progress.stage_started_at = Some(now());
+        });
+    }
+
+    pub fn libraries(&self, total: usize) {
+        self.update(|progress| {
+            progress.libraries_done = 0;
+            progress.libraries_total = total as u32;
+        });
+    }
+
+    // safe to call from the rayon pool
+    pub fn library_finished(&self) {
+        self.update(|progress| {
+            progress.libraries_done = (progress.libraries_done + 1).min(progress.libraries_total);
+        });
+    }
+
+    pub fn current(&self) -> Progress {
+        self.state.lock().unwrap().progress.clone()
+    }
+
+    // the last update goes out right away
+    pub async fn finish(&self) {
+        let progress = {
+            let mut state = self.state.lock().unwrap();
+            state.progress.finished_at = Some(now());
+            state.last_sent = Some(Instant::now());
+            state.progress.clone()
+        };
+
+        if self.runtime.is_some() {
+            self.send(progress).await;
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Progress)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state.progress);
+
+        let Some(runtime) = &self.runtime else {
+            return;
+        };
+
+        // a send is already waiting out the interval and will carry this change
+        if state.send_scheduled {
+            return;
+        }
+        state.send_scheduled = true;
+
+        let wait = state.last_sent.map_or(Duration::ZERO, |last_sent| {
+            self.interval.saturating_sub(last_sent.elapsed())
+        });
+
+        let reporter = self.clone();
+        runtime.spawn(async move {
+            tokio::time::sleep(wait).await;
+
+            let progress = {
+                let mut state = reporter.state.lock().unwrap();
+                state.send_scheduled = false;
+                state.last_sent = Some(Instant::now());
+                state.progress.clone()
+            };
+
+            reporter.send(progress).await;
+        });
+    }
+
+    async fn send(&self, progress: Progress) {
+        let patch = PipelinePatch { progress: Some(progress), ..Default::default() };
+
+        if let Err(e) = self.api.patch(&self.url, &patch).await {
+            println!("Failed to report progress: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let reporter = ProgressReporter::new(
+            "http://localhost/abc123",
+            ApiClient::default(),
+            Duration::ZERO
+        );
+
+        reporter.stage("tcs");
+        reporter.libraries(2);
+        reporter.library_finished();
+        reporter.library_finished();
+        reporter.library_finished();
+
+        let progress = reporter.current();
+        assert_eq!(progress.stage, "tcs");
+        assert_eq!((progress.libraries_done, progress.libraries_total), (2, 2));
+        assert!(progress.stage_started_at.is_some());
+
+        assert_eq!(
+            serde_json::to_value(&progress).unwrap()["librariesTotal"],
+            serde_json::json!(2)
+        );
+    }
+}
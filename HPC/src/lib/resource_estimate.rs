@@ -0,0 +1,238 @@
+use std::path::Path;
+use serde::{ Deserialize, Serialize };
+
+use crate::load_locations::PipelineKeys;
+use crate::scheduler::JobResources;
+
+/*
+    Cores, memory and wall time for a submission, scaled by the size of its input
+    Coefficients per pipeline under `estimator` in locations.json
+    Missing keys use Coefficients::default, a missing `estimator` uses default_estimator
+        cores   = clamp(files * cores_per_file + sequences * cores_per_sequence, min_cores, max_cores)
+        memory  = base_memory + cores * memory_per_core + GB * memory_per_gb        (MB, <= max_memory)
+        time    = base_time + GB * time_per_gb + sequences * time_per_sequence      (minutes, <= max_time)
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct InputSize {
+    pub bytes: u64,
+    pub files: u32,
+    // fasta records, `bytes` is then their total length
+    pub sequences: u32,
+}
+
+impl InputSize {
+    pub fn gb(&self) -> f64 {
+        (self.bytes as f64) / 1e9
+    }
+}
+
+// PipelineJob::resources result, the input is kept to record against the job's usage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub input: InputSize,
+    pub resources: JobResources,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Coefficients {
+    pub min_cores: u8,
+    pub max_cores: u8,
+    pub cores_per_file: f64,
+    pub cores_per_sequence: f64,
+    pub base_memory: u32,
+    pub memory_per_core: u32,
+    pub memory_per_gb: u32,
+    pub max_memory: u32,
+    pub base_time: u32,
+    pub time_per_gb: u32,
+    pub time_per_sequence: f64,
+    pub max_time: u32,
+}
+
+impl Default for Coefficients {
+    fn default() -> Self {
+        Coefficients {
+            min_cores: 1,
+            max_cores: 1,
+            cores_per_file: 0.0,
+            cores_per_sequence: 0.0,
+            base_memory: 0,
+            memory_per_core: 0,
+            memory_per_gb: 0,
+            max_memory: 250000,
+            base_time: 1440,
+            time_per_gb: 0,
+            time_per_sequence: 0.0,
+            max_time: 10080,
+        }
+    }
+}
+
+impl Coefficients {
+    // pipeline's own cores, process_queue adds one for the job itself
+    pub fn estimate(&self, input: &InputSize) -> JobResources {
+        let wanted_cores =
+            (input.files as f64) * self.cores_per_file +
+            (input.sequences as f64) * self.cores_per_sequence;
+        let max_cores = self.max_cores.max(self.min_cores);
+        let cores = (wanted_cores.ceil() as u8).clamp(self.min_cores, max_cores);
+
+        let memory =
+            (self.base_memory as f64) +
+            (cores as f64) * (self.memory_per_core as f64) +
+            input.gb() * (self.memory_per_gb as f64);
+
+        let time =
+            (self.base_time as f64) +
+            input.gb() * (self.time_per_gb as f64) +
+            (input.sequences as f64) * self.time_per_sequence;
+
+        JobResources {
+            cores,
+            memory: (memory.ceil() as u32).min(self.max_memory),
+            time: (time.ceil() as u32).min(self.max_time),
+        }
+    }
+}
+
+pub fn default_estimator() -> PipelineKeys<Coefficients> {
+    let defaults = Coefficients::default();
+
+    PipelineKeys {
+        base: defaults.clone(),
+        // 2 cores and 10gb per library
+        ogv: Coefficients {
+            min_cores: 2,
+            max_cores: 40,
+            cores_per_file: 2.0,
+            memory_per_core: 10000,
+            memory_per_gb: 2000,
+            max_memory: 500000,
+            base_time: 5760,
+            time_per_gb: 60,
+            ..defaults.clone()
+        },
+        // one single-threaded pair per core, memory grows with the pool
+        tcs: Coefficients {
+            max_cores: 9,
+            cores_per_file: 0.5,
+            base_memory: 4000,
+            memory_per_core: 10000,
+            memory_per_gb: 8000,
+            time_per_gb: 60,
+            ..defaults.clone()
+        },
+        intact: Coefficients {
+            max_cores: 9,
+            cores_per_sequence: 0.5,
+            memory_per_core: 20000,
+            ..defaults.clone()
+        },
+        // single-threaded, don't overload g2p
+        coreceptor: Coefficients {
+            base_memory: 5000,
+            ..defaults.clone()
+        },
+        // libraries run sequentially, cores are given to virust-splicing
+        splicing: Coefficients {
+            min_cores: 50,
+            max_cores: 50,
+            base_memory: 20000,
+            memory_per_gb: 4000,
+            time_per_gb: 60,
+            ..defaults.clone()
+        },
+        // threading happens in viral_seq::Locator
+        locator: Coefficients {
+            min_cores: 10,
+            max_cores: 10,
+            base_memory: 10000,
+            memory_per_gb: 2000,
+            ..defaults
+        },
+    }
+}
+
+// every file under a local directory, ex) an HTSF location
+pub fn dir_size(path: &Path) -> InputSize {
+    let mut size = InputSize::default();
+
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return size;
+    };
+
+    for entry in entries.flatten() {
+        let entry_path = entry.path();
+        if entry_path.is_dir() {
+            let inner = dir_size(&entry_path);
+            size.bytes += inner.bytes;
+            size.files += inner.files;
+        } else if let Ok(metadata) = entry.metadata() {
+            size.bytes += metadata.len();
+            size.files += 1;
+        }
+    }
+
+    size
+}
+
+// fasta pasted into the submission form
+pub fn sequence_size(fasta: &str) -> InputSize {
+    let mut size = InputSize::default();
+
+    for line in fasta.lines().map(|line| line.trim()) {
+        if line.starts_with('>') {
+            size.sequences += 1;
+        } else {
+            size.bytes += line.len() as u64;
+        }
+    }
+
+    size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_scales_with_input() {
+        let tcs = &default_estimator().tcs;
+
+        let small = tcs.estimate(&(InputSize { bytes: 100_000_000, files: 2, sequences: 0 }));
+        let big = tcs.estimate(&(InputSize { bytes: 20_000_000_000, files: 18, sequences: 0 }));
+
+        assert_eq!(small, JobResources { cores: 1, memory: 14800, time: 1446 });
+        assert_eq!(big.cores, 9);
+        assert_eq!(big.memory, 250000);
+        assert_eq!(big.time, 2640);
+    }
+
+    #[test]
+    fn test_estimate_fixed_cores() {
+        let splicing = &default_estimator().splicing;
+        let estimate = splicing.estimate(&InputSize::default());
+
+        assert_eq!(estimate, JobResources { cores: 50, memory: 20000, time: 1440 });
+    }
+
+    #[test]
+    fn test_sequence_size() {
+        let size = sequence_size(">seq1\nACGT\nAC\n>seq2\nACGTACGT\n");
+
+        assert_eq!(size, InputSize { bytes: 14, files: 0, sequences: 2 });
+        assert_eq!(default_estimator().intact.estimate(&size).cores, 1);
+    }
+
+    #[test]
+    fn test_partial_coefficients() {
+        let coefficients: Coefficients = serde_json
+            ::from_str(r#"{ "max_cores": 4, "memory_per_core": 1000 }"#)
+            .unwrap();
+
+        assert_eq!(coefficients.max_cores, 4);
+        assert_eq!(coefficients.max_time, 10080);
+    }
+}
@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::path::{ Path, PathBuf };
+use anyhow::{ Context, Result };
+use chrono::{ DateTime, Duration, Utc };
+
+use crate::api_client::{ ApiClient, PipelinePatch };
+use crate::load_locations::{ Locations, PipelineType };
+use crate::resource_estimate::dir_size;
+use crate::storage::{ StorageBackend, StoredObject };
+
+/*
+    Deletes what a submission leaves behind once retention_days[pipeline] have passed since it
+    last changed: its objects under bucket_url[pipeline]/{id}/ and scratch_space[pipeline]/{id}
+    The submission is then PATCHed { expired: true }
+        retention              deletes and patches
+        retention --dry-run    only reports what would be deleted
+    The last change is the newest object or scratch file, submissions with objects the storage
+    doesn't date are kept
+*/
+
+const PIPELINES: [PipelineType; 6] = [
+    PipelineType::Ogv,
+    PipelineType::Tcs,
+    PipelineType::Intact,
+    PipelineType::Coreceptor,
+    PipelineType::Splicing,
+    PipelineType::Locator,
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expired {
+    pub pipeline_type: PipelineType,
+    pub id: String,
+    pub objects: Vec<StoredObject>,
+    pub scratch_dir: Option<PathBuf>,
+    // stored and in scratch
+    pub bytes: u64,
+    pub last_changed: DateTime<Utc>,
+}
+
+#[derive(Debug, Default)]
+struct Submission {
+    objects: Vec<StoredObject>,
+    scratch_dir: Option<PathBuf>,
+    last_changed: Option<DateTime<Utc>>,
+    undated: bool,
+}
+
+// newest modification of a file under `path`, a directory's own time only counts when it's
+// empty since creating a subdirectory touches it
+fn newest_change(path: &Path) -> Option<DateTime<Utc>> {
+    let metadata = std::fs::symlink_metadata(path).ok()?;
+    if !metadata.is_dir() {
+        return metadata.modified().ok().map(DateTime::<Utc>::from);
+    }
+
+    std::fs
+        ::read_dir(path)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| newest_change(&entry.path()))
+        .max()
+        .or_else(|| metadata.modified().ok().map(DateTime::<Utc>::from))
+}
+
+async fn submissions(
+    locations: &Locations,
+    storage: &dyn StorageBackend,
+    pipeline_type: PipelineType
+) -> Result<BTreeMap<String, Submission>> {
+    let mut submissions: BTreeMap<String, Submission> = BTreeMap::new();
+
+    let prefix = format!("{}/", locations.bucket_url[pipeline_type].trim_end_matches('/'));
+    let objects = storage
+        .list(&prefix).await
+        .with_context(|| format!("Failed to list {}", &prefix))?;
+
+    for object in objects {
+        // ids are the first "directory" under bucket_url
+        let Some((id, _)) = object.location[prefix.len()..].split_once('/') else {
+            continue;
+        };
+        let submission = submissions.entry(id.to_owned()).or_default();
+        match object.updated {
+            Some(updated) => {
+                submission.last_changed = submission.last_changed.max(Some(updated));
+            }
+            None => {
+                submission.undated = true;
+            }
+        }
+        submission.objects.push(object);
+    }
+
+    // a missing scratch_space has nothing to expire
+    let scratch_space = Path::new(&locations.scratch_space[pipeline_type]);
+    for entry in std::fs::read_dir(scratch_space).into_iter().flatten().filter_map(Result::ok) {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let submission = submissions
+            .entry(entry.file_name().to_string_lossy().to_string())
+            .or_default();
+        submission.last_changed = submission.last_changed.max(newest_change(&path));
+        submission.scratch_dir = Some(path);
+    }
+
+    Ok(submissions)
+}
+
+// submissions not changed in retention_days, pipelines with 0 days are skipped
+pub async fn find_expired(
+    locations: &Locations,
+    storage: &dyn StorageBackend,
+    now: DateTime<Utc>
+) -> Result<Vec<Expired>> {
+    let mut expired: Vec<Expired> = vec![];
+
+    for pipeline_type in PIPELINES {
+        let days = locations.retention_days[pipeline_type];
+        if days == 0 {
+            continue;
+        }
+        let cutoff = now - Duration::days(days as i64);
+
+        for (id, submission) in submissions(locations, storage, pipeline_type).await? {
+            let Some(last_changed) = submission.last_changed else {
+                continue;
+            };
+            if submission.undated || last_changed >= cutoff {
+                continue;
+            }
+
+            let stored: u64 = submission.objects
+                .iter()
+                .map(|object| object.size)
+                .sum();
+            let scratch = submission.scratch_dir.as_ref().map_or(0, |dir| dir_size(dir).bytes);
+
+            expired.push(Expired {
+                pipeline_type,
+                id,
+                objects: submission.objects,
+                scratch_dir: submission.scratch_dir,
+                bytes: stored + scratch,
+                last_changed,
+            });
+        }
+    }
+
+    Ok(expired)
+}
+
+// the patch is only sent once everything is deleted, so a failure is tried again next run
+pub async fn expire(
+    expired: &Expired,
+    storage: &dyn StorageBackend,
+    api: &ApiClient,
+    locations: &Locations
+) -> Result<()> {
+    for object in &expired.objects {
+        storage
+            .delete(&object.location).await
+            .with_context(|| format!("Failed to delete {}", &object.location))?;
+    }
+
+    if let Some(dir) = &expired.scratch_dir {
+        std::fs
+            ::remove_dir_all(dir)
+            .with_context(|| format!("Failed to delete {}", dir.display()))?;
+    }
+
+    let url = format!("{}/{}", &locations.api_url[expired.pipeline_type], &expired.id);
+    api
+        .patch(&url, &PipelinePatch::expired()).await
+        .with_context(|| format!("Failed to mark {} as expired", url))
+}
+
+pub fn report(expired: &[Expired], dry_run: bool) -> String {
+    let mut lines: Vec<String> = expired
+        .iter()
+        .map(|submission| {
+            format!(
+                "{} {}: {} objects{}, {} bytes, last changed {}",
+                format!("{:?}", submission.pipeline_type).to_lowercase(),
+                &submission.id,
+                submission.objects.len(),
+                if submission.scratch_dir.is_some() { " and scratch" } else { "" },
+                submission.bytes,
+                submission.last_changed.format("%Y-%m-%d")
+            )
+        })
+        .collect();
+
+    lines.push(
+        format!(
+            "{} expired submissions, {} bytes {}",
+            expired.len(),
+            expired
+                .iter()
+                .map(|submission| submission.bytes)
+                .sum::<u64>(),
+            if dry_run { "would be deleted (dry run)" } else { "to delete" }
+        )
+    );
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_locations::PipelineKeys;
+    use crate::mock_api::MockApi;
+    use crate::storage::{ LocalStorage, StorageConfig };
+    use std::time::SystemTime;
+
+    fn write_aged(path: &Path, days: u64) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "ACGT").unwrap();
+        let modified = SystemTime::now() - std::time::Duration::from_secs(days * 24 * 60 * 60);
+        std::fs::File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retention() {
+        let api = MockApi::start().await.unwrap();
+        let dir = std::env::temp_dir().join(format!("retention_{}", std::process::id()));
+        let mut locations = api.locations(&dir);
+        locations.storage = StorageConfig::Local { root: String::new(), public_url: None };
+        locations.bucket_url.ogv = format!("file://{}/bucket/ogv-dating", dir.display());
+        locations.retention_days = PipelineKeys {
+            base: 0,
+            ogv: 30,
+            tcs: 0,
+            intact: 0,
+            coreceptor: 0,
+            splicing: 0,
+            locator: 0,
+        };
+
+        let bucket = dir.join("bucket/ogv-dating");
+        let scratch = dir.join("scratch/ogv");
+        // results and scratch both old
+        write_aged(&bucket.join("old1/results.zip"), 40);
+        write_aged(&scratch.join("old1/data/a.fasta"), 40);
+        // only scratch is left
+        write_aged(&scratch.join("old2/samples.json"), 31);
+        // still being looked at, or recently rerun
+        write_aged(&bucket.join("new1/results.zip"), 1);
+        write_aged(&bucket.join("new2/results.zip"), 40);
+        write_aged(&scratch.join("new2/results/summary.csv"), 2);
+
+        let storage = LocalStorage::default();
+        let expired = find_expired(&locations, &storage, Utc::now()).await.unwrap();
+        let ids: Vec<&str> = expired
+            .iter()
+            .map(|submission| submission.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["old1", "old2"]);
+        assert_eq!(expired[0].objects.len(), 1);
+        assert_eq!(expired[0].bytes, 8);
+
+        let report = report(&expired, true);
+        assert!(report.starts_with("ogv old1: 1 objects and scratch, 8 bytes, last changed "));
+        assert!(report.ends_with("2 expired submissions, 12 bytes would be deleted (dry run)"));
+
+        api.submission(PipelineType::Ogv, "old1");
+        api.submission(PipelineType::Ogv, "old2");
+        let client = ApiClient::new(&locations.api_key, &locations.api).unwrap();
+        for submission in &expired {
+            expire(submission, &storage, &client, &locations).await.unwrap();
+        }
+
+        assert!(!bucket.join("old1").join("results.zip").exists());
+        assert!(!scratch.join("old1").exists());
+        assert!(!scratch.join("old2").exists());
+        assert!(bucket.join("new1/results.zip").exists());
+        assert!(scratch.join("new2").exists());
+        assert_eq!(api.patches("/api/ogv/old1"), vec![serde_json::json!({ "expired": true })]);
+        assert!(find_expired(&locations, &storage, Utc::now()).await.unwrap().is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,6 +1,8 @@
 use std::process::{ Command, Stdio };
+use std::time::Instant;
 use anyhow::{ Result, Context };
 use crate::load_locations::{ load_locations, Locations };
+use crate::logging::log_command;
 
 pub fn run_command(cmd: &str, current_dir: &str) -> Result<String> {
     let mut dir = current_dir.to_owned();
@@ -12,6 +14,7 @@ pub fn run_command(cmd: &str, current_dir: &str) -> Result<String> {
 
     println!("Command directory: {}\nCommand: {}", dir, cmd);
 
+    let started = Instant::now();
     let command = Command::new("bash")
         .arg("-c")
         .arg(cmd)
@@ -23,6 +26,12 @@ pub fn run_command(cmd: &str, current_dir: &str) -> Result<String> {
 
     let status = command.wait_with_output();
 
+    log_command(
+        cmd,
+        status.as_ref().ok().and_then(|output| output.status.code()),
+        started.elapsed()
+    );
+
     if status.is_err() {
         return Err(anyhow::anyhow!("Failed to run command: {:?}", cmd));
     }
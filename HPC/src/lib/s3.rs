@@ -0,0 +1,464 @@
+use std::path::Path;
+use anyhow::{ Context, Result };
+use chrono::{ DateTime, Utc };
+use reqwest::{ Client, Method, Response, StatusCode };
+use tokio::io::AsyncWriteExt;
+
+use crate::signing::{
+    aws_signature,
+    aws_signing_key,
+    scope,
+    string_to_sign,
+    uri_encode,
+    CanonicalRequest,
+    AWS_ALGORITHM,
+    UNSIGNED_PAYLOAD,
+};
+use crate::storage::{ StorageBackend, StoredObject };
+use crate::tools::BoxFuture;
+
+/*
+    S3-compatible object storage (MinIO, Ceph, AWS ...) through its REST API
+    Requests are signed with Signature V4, see signing.rs, and use path-style URLs,
+    {endpoint}/{bucket}/{key}, which every S3-compatible server supports
+    Tests run against MockS3, see mock_s3.rs
+*/
+
+// as long as S3 allows, like the 7 day GCS links
+pub const LINK_EXPIRY_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct S3Object {
+    pub key: String,
+    pub size: u64,
+    // the MD5 in hex, except for multipart uploads, ex) "...-3"
+    pub etag: String,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+    endpoint: String,
+    // what the Host header will be, it is signed
+    host: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    client: Client,
+}
+
+impl S3Storage {
+    pub fn new(
+        endpoint: &str,
+        region: &str,
+        access_key_id: &str,
+        secret_access_key: &str
+    ) -> Result<S3Storage> {
+        let url = reqwest::Url
+            ::parse(endpoint)
+            .with_context(|| format!("Invalid S3 endpoint {}", endpoint))?;
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_owned(),
+            _ => {
+                return Err(anyhow::anyhow!("S3 endpoint has no host: {}", endpoint));
+            }
+        };
+
+        Ok(S3Storage {
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            host,
+            region: region.to_owned(),
+            access_key_id: access_key_id.to_owned(),
+            secret_access_key: secret_access_key.to_owned(),
+            client: Client::new(),
+        })
+    }
+
+    // s3://bucket/some/key -> (bucket, some/key)
+    pub fn split(location: &str) -> Result<(String, String)> {
+        let path = location
+            .strip_prefix("s3://")
+            .with_context(|| format!("Not an s3:// location: {}", location))?;
+        let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+        Ok((bucket.to_owned(), key.to_owned()))
+    }
+
+    fn path(bucket: &str, key: &str) -> String {
+        if key.is_empty() { format!("/{}", bucket) } else { format!("/{}/{}", bucket, key) }
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        bucket: &str,
+        key: &str,
+        query: Vec<(String, String)>,
+        body: Option<Vec<u8>>,
+        // not signed, ex) Range
+        headers: &[(&str, String)]
+    ) -> Result<Response> {
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let request = CanonicalRequest {
+            method: method.to_string(),
+            path: S3Storage::path(bucket, key),
+            query,
+            headers: vec![
+                ("host".to_string(), self.host.clone()),
+                ("x-amz-content-sha256".to_string(), UNSIGNED_PAYLOAD.to_string()),
+                ("x-amz-date".to_string(), timestamp.clone())
+            ],
+            payload_hash: UNSIGNED_PAYLOAD.to_string(),
+        };
+
+        let scope = scope(&date, &self.region, "s3", "aws4_request");
+        let key = aws_signing_key(&self.secret_access_key, &date, &self.region, "s3")?;
+        let signature = aws_signature(
+            &key,
+            &string_to_sign(AWS_ALGORITHM, &timestamp, &scope, &request)
+        )?;
+        let authorization = format!(
+            "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+            AWS_ALGORITHM,
+            &self.access_key_id,
+            scope,
+            request.signed_headers(),
+            signature
+        );
+
+        let url = self.url(&request);
+        let mut builder = self.client
+            .request(method.clone(), &url)
+            .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .header("x-amz-date", &timestamp)
+            .header("authorization", authorization);
+        if let Some(body) = body {
+            builder = builder.body(body);
+        }
+        for (name, value) in headers {
+            builder = builder.header(*name, value);
+        }
+
+        let response = builder
+            .send().await
+            .with_context(|| format!("S3 request failed. {} {}", method, url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(
+                anyhow::anyhow!(
+                    "S3 request failed. {} {} status={} body={}",
+                    method,
+                    url,
+                    status,
+                    body
+                )
+            );
+        }
+
+        Ok(response)
+    }
+
+    fn url(&self, request: &CanonicalRequest) -> String {
+        let query = request.canonical_query();
+        let path = uri_encode(&request.path, false);
+
+        if query.is_empty() {
+            format!("{}{}", &self.endpoint, path)
+        } else {
+            format!("{}{}?{}", &self.endpoint, path, query)
+        }
+    }
+
+    // every object whose key starts with `prefix`, all pages
+    pub async fn list(&self, bucket: &str, prefix: &str) -> Result<Vec<S3Object>> {
+        let mut objects: Vec<S3Object> = vec![];
+        let mut token: Option<String> = None;
+
+        loop {
+            let mut query = vec![
+                ("list-type".to_string(), "2".to_string()),
+                ("prefix".to_string(), prefix.to_owned())
+            ];
+            if let Some(token) = &token {
+                query.push(("continuation-token".to_string(), token.clone()));
+            }
+
+            let xml = self.send(Method::GET, bucket, "", query, None, &[]).await?.text().await?;
+
+            for contents in xml_tags(&xml, "Contents") {
+                let key = xml_tags(contents, "Key").first().map(|key| xml_unescape(key));
+                let size = xml_tags(contents, "Size")
+                    .first()
+                    .and_then(|size| size.parse::<u64>().ok());
+                let etag = xml_tags(contents, "ETag")
+                    .first()
+                    .map(|etag| xml_unescape(etag).trim_matches('"').to_owned())
+                    .unwrap_or_default();
+                let last_modified = xml_tags(contents, "LastModified")
+                    .first()
+                    .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+                    .map(|time| time.to_utc());
+
+                match (key, size) {
+                    (Some(key), Some(size)) => {
+                        objects.push(S3Object { key, size, etag, last_modified });
+                    }
+                    _ => {
+                        return Err(anyhow::anyhow!("Unexpected S3 listing: {}", contents));
+                    }
+                }
+            }
+
+            let truncated = xml_tags(&xml, "IsTruncated").first() == Some(&"true");
+            token = xml_tags(&xml, "NextContinuationToken")
+                .first()
+                .map(|token| xml_unescape(token));
+
+            if !truncated || token.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
+
+    async fn list_objects(&self, prefix: &str) -> Result<Vec<StoredObject>> {
+        let (bucket, key) = S3Storage::split(prefix)?;
+
+        Ok(
+            self
+                .list(&bucket, &key).await?
+                .into_iter()
+                .map(|object| StoredObject {
+                    location: format!("s3://{}/{}", bucket, object.key),
+                    size: object.size,
+                    md5: (object.etag.len() == 32).then_some(object.etag),
+                    crc32c: None,
+                    updated: object.last_modified,
+                })
+                .collect()
+        )
+    }
+
+    // a partial `to_file` is continued with a Range request
+    async fn fetch_now(&self, location: &str, to_file: &Path) -> Result<()> {
+        let (bucket, key) = S3Storage::split(location)?;
+        let have = tokio::fs::metadata(to_file).await.map_or(0, |metadata| metadata.len());
+
+        let range = [("range", format!("bytes={}-", have))];
+        let headers: &[(&str, String)] = if have > 0 { &range } else { &[] };
+        let mut response = self.send(Method::GET, &bucket, &key, vec![], None, headers).await?;
+
+        // anything but 206 is the whole object
+        let append = response.status() == StatusCode::PARTIAL_CONTENT;
+        let mut file = tokio::fs::OpenOptions
+            ::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(to_file).await
+            .with_context(|| format!("Failed to open {}", to_file.display()))?;
+
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        Ok(())
+    }
+
+    async fn upload_now(&self, from_local: &str, to: &str) -> Result<()> {
+        let (bucket, mut key) = S3Storage::split(to)?;
+        if key.is_empty() || key.ends_with('/') {
+            let file_name = Path::new(from_local).file_name().context("No file name")?;
+            key.push_str(&file_name.to_string_lossy());
+        }
+
+        // TODO stream, reqwest's stream feature conflicts with mail-send's dependencies
+        let body = tokio::fs
+            ::read(from_local).await
+            .with_context(|| format!("Failed to read {}", from_local))?;
+        self.send(Method::PUT, &bucket, &key, vec![], Some(body), &[]).await?;
+
+        Ok(())
+    }
+
+    // a GET link anyone can use until it expires
+    pub fn presign(&self, location: &str, now: DateTime<Utc>, expires: u64) -> Result<String> {
+        let (bucket, key) = S3Storage::split(location)?;
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let scope = scope(&date, &self.region, "s3", "aws4_request");
+
+        let request = CanonicalRequest {
+            method: "GET".to_string(),
+            path: S3Storage::path(&bucket, &key),
+            query: vec![
+                ("X-Amz-Algorithm".to_string(), AWS_ALGORITHM.to_string()),
+                ("X-Amz-Credential".to_string(), format!("{}/{}", &self.access_key_id, scope)),
+                ("X-Amz-Date".to_string(), timestamp.clone()),
+                ("X-Amz-Expires".to_string(), expires.to_string()),
+                ("X-Amz-SignedHeaders".to_string(), "host".to_string())
+            ],
+            headers: vec![("host".to_string(), self.host.clone())],
+            payload_hash: UNSIGNED_PAYLOAD.to_string(),
+        };
+
+        let key = aws_signing_key(&self.secret_access_key, &date, &self.region, "s3")?;
+        let signature = aws_signature(
+            &key,
+            &string_to_sign(AWS_ALGORITHM, &timestamp, &scope, &request)
+        )?;
+
+        Ok(format!("{}&X-Amz-Signature={}", self.url(&request), signature))
+    }
+
+    async fn size(&self, location: &str) -> Result<u64> {
+        let (bucket, key) = S3Storage::split(location)?;
+        // abc123 shouldn't count abc1234
+        let dir = if key.is_empty() || key.ends_with('/') {
+            key.clone()
+        } else {
+            format!("{}/", key)
+        };
+
+        let objects = self.list(&bucket, &key).await?;
+        Ok(
+            objects
+                .iter()
+                .filter(|object| object.key == key || object.key.starts_with(&dir))
+                .map(|object| object.size)
+                .sum()
+        )
+    }
+}
+
+impl StorageBackend for S3Storage {
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<StoredObject>>> {
+        Box::pin(self.list_objects(prefix))
+    }
+
+    fn fetch<'a>(&'a self, location: &'a str, to_file: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.fetch_now(location, to_file))
+    }
+
+    fn upload<'a>(&'a self, from_local: &'a str, to: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(self.upload_now(from_local, to))
+    }
+
+    fn delete<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let (bucket, key) = S3Storage::split(location)?;
+            self.send(Method::DELETE, &bucket, &key, vec![], None, &[]).await?;
+            Ok(())
+        })
+    }
+
+    fn signed_url<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { self.presign(location, Utc::now(), LINK_EXPIRY_SECONDS) })
+    }
+
+    fn du<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(self.size(location))
+    }
+}
+
+// contents of every <tag>...</tag>, S3 listings have no attributes or self-closing tags
+pub(crate) fn xml_tags<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut found: Vec<&str> = vec![];
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        let Some(end) = after.find(&close) else {
+            break;
+        };
+        found.push(&after[..end]);
+        rest = &after[end + close.len()..];
+    }
+
+    found
+}
+
+pub(crate) fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_s3::{ MockS3, MOCK_ACCESS_KEY, MOCK_SECRET_KEY };
+
+    #[test]
+    fn test_xml() {
+        let xml =
+            "<ListBucketResult><Contents><Key>a&amp;b</Key><Size>4</Size></Contents>\
+             <Contents><Key>c</Key><Size>1</Size></Contents></ListBucketResult>";
+        let contents = xml_tags(xml, "Contents");
+
+        assert_eq!(contents.len(), 2);
+        assert_eq!(xml_unescape(xml_tags(contents[0], "Key")[0]), "a&b");
+        assert!(xml_tags(xml, "NextContinuationToken").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_s3_storage() {
+        let s3 = MockS3::start().await.unwrap();
+        s3.set_page_size(2);
+        let storage = S3Storage::new(&s3.endpoint(), "us-east-1", MOCK_ACCESS_KEY, MOCK_SECRET_KEY)
+            .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("s3_storage_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let results = dir.join("results 1.zip");
+        std::fs::write(&results, "zipped").unwrap();
+
+        storage
+            .upload(&results.display().to_string(), "s3://bucket/ogv/abc123/").await
+            .unwrap();
+        assert_eq!(s3.object("bucket/ogv/abc123/results 1.zip").unwrap(), b"zipped");
+
+        s3.put("bucket/ogv/abc123/a.fasta", b">a\nACGT\n");
+        s3.put("bucket/ogv/abc123/lib1/b.fasta", b">b\nAC\n");
+        s3.put("bucket/ogv/abc1234/other.fasta", b">c\n");
+        assert_eq!(storage.du("s3://bucket/ogv/abc123").await.unwrap(), 6 + 8 + 6);
+
+        let objects = storage.list_objects("s3://bucket/ogv/abc123/").await.unwrap();
+        assert_eq!(objects.len(), 3);
+        assert_eq!(objects[1].location, "s3://bucket/ogv/abc123/lib1/b.fasta");
+        assert_eq!(objects[1].md5.as_deref(), Some(crate::mock_s3::md5_hex(b">b\nAC\n").as_str()));
+
+        // a partial download is continued with a Range request
+        let partial = dir.join("b.fasta");
+        std::fs::write(&partial, ">b\n").unwrap();
+        storage.fetch(&objects[1].location, &partial).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&partial).unwrap(), ">b\nAC\n");
+
+        let link = storage.signed_url("s3://bucket/ogv/abc123/results 1.zip").await.unwrap();
+        assert!(link.contains("/bucket/ogv/abc123/results%201.zip?X-Amz-Algorithm="));
+        assert_eq!(reqwest::get(&link).await.unwrap().text().await.unwrap(), "zipped");
+
+        let old = "2026-01-02T03:04:05Z".parse::<DateTime<Utc>>().unwrap();
+        s3.set_modified("bucket/ogv/abc123/a.fasta", old);
+        let objects = storage.list_objects("s3://bucket/ogv/abc123/a").await.unwrap();
+        assert_eq!(objects[0].updated, Some(old));
+        storage.delete("s3://bucket/ogv/abc123/a.fasta").await.unwrap();
+        assert!(s3.object("bucket/ogv/abc123/a.fasta").is_none());
+
+        let wrong = S3Storage::new(&s3.endpoint(), "us-east-1", MOCK_ACCESS_KEY, "wrong").unwrap();
+        let error = wrong.du("s3://bucket/ogv/abc123").await.unwrap_err();
+        assert!(error.to_string().contains("status=403"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,836 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::{ Arc, Mutex };
+use anyhow::{ Context, Result };
+use serde::{ Deserialize, Serialize };
+use tokio::sync::{ Notify, Semaphore };
+use tokio::task::JoinHandle;
+
+use crate::load_locations::Locations;
+use crate::tools::BoxFuture;
+
+/*
+    Backends used by bin/process_queue to start pipeline binaries
+    Selected with `scheduler` in locations.json:
+        { "type": "slurm" }                 sbatch on the HPC (default)
+        { "type": "local", "max_jobs": 2 }  child processes on this machine
+        { "type": "dry_run" }               print what would be submitted
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SchedulerConfig {
+    #[default]
+    Slurm,
+    Local {
+        max_jobs: usize,
+    },
+    DryRun,
+}
+
+#[derive(Debug, Clone)]
+pub struct JobSpec {
+    pub cmd: String,
+    pub cores: u8,
+    // MB
+    pub memory: u32,
+    // minutes
+    pub time: u32,
+    pub job_name: String,
+    pub output_file: String,
+}
+
+impl JobSpec {
+    pub fn resources(&self) -> JobResources {
+        JobResources { cores: self.cores, memory: self.memory, time: self.time }
+    }
+}
+
+// what a job was submitted with, saved on the submission as `resources`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct JobResources {
+    pub cores: u8,
+    pub memory: u32,
+    pub time: u32,
+}
+
+/*
+    Resubmission of jobs the scheduler killed for memory or time
+    `retry` in locations.json, any missing key uses the default:
+        { "max_attempts": 3, "memory_multiplier": 2.0, "time_multiplier": 2.0,
+          "max_memory": 500000, "max_time": 10080 }
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RetryConfig {
+    // including the first run
+    pub max_attempts: u32,
+    pub memory_multiplier: f32,
+    pub time_multiplier: f32,
+    // MB
+    pub max_memory: u32,
+    // minutes
+    pub max_time: u32,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_attempts: 3,
+            memory_multiplier: 2.0,
+            time_multiplier: 2.0,
+            max_memory: 500000,
+            max_time: 10080,
+        }
+    }
+}
+
+impl RetryConfig {
+    // resources for the next attempt, None when the failure isn't retryable or nothing can grow
+    pub fn escalate(
+        &self,
+        resources: &JobResources,
+        state: &JobState,
+        attempts: u32
+    ) -> Option<JobResources> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+
+        let next = match state {
+            JobState::OutOfMemory =>
+                JobResources {
+                    memory: scale(resources.memory, self.memory_multiplier, self.max_memory),
+                    ..*resources
+                },
+            JobState::Timeout =>
+                JobResources {
+                    time: scale(resources.time, self.time_multiplier, self.max_time),
+                    ..*resources
+                },
+            _ => {
+                return None;
+            }
+        };
+
+        // already at the cap, another attempt would fail the same way
+        if next == *resources {
+            return None;
+        }
+
+        Some(next)
+    }
+}
+
+fn scale(value: u32, multiplier: f32, max: u32) -> u32 {
+    ((value as f32) * multiplier).ceil().min(max as f32).max(value as f32) as u32
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+    OutOfMemory,
+    Timeout,
+    Cancelled,
+    Other(String),
+}
+
+impl JobState {
+    pub fn from_slurm(state: &str) -> JobState {
+        // sacct can append details, ex) "CANCELLED by 1234"
+        match state.split_whitespace().next().unwrap_or("") {
+            "PENDING" | "REQUEUED" => JobState::Pending,
+            "RUNNING" | "COMPLETING" | "CONFIGURING" => JobState::Running,
+            "COMPLETED" => JobState::Completed,
+            "FAILED" | "NODE_FAIL" | "BOOT_FAIL" => JobState::Failed,
+            "OUT_OF_MEMORY" => JobState::OutOfMemory,
+            "TIMEOUT" | "DEADLINE" => JobState::Timeout,
+            "CANCELLED" => JobState::Cancelled,
+            other => JobState::Other(other.to_string()),
+        }
+    }
+
+    // value PATCHed to the API as schedulerState
+    pub fn as_str(&self) -> &str {
+        match self {
+            JobState::Pending => "PENDING",
+            JobState::Running => "RUNNING",
+            JobState::Completed => "COMPLETED",
+            JobState::Failed => "FAILED",
+            JobState::OutOfMemory => "OUT_OF_MEMORY",
+            JobState::Timeout => "TIMEOUT",
+            JobState::Cancelled => "CANCELLED",
+            JobState::Other(state) => state,
+        }
+    }
+
+    pub fn is_failure(&self) -> bool {
+        matches!(self, JobState::Failed | JobState::OutOfMemory | JobState::Timeout)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobStatus {
+    pub job_id: String,
+    pub state: JobState,
+}
+
+// what a finished job actually used, as reported by sacct
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobUsage {
+    pub state: String,
+    // MB, highest MaxRSS of any step
+    pub memory: u32,
+    // minutes
+    pub time: u32,
+}
+
+// how far back jobs are looked up in the scheduler's accounting
+pub const ACCOUNTING_DAYS: i64 = 14;
+
+pub trait Scheduler {
+    // returns the scheduler's job id when the backend has one
+    fn submit(&self, job: &JobSpec) -> Result<Option<String>>;
+
+    // stop a submitted job, job_id is the one returned by submit if it was saved
+    fn cancel(&self, job_name: &str, job_id: Option<&str>) -> Result<()>;
+
+    // latest status per job name, backends without a queue to ask return nothing
+    fn job_states(&self, _job_names: &[String]) -> Result<HashMap<String, JobStatus>> {
+        Ok(HashMap::new())
+    }
+
+    // usage of finished jobs by job id, running and unknown jobs are left out
+    fn job_usage(&self, _job_ids: &[String]) -> Result<HashMap<String, JobUsage>> {
+        Ok(HashMap::new())
+    }
+
+    // until submitted jobs are done, only meaningful for backends that own the processes
+    fn wait(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    // callers skip receipts and API patches when nothing was actually submitted
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+pub struct SlurmScheduler {
+    admin_email: String,
+    base: String,
+}
+
+impl SlurmScheduler {
+    pub fn new(admin_email: &str, base: &str) -> SlurmScheduler {
+        SlurmScheduler {
+            admin_email: admin_email.to_owned(),
+            base: base.to_owned(),
+        }
+    }
+
+    pub fn sbatch_cmd(&self, job: &JobSpec) -> String {
+        let slurm_error_handling = format!("--mail-type=FAIL --mail-user={}", &self.admin_email);
+
+        format!(
+            "sbatch {} -o {} --cpus-per-task={} --job-name='{}' --mem={} -t {} --wrap='{}'",
+            slurm_error_handling,
+            &job.output_file,
+            job.cores,
+            &job.job_name,
+            job.memory,
+            job.time,
+            &job.cmd
+        )
+    }
+}
+
+impl Scheduler for SlurmScheduler {
+    fn submit(&self, job: &JobSpec) -> Result<Option<String>> {
+        let sbatch_command = self.sbatch_cmd(job);
+
+        // keep the last sbatch command at {}/sbatch_commands.txt for debugging
+        let sbatch_commands_file = format!("{}/sbatch_commands.txt", &self.base);
+        let contents = format!("{}\n\n", &sbatch_command);
+        std::fs::write(&sbatch_commands_file, contents).unwrap_or_else(|e| {
+            println!("Error writing sbatch command to file: {:?}", e);
+        });
+
+        let output = run_captured(&sbatch_command, &self.base).with_context(||
+            format!("Failed to submit {} to Slurm.", &job.job_name)
+        )?;
+
+        let job_id = parse_job_id(&output);
+        if job_id.is_none() {
+            println!("Failed to find Slurm job ID in sbatch output: {:?}", &output);
+        }
+
+        Ok(job_id)
+    }
+
+    fn job_states(&self, job_names: &[String]) -> Result<HashMap<String, JobStatus>> {
+        if job_names.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        // -X skips job steps, sacct only reports from midnight unless given a start time
+        let sacct_command = format!(
+            "sacct -n -P -X -S now-{}days --name={} --format=JobName,JobID,State",
+            ACCOUNTING_DAYS,
+            job_names.join(",")
+        );
+        let output = run_captured(&sacct_command, &self.base).context("Failed to run sacct.")?;
+
+        Ok(parse_sacct(&output))
+    }
+
+    fn job_usage(&self, job_ids: &[String]) -> Result<HashMap<String, JobUsage>> {
+        if job_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        // without -X, MaxRSS is only reported on the steps
+        let sacct_command = format!(
+            "sacct -n -P -j {} --format=JobID,State,MaxRSS,Elapsed",
+            job_ids.join(",")
+        );
+        let output = run_captured(&sacct_command, &self.base).context("Failed to run sacct.")?;
+
+        Ok(parse_sacct_usage(&output))
+    }
+
+    fn cancel(&self, job_name: &str, _job_id: Option<&str>) -> Result<()> {
+        // by name so jobs submitted before their ID was saved are found too
+        let scancel_command = format!("scancel --name={}", job_name);
+        run_captured(&scancel_command, &self.base).with_context(||
+            format!("Failed to scancel {}.", job_name)
+        )?;
+        Ok(())
+    }
+}
+
+// sbatch prints "Submitted batch job 12345"
+pub fn parse_job_id(sbatch_output: &str) -> Option<String> {
+    sbatch_output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Submitted batch job "))
+        .map(|job_id| job_id.trim().to_string())
+        .filter(|job_id| !job_id.is_empty())
+}
+
+// sacct -P lines are "JobName|JobID|State", keep the newest job for each name
+pub fn parse_sacct(sacct_output: &str) -> HashMap<String, JobStatus> {
+    let mut states: HashMap<String, JobStatus> = HashMap::new();
+
+    for line in sacct_output.lines() {
+        let columns: Vec<&str> = line.trim().split('|').collect();
+        if columns.len() < 3 || columns[0].is_empty() {
+            continue;
+        }
+
+        let status = JobStatus {
+            job_id: columns[1].to_string(),
+            state: JobState::from_slurm(columns[2]),
+        };
+
+        let is_newer = match states.get(columns[0]) {
+            Some(existing) => numeric_job_id(&status.job_id) > numeric_job_id(&existing.job_id),
+            None => true,
+        };
+
+        if is_newer {
+            states.insert(columns[0].to_string(), status);
+        }
+    }
+
+    states
+}
+
+// sacct -P lines are "JobID|State|MaxRSS|Elapsed", one for the job and one per step (12345.batch)
+pub fn parse_sacct_usage(sacct_output: &str) -> HashMap<String, JobUsage> {
+    let mut usage: HashMap<String, JobUsage> = HashMap::new();
+    let mut step_memory: HashMap<String, u32> = HashMap::new();
+
+    for line in sacct_output.lines() {
+        let columns: Vec<&str> = line.trim().split('|').collect();
+        if columns.len() < 4 || columns[0].is_empty() {
+            continue;
+        }
+
+        let memory = parse_max_rss(columns[2]);
+
+        match columns[0].split_once('.') {
+            Some((job_id, _)) => {
+                let max = step_memory.entry(job_id.to_string()).or_insert(0);
+                *max = (*max).max(memory);
+            }
+            None => {
+                let state = JobState::from_slurm(columns[1]);
+                if matches!(state, JobState::Pending | JobState::Running) {
+                    continue;
+                }
+
+                usage.insert(columns[0].to_string(), JobUsage {
+                    state: state.as_str().to_string(),
+                    memory,
+                    time: parse_elapsed(columns[3]),
+                });
+            }
+        }
+    }
+
+    for (job_id, job_usage) in usage.iter_mut() {
+        if let Some(memory) = step_memory.get(job_id) {
+            job_usage.memory = job_usage.memory.max(*memory);
+        }
+    }
+
+    usage
+}
+
+// MaxRSS as MB, ex) "2048K", "1.5G", plain numbers are bytes
+fn parse_max_rss(max_rss: &str) -> u32 {
+    let max_rss = max_rss.trim();
+    let (number, unit_mb) = match max_rss.chars().last() {
+        Some('K') => (&max_rss[..max_rss.len() - 1], 1.0 / 1024.0),
+        Some('M') => (&max_rss[..max_rss.len() - 1], 1.0),
+        Some('G') => (&max_rss[..max_rss.len() - 1], 1024.0),
+        Some('T') => (&max_rss[..max_rss.len() - 1], 1024.0 * 1024.0),
+        _ => (max_rss, 1.0 / (1024.0 * 1024.0)),
+    };
+
+    number
+        .parse::<f64>()
+        .map(|n| (n * unit_mb).ceil() as u32)
+        .unwrap_or(0)
+}
+
+// Elapsed as minutes, ex) "1-02:03:04", "02:03:04", "03:04"
+fn parse_elapsed(elapsed: &str) -> u32 {
+    let (days, clock) = match elapsed.trim().split_once('-') {
+        Some((days, clock)) => (days.parse::<u32>().unwrap_or(0), clock),
+        None => (0, elapsed.trim()),
+    };
+
+    let seconds = clock
+        .split(':')
+        .fold(0, |total, part| total * 60 + part.parse::<u32>().unwrap_or(0));
+
+    days * 1440 + seconds.div_ceil(60)
+}
+
+fn numeric_job_id(job_id: &str) -> u64 {
+    job_id
+        .split(|c: char| !c.is_ascii_digit())
+        .next()
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(0)
+}
+
+// run_command streams stdout to the terminal, scheduler commands need it returned
+fn run_captured(cmd: &str, current_dir: &str) -> Result<String> {
+    println!("Command directory: {}\nCommand: {}", current_dir, cmd);
+
+    let output = Command::new("bash")
+        .arg("-c")
+        .arg(cmd)
+        .current_dir(current_dir)
+        .output()
+        .with_context(|| format!("Failed running command:\n{}", cmd))?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+
+    if !output.status.success() {
+        return Err(
+            anyhow::anyhow!(
+                "Command exited with {}: {}\n{}",
+                output.status,
+                cmd,
+                String::from_utf8_lossy(&output.stderr)
+            )
+        );
+    }
+
+    println!("Command output: {}", stdout);
+
+    Ok(stdout)
+}
+
+// jobs are children of process_queue, queued until one of max_jobs slots is free
+// job ids are only known to the process_queue that started them, ex) "local-4242-0"
+pub struct LocalScheduler {
+    base: String,
+    slots: Arc<Semaphore>,
+    // what cancel notifies to stop a job, removed once it ends
+    jobs: Arc<Mutex<HashMap<String, Arc<Notify>>>>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    next_id: AtomicUsize,
+}
+
+impl LocalScheduler {
+    pub fn new(base: &str, max_jobs: usize) -> LocalScheduler {
+        LocalScheduler {
+            base: base.to_owned(),
+            slots: Arc::new(Semaphore::new(std::cmp::max(max_jobs, 1))),
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            tasks: Mutex::new(vec![]),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    async fn run(
+        base: String,
+        job: JobSpec,
+        output: File,
+        slots: Arc<Semaphore>,
+        cancel: Arc<Notify>
+    ) -> Result<()> {
+        // cancelled while still queued, checked first in case the slot is free by now too
+        let _slot = tokio::select! {
+            biased;
+            _ = cancel.notified() => {
+                return Ok(());
+            }
+            slot = slots.acquire_owned() => slot?,
+        };
+
+        println!("Starting local job {}: {}", &job.job_name, &job.cmd);
+
+        let mut child = tokio::process::Command
+            ::new("bash")
+            .arg("-c")
+            .arg(&job.cmd)
+            .current_dir(&base)
+            .stdout(output.try_clone()?)
+            .stderr(output)
+            .spawn()
+            .with_context(|| format!("Failed to start local job: {}", &job.job_name))?;
+
+        tokio::select! {
+            status = child.wait() => {
+                status.with_context(|| format!("Failed waiting for local job {}.", &job.job_name))?;
+            }
+            _ = cancel.notified() => {
+                child
+                    .kill().await
+                    .with_context(|| format!("Failed to kill local job {}.", &job.job_name))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Scheduler for LocalScheduler {
+    fn submit(&self, job: &JobSpec) -> Result<Option<String>> {
+        let runtime = tokio::runtime::Handle
+            ::try_current()
+            .context("Local jobs need to be submitted from the tokio runtime.")?;
+
+        if let Some(parent) = Path::new(&job.output_file).parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        let output = File::create(&job.output_file).with_context(|| {
+            format!("Failed to create output file: {}", &job.output_file)
+        })?;
+
+        let job_id = format!(
+            "local-{}-{}",
+            std::process::id(),
+            self.next_id.fetch_add(1, Ordering::SeqCst)
+        );
+        let cancel = Arc::new(Notify::new());
+        self.jobs.lock().unwrap().insert(job_id.clone(), cancel.clone());
+
+        println!("Queued local job {} as {}", &job.job_name, &job_id);
+
+        let (base, job, slots, jobs, id) = (
+            self.base.clone(),
+            job.clone(),
+            self.slots.clone(),
+            self.jobs.clone(),
+            job_id.clone(),
+        );
+        let task = runtime.spawn(async move {
+            if let Err(e) = LocalScheduler::run(base, job, output, slots, cancel).await {
+                println!("Local job {} failed: {:?}", &id, e);
+            }
+            jobs.lock().unwrap().remove(&id);
+        });
+
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(task);
+
+        Ok(Some(job_id))
+    }
+
+    fn cancel(&self, job_name: &str, job_id: Option<&str>) -> Result<()> {
+        let Some(job_id) = job_id else {
+            return Err(anyhow::anyhow!("No job ID saved for local job {}.", job_name));
+        };
+
+        // only this process's own children, anything else may not be the job anymore
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(cancel) => {
+                cancel.notify_one();
+                Ok(())
+            }
+            None =>
+                Err(
+                    anyhow::anyhow!(
+                        "Local job {} ({}) has ended or was started by another process_queue.",
+                        job_name,
+                        job_id
+                    )
+                ),
+        }
+    }
+
+    fn wait(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let tasks: Vec<JoinHandle<()>> = self.tasks.lock().unwrap().drain(..).collect();
+            for task in tasks {
+                task.await.context("Failed waiting for local job.")?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct DryRunScheduler {
+    submitted: Mutex<Vec<JobSpec>>,
+}
+
+impl DryRunScheduler {
+    pub fn new() -> DryRunScheduler {
+        DryRunScheduler::default()
+    }
+
+    pub fn submitted(&self) -> Vec<JobSpec> {
+        self.submitted.lock().unwrap().clone()
+    }
+}
+
+impl Scheduler for DryRunScheduler {
+    fn submit(&self, job: &JobSpec) -> Result<Option<String>> {
+        let summary = format!(
+            "Dry run: {} cores={} memory={}MB time={}min output={}\n{}",
+            &job.job_name,
+            job.cores,
+            job.memory,
+            job.time,
+            &job.output_file,
+            &job.cmd
+        );
+        println!("{}", &summary);
+        self.submitted.lock().unwrap().push(job.clone());
+        Ok(None)
+    }
+
+    fn cancel(&self, job_name: &str, _job_id: Option<&str>) -> Result<()> {
+        println!("Dry run: cancel {}", job_name);
+        Ok(())
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+pub fn load_scheduler(locations: &Locations, is_dev: bool) -> Box<dyn Scheduler> {
+    match &locations.scheduler {
+        // no Slurm in dev, run jobs one at a time
+        SchedulerConfig::Slurm if is_dev => Box::new(LocalScheduler::new(&locations.base, 1)),
+        SchedulerConfig::Slurm =>
+            Box::new(SlurmScheduler::new(&locations.admin_email, &locations.base)),
+        SchedulerConfig::Local { max_jobs } =>
+            Box::new(LocalScheduler::new(&locations.base, *max_jobs)),
+        SchedulerConfig::DryRun => Box::new(DryRunScheduler::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(cmd: &str, output_file: &str) -> JobSpec {
+        JobSpec {
+            cmd: cmd.to_string(),
+            cores: 2,
+            memory: 5000,
+            time: 1440,
+            job_name: "coreceptor-abc123".to_string(),
+            output_file: output_file.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_sbatch_cmd() {
+        let scheduler = SlurmScheduler::new("admin@uni.edu", "/app");
+        let cmd = scheduler.sbatch_cmd(&job("/bin/coreceptor --id=abc123", "/log/abc123.out"));
+
+        assert_eq!(
+            cmd,
+            "sbatch --mail-type=FAIL --mail-user=admin@uni.edu -o /log/abc123.out --cpus-per-task=2 --job-name='coreceptor-abc123' --mem=5000 -t 1440 --wrap='/bin/coreceptor --id=abc123'"
+        );
+    }
+
+    #[test]
+    fn test_dry_run_records_jobs() {
+        let scheduler = DryRunScheduler::new();
+        let job_id = scheduler.submit(&job("/bin/coreceptor --id=abc123", "/log/abc123.out")).unwrap();
+
+        assert!(scheduler.is_dry_run());
+        assert_eq!(job_id, None);
+        assert_eq!(scheduler.submitted()[0].job_name, "coreceptor-abc123");
+    }
+
+    #[tokio::test]
+    async fn test_local_runs_jobs() {
+        let dir = std::env::temp_dir().join(format!("scheduler-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.display().to_string();
+
+        let scheduler = LocalScheduler::new(&base, 1);
+        for i in 0..3 {
+            let output_file = format!("{}/logs/{}.out", &base, i);
+            scheduler.submit(&job(&format!("echo job-{}", i), &output_file)).unwrap();
+        }
+        scheduler.wait().await.unwrap();
+
+        for i in 0..3 {
+            let output = std::fs::read_to_string(format!("{}/logs/{}.out", &base, i)).unwrap();
+            assert_eq!(output.trim(), format!("job-{}", i));
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_local_cancels_jobs() {
+        let dir = std::env::temp_dir().join(format!("scheduler-cancel-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.display().to_string();
+
+        let scheduler = LocalScheduler::new(&base, 1);
+        let running = scheduler
+            .submit(&job("sleep 30", &format!("{}/sleep.out", &base)))
+            .unwrap()
+            .unwrap();
+        // waits for the slot running holds
+        let queued = scheduler
+            .submit(&job("echo queued", &format!("{}/queued.out", &base)))
+            .unwrap()
+            .unwrap();
+
+        assert!(scheduler.cancel("coreceptor-abc123", None).is_err());
+        // a PID saved by an earlier run is never killed
+        assert!(scheduler.cancel("coreceptor-abc123", Some("1")).is_err());
+        scheduler.cancel("coreceptor-abc123", Some(&queued)).unwrap();
+        scheduler.cancel("coreceptor-abc123", Some(&running)).unwrap();
+
+        let started = std::time::Instant::now();
+        scheduler.wait().await.unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(10));
+        assert_eq!(std::fs::read_to_string(format!("{}/queued.out", &base)).unwrap(), "");
+        assert!(scheduler.cancel("coreceptor-abc123", Some(&running)).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_job_id() {
+        assert_eq!(parse_job_id("Submitted batch job 123456\n"), Some("123456".to_string()));
+        assert_eq!(parse_job_id("sbatch: error: Batch job submission failed"), None);
+        assert_eq!(parse_job_id(""), None);
+    }
+
+    #[test]
+    fn test_parse_sacct() {
+        let output =
+            "tcs-abc|900|OUT_OF_MEMORY\ntcs-abc|1001|RUNNING\nogv-def|950|CANCELLED by 1234\nintact-ghi|960|TIMEOUT\n";
+
+        let states = parse_sacct(output);
+
+        assert_eq!(states.len(), 3);
+        assert_eq!(states["tcs-abc"].job_id, "1001");
+        assert_eq!(states["tcs-abc"].state, JobState::Running);
+        assert_eq!(states["ogv-def"].state, JobState::Cancelled);
+        assert_eq!(states["intact-ghi"].state, JobState::Timeout);
+        assert!(states["intact-ghi"].state.is_failure());
+    }
+
+    #[test]
+    fn test_parse_sacct_usage() {
+        let output = [
+            "100|COMPLETED||01:30:10",
+            "100.batch|COMPLETED|2097152K|01:30:10",
+            "100.extern|COMPLETED|0|01:30:10",
+            "101|OUT_OF_MEMORY||1-00:00:00",
+            "101.batch|OUT_OF_MEMORY|1.5G|1-00:00:00",
+            "102|RUNNING||00:05:00",
+        ].join("\n");
+        let usage = parse_sacct_usage(&output);
+
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage["100"], JobUsage {
+            state: "COMPLETED".to_string(),
+            memory: 2048,
+            time: 91,
+        });
+        assert_eq!(usage["101"].memory, 1536);
+        assert_eq!(usage["101"].time, 1440);
+        assert_eq!(usage["101"].state, "OUT_OF_MEMORY");
+    }
+
+    #[test]
+    fn test_job_state_from_slurm() {
+        assert_eq!(JobState::from_slurm("PENDING"), JobState::Pending);
+        assert_eq!(JobState::from_slurm("OUT_OF_MEMORY").as_str(), "OUT_OF_MEMORY");
+        assert_eq!(JobState::from_slurm("SUSPENDED"), JobState::Other("SUSPENDED".to_string()));
+    }
+
+    #[test]
+    fn test_retry_escalation() {
+        let retry = RetryConfig { max_memory: 30000, ..RetryConfig::default() };
+        let resources = JobResources { cores: 4, memory: 20000, time: 1440 };
+
+        let oom = retry.escalate(&resources, &JobState::OutOfMemory, 1).unwrap();
+        assert_eq!(oom, JobResources { memory: 30000, ..resources });
+
+        let timeout = retry.escalate(&resources, &JobState::Timeout, 1).unwrap();
+        assert_eq!(timeout, JobResources { time: 2880, ..resources });
+
+        // capped, out of attempts, or not a resource failure
+        assert_eq!(retry.escalate(&oom, &JobState::OutOfMemory, 2), None);
+        assert_eq!(retry.escalate(&resources, &JobState::Timeout, 3), None);
+        assert_eq!(retry.escalate(&resources, &JobState::Failed, 1), None);
+    }
+
+    #[test]
+    fn test_retry_config_defaults() {
+        let retry: RetryConfig = serde_json::from_str(r#"{ "max_attempts": 5 }"#).unwrap();
+
+        assert_eq!(retry, RetryConfig { max_attempts: 5, ..RetryConfig::default() });
+    }
+
+    #[test]
+    fn test_scheduler_config() {
+        let local: SchedulerConfig = serde_json
+            ::from_str(r#"{ "type": "local", "max_jobs": 3 }"#)
+            .unwrap();
+        let dry_run: SchedulerConfig = serde_json::from_str(r#"{ "type": "dry_run" }"#).unwrap();
+
+        assert_eq!(local, SchedulerConfig::Local { max_jobs: 3 });
+        assert_eq!(dry_run, SchedulerConfig::DryRun);
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use anyhow::{ Context, Result };
+use chrono::NaiveDate;
+use serde::{ Deserialize, Serialize };
+
+/*
+    Credentials are looked up by name, the first found wins
+        1. PRIMER_ID_{NAME}, ex) PRIMER_ID_SMTP_PASSWORD
+        2. PRIMER_ID_{NAME}_FILE, a file holding only the secret
+        3. the secrets file, a JSON object of name to secret
+            { "api_key": "...", "api_key_next": "...", "api_key_created": "2026-01-31",
+              "smtp_username": "...", "smtp_password": "..." }
+        4. the same key in locations.json
+    Files holding secrets must not be readable by other users, startup fails if they are
+    Tuned under `secrets` in locations.json:
+        { "file": "/etc/primer_id/secrets.json", "max_key_age_days": 90 }
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct SecretsConfig {
+    pub file: Option<String>,
+    // rotate the API key once it is older, see Locations::api_key_age_warning
+    pub max_key_age_days: i64,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        SecretsConfig { file: None, max_key_age_days: 90 }
+    }
+}
+
+pub fn env_name(name: &str) -> String {
+    format!("PRIMER_ID_{}", name.to_uppercase())
+}
+
+// errors when other users could read the file
+pub fn check_permissions(path: &Path) -> Result<()> {
+    let mode = std::fs
+        ::metadata(path)
+        .with_context(|| format!("Failed to read permissions of {}.", path.display()))?
+        .permissions()
+        .mode();
+
+    if mode & 0o004 != 0 {
+        return Err(
+            anyhow::anyhow!(
+                "{} holds secrets but is world-readable (mode {:o}), run: chmod 600 {}",
+                path.display(),
+                mode & 0o777,
+                path.display()
+            )
+        );
+    }
+
+    Ok(())
+}
+
+pub fn read_secret_file(path: &Path) -> Result<String> {
+    check_permissions(path)?;
+    let secret = std::fs
+        ::read_to_string(path)
+        .with_context(|| format!("Failed to read secret file {}.", path.display()))?;
+    Ok(secret.trim().to_owned())
+}
+
+pub fn read_secrets_file(path: &Path) -> Result<HashMap<String, String>> {
+    check_permissions(path)?;
+    let contents = std::fs
+        ::read_to_string(path)
+        .with_context(|| format!("Failed to read secrets file {}.", path.display()))?;
+    serde_json
+        ::from_str(&contents)
+        .with_context(|| format!("Failed to parse secrets file {}.", path.display()))
+}
+
+// None when nowhere but locations.json has it, `var` reads the environment
+pub fn lookup(
+    name: &str,
+    var: &impl Fn(&str) -> Option<String>,
+    secrets_file: &HashMap<String, String>
+) -> Result<Option<String>> {
+    let env = env_name(name);
+
+    if let Some(secret) = var(&env).filter(|secret| !secret.is_empty()) {
+        return Ok(Some(secret));
+    }
+
+    if let Some(file) = var(&format!("{}_FILE", env)).filter(|file| !file.is_empty()) {
+        return read_secret_file(Path::new(&file)).map(Some);
+    }
+
+    Ok(secrets_file.get(name).filter(|secret| !secret.is_empty()).cloned())
+}
+
+pub fn key_age_days(created: &str, today: NaiveDate) -> Result<i64> {
+    let created = NaiveDate::parse_from_str(created, "%Y-%m-%d").with_context(|| {
+        format!("api_key_created is not a YYYY-MM-DD date: {:?}", created)
+    })?;
+    Ok((today - created).num_days())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let dir = std::env::temp_dir().join(format!("secrets_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let key_file = dir.join("smtp_password");
+        std::fs::write(&key_file, "from-file\n").unwrap();
+        std::fs::set_permissions(&key_file, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+        let env = HashMap::from([
+            ("PRIMER_ID_API_KEY".to_string(), "from-env".to_string()),
+            ("PRIMER_ID_SMTP_PASSWORD_FILE".to_string(), key_file.display().to_string()),
+        ]);
+        let var = |name: &str| env.get(name).cloned();
+        let secrets_file = HashMap::from([
+            ("api_key".to_string(), "from-secrets".to_string()),
+            ("smtp_username".to_string(), "hpc".to_string()),
+        ]);
+
+        assert_eq!(lookup("api_key", &var, &secrets_file).unwrap().unwrap(), "from-env");
+        assert_eq!(lookup("smtp_password", &var, &secrets_file).unwrap().unwrap(), "from-file");
+        assert_eq!(lookup("smtp_username", &var, &secrets_file).unwrap().unwrap(), "hpc");
+        assert_eq!(lookup("api_key_next", &var, &secrets_file).unwrap(), None);
+
+        std::fs::set_permissions(&key_file, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let error = lookup("smtp_password", &var, &secrets_file).unwrap_err().to_string();
+        assert!(error.contains("world-readable (mode 644)"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_key_age_days() {
+        let today = NaiveDate::from_ymd_opt(2026, 4, 1).unwrap();
+        assert_eq!(key_age_days("2026-01-01", today).unwrap(), 90);
+        assert!(key_age_days("January", today).is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::time::Duration;
 use anyhow::{ Result, Context };
 use mail_send::{ mail_builder::MessageBuilder, SmtpClientBuilder };
 use crate::{
@@ -13,9 +14,8 @@ pub async fn send_email(
 ) -> Result<()> {
     let EnvVars { is_dev, .. } = load_env_vars();
 
-    let Locations { admin_email, smtp_address, smtp_port, .. } = load_locations().expect(
-        "Error loading locations."
-    );
+    let locations = load_locations()?;
+    let admin_email = &locations.admin_email;
 
     if is_dev {
         println!("Email: {} - {}", subject, body);
@@ -25,7 +25,7 @@ pub async fn send_email(
     let mut to = vec![to_email];
 
     if include_admin {
-        to.push(&admin_email);
+        to.push(admin_email);
     }
 
     let body_html: &str = &body.replace("\n", "<br>");
@@ -37,8 +37,7 @@ pub async fn send_email(
         .html_body(body_html);
     // .text_body("Hello world!");
 
-    SmtpClientBuilder::new(smtp_address, smtp_port)
-        .implicit_tls(false)
+    smtp_client(&locations)
         .connect().await
         .context("Failed to connect to SMTP server.")?
         .send(message).await
@@ -46,3 +45,26 @@ pub async fn send_email(
 
     Ok(())
 }
+
+// connects, authenticates and hangs up without sending anything
+pub async fn check_smtp(locations: &Locations, timeout: Duration) -> Result<()> {
+    smtp_client(locations)
+        .timeout(timeout)
+        .connect().await
+        .context("Failed to connect to SMTP server.")?
+        .quit().await
+        .context("Failed to close SMTP connection.")?;
+
+    Ok(())
+}
+
+// SMTP AUTH when both credentials are set
+fn smtp_client(locations: &Locations) -> SmtpClientBuilder<String> {
+    let smtp = SmtpClientBuilder::new(locations.smtp_address.clone(), locations.smtp_port)
+        .implicit_tls(false);
+
+    match (&locations.smtp_username, &locations.smtp_password) {
+        (Some(username), Some(password)) => smtp.credentials((username.clone(), password.clone())),
+        _ => smtp,
+    }
+}
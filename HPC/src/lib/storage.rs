@@ -0,0 +1,452 @@
+use std::fmt::Debug;
+use std::fs::{ File, OpenOptions };
+use std::io::{ Seek, SeekFrom };
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use anyhow::{ Context, Result };
+use chrono::{ DateTime, Utc };
+use glob::glob;
+use serde::{ Deserialize, Serialize };
+
+use crate::cloud_storage::{ UrlSigner, MAX_LINK_EXPIRY_DAYS };
+use crate::resource_estimate::dir_size;
+use crate::s3::{ S3Storage, LINK_EXPIRY_SECONDS };
+use crate::signing::uri_encode;
+use crate::tools::{ copy_all, BoxFuture, Tools };
+use crate::transfer::checksums;
+
+/*
+    Where submissions' uploads and results are kept, set under `storage` in locations.json
+    bucket_url and tcs_log_bucket_url use the scheme of the backend
+        { "type": "gcs", "region": "us-east1", "link_expiry_days": 7 }
+            gs://bucket/ogv-dating, through gsutil, the default
+            links are V4 signed URLs, signed with the service account at private_key_location
+        { "type": "local", "root": "/srv/primer_id", "public_url": "https://hpc.uni.edu/files" }
+            file:///srv/primer_id/ogv-dating, links are public_url + the path under root,
+            file:// links without a public_url
+        { "type": "s3", "endpoint": "https://s3.uni.edu", "region": "us-east-1" }
+            s3://bucket/ogv-dating, any S3-compatible server, see s3.rs
+            the keys are secrets, s3_access_key_id and s3_secret_access_key, see secrets.rs
+    Backends are given full locations, ex) gs://bucket/ogv-dating/abc123/results.zip
+    Pipelines copy through transfer.rs, which checks every file against what `list` reports
+*/
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StorageConfig {
+    Gcs {
+        // of the bucket, part of the signed URL's credential scope
+        #[serde(default = "default_gcs_region")]
+        region: String,
+        #[serde(default = "default_link_expiry_days")]
+        link_expiry_days: u64,
+    },
+    Local {
+        #[serde(default)]
+        root: String,
+        #[serde(default)]
+        public_url: Option<String>,
+    },
+    S3 {
+        endpoint: String,
+        #[serde(default = "default_region")]
+        region: String,
+        #[serde(default)]
+        access_key_id: String,
+        #[serde(default)]
+        secret_access_key: String,
+    },
+}
+
+impl Default for StorageConfig {
+    fn default() -> StorageConfig {
+        StorageConfig::Gcs {
+            region: default_gcs_region(),
+            link_expiry_days: default_link_expiry_days(),
+        }
+    }
+}
+
+fn default_region() -> String {
+    "us-east-1".to_string()
+}
+
+fn default_gcs_region() -> String {
+    "us-east1".to_string()
+}
+
+fn default_link_expiry_days() -> u64 {
+    MAX_LINK_EXPIRY_DAYS
+}
+
+impl StorageConfig {
+    // every bucket_url starts with it
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            StorageConfig::Gcs { .. } => "gs://",
+            StorageConfig::Local { .. } => "file://",
+            StorageConfig::S3 { .. } => "s3://",
+        }
+    }
+
+    // how long the links signed_url makes work, None when they don't expire
+    pub fn link_expiry_days(&self) -> Option<u64> {
+        match self {
+            StorageConfig::Gcs { link_expiry_days, .. } =>
+                Some((*link_expiry_days).min(MAX_LINK_EXPIRY_DAYS)),
+            StorageConfig::Local { .. } => None,
+            StorageConfig::S3 { .. } => Some(LINK_EXPIRY_SECONDS / (24 * 60 * 60)),
+        }
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = vec![];
+
+        match self {
+            StorageConfig::Gcs { region, link_expiry_days } => {
+                if region.is_empty() {
+                    problems.push("storage.region is empty".to_string());
+                }
+                if !(1..=MAX_LINK_EXPIRY_DAYS).contains(link_expiry_days) {
+                    problems.push(
+                        format!(
+                            "storage.link_expiry_days must be 1 to {}, not {}",
+                            MAX_LINK_EXPIRY_DAYS,
+                            link_expiry_days
+                        )
+                    );
+                }
+            }
+            StorageConfig::Local { root, public_url } => {
+                if public_url.is_some() && root.is_empty() {
+                    problems.push("storage.root is needed for storage.public_url".to_string());
+                }
+            }
+            StorageConfig::S3 { endpoint, access_key_id, secret_access_key, .. } => {
+                if !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+                    problems.push(
+                        format!("storage.endpoint is not an http(s) URL: {:?}", endpoint)
+                    );
+                }
+                if access_key_id.is_empty() || secret_access_key.is_empty() {
+                    let keys = "s3_access_key_id and s3_secret_access_key";
+                    problems.push(format!("{} are needed", keys));
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+// an object as a backend lists it, with the hashes it keeps, if any
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct StoredObject {
+    // ex) gs://bucket/ogv-dating/abc123/lib1/a.fasta
+    pub location: String,
+    pub size: u64,
+    // hex
+    pub md5: Option<String>,
+    pub crc32c: Option<u32>,
+    // last written
+    pub updated: Option<DateTime<Utc>>,
+}
+
+pub trait StorageBackend: Debug + Send + Sync {
+    // every object whose location starts with `prefix`
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<StoredObject>>>;
+    // one object into `to_file`, named like the object, continuing a partial `to_file` when
+    // the backend can
+    fn fetch<'a>(&'a self, location: &'a str, to_file: &'a Path) -> BoxFuture<'a, Result<()>>;
+    fn upload<'a>(&'a self, from_local: &'a str, to: &'a str) -> BoxFuture<'a, Result<()>>;
+    // one object
+    fn delete<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<()>>;
+    // a link to send users, it works without credentials
+    fn signed_url<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<String>>;
+    // total bytes stored under a location
+    fn du<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<u64>>;
+}
+
+// gcs copies go through `tools` so tests can fake gsutil, its links are signed with the key at
+// `private_key_location`
+pub fn storage_backend(
+    config: &StorageConfig,
+    private_key_location: &str,
+    tools: Arc<dyn Tools>
+) -> Result<Arc<dyn StorageBackend>> {
+    Ok(match config {
+        StorageConfig::Gcs { region, link_expiry_days } => {
+            let signer = UrlSigner::new(private_key_location, region, *link_expiry_days);
+            Arc::new(GcsStorage::new(tools, signer))
+        }
+        StorageConfig::Local { root, public_url } =>
+            Arc::new(LocalStorage { root: root.clone(), public_url: public_url.clone() }),
+        StorageConfig::S3 { endpoint, region, access_key_id, secret_access_key } =>
+            Arc::new(S3Storage::new(endpoint, region, access_key_id, secret_access_key)?),
+    })
+}
+
+#[derive(Debug)]
+pub struct GcsStorage {
+    tools: Arc<dyn Tools>,
+    signer: UrlSigner,
+}
+
+impl GcsStorage {
+    pub fn new(tools: Arc<dyn Tools>, signer: UrlSigner) -> GcsStorage {
+        GcsStorage { tools, signer }
+    }
+}
+
+// gsutil cp continues large downloads by itself, from its tracker files
+impl StorageBackend for GcsStorage {
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<StoredObject>>> {
+        Box::pin(async move { self.tools.ls(prefix) })
+    }
+
+    fn fetch<'a>(&'a self, location: &'a str, to_file: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let dir = to_file.parent().context("No directory to download to")?;
+            self.tools.download(location, &dir.display().to_string(), false)
+        })
+    }
+
+    fn upload<'a>(&'a self, from_local: &'a str, to: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.tools.upload(from_local, to) })
+    }
+
+    fn delete<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.tools.rm(location) })
+    }
+
+    fn signed_url<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { self.signer.sign(location) })
+    }
+
+    fn du<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move { self.tools.du(location) })
+    }
+}
+
+// a directory standing in for the bucket, ex) on-prem storage mounted on the HPC
+#[derive(Debug, Clone, Default)]
+pub struct LocalStorage {
+    pub root: String,
+    pub public_url: Option<String>,
+}
+
+impl LocalStorage {
+    fn path(location: &str) -> Result<PathBuf> {
+        location
+            .strip_prefix("file://")
+            .map(PathBuf::from)
+            .with_context(|| format!("Not a file:// location: {}", location))
+    }
+
+    // everything is hashed, there's no metadata to read hashes from
+    fn list_now(&self, prefix: &str) -> Result<Vec<StoredObject>> {
+        let prefix_path = LocalStorage::path(prefix)?;
+        let prefix_str = prefix_path.to_string_lossy().to_string();
+        // the directory to look through, ex) /srv/ogv/abc123/lib -> /srv/ogv/abc123
+        let dir = if prefix_str.ends_with('/') {
+            prefix_path.clone()
+        } else {
+            prefix_path.parent().map(Path::to_path_buf).unwrap_or_default()
+        };
+
+        let pattern = format!("{}/**/*", dir.to_string_lossy().trim_end_matches('/'));
+        let mut objects: Vec<StoredObject> = vec![];
+
+        for path in glob(&pattern)?.filter_map(Result::ok) {
+            if !path.is_file() || !path.to_string_lossy().starts_with(&prefix_str) {
+                continue;
+            }
+            let sums = checksums(&path)?;
+            objects.push(StoredObject {
+                location: format!("file://{}", path.display()),
+                size: sums.size,
+                md5: Some(sums.md5),
+                crc32c: Some(sums.crc32c),
+                updated: path.metadata()?.modified().ok().map(DateTime::<Utc>::from),
+            });
+        }
+
+        Ok(objects)
+    }
+
+    // appends what `to_file` is missing, or starts over if it isn't shorter
+    fn fetch_now(&self, location: &str, to_file: &Path) -> Result<()> {
+        let from = LocalStorage::path(location)?;
+        let mut source = File::open(&from).with_context(|| {
+            format!("Nothing stored at {}", from.display())
+        })?;
+
+        let size = source.metadata()?.len();
+        let have = std::fs::metadata(to_file).map_or(0, |metadata| metadata.len());
+        let offset = if have < size { have } else { 0 };
+
+        let mut destination = OpenOptions::new()
+            .create(true)
+            .append(offset > 0)
+            .write(true)
+            .truncate(offset == 0)
+            .open(to_file)
+            .with_context(|| format!("Failed to open {}", to_file.display()))?;
+
+        source.seek(SeekFrom::Start(offset))?;
+        std::io::copy(&mut source, &mut destination).with_context(|| {
+            format!("Failed to copy {} to {}", from.display(), to_file.display())
+        })?;
+
+        Ok(())
+    }
+
+    fn upload_now(&self, from_local: &str, to: &str) -> Result<()> {
+        let mut to = LocalStorage::path(to)?;
+        if to.to_string_lossy().ends_with('/') {
+            to = to.join(Path::new(from_local).file_name().context("No file name")?);
+        }
+
+        copy_all(Path::new(from_local), &to).with_context(|| {
+            format!("Failed to copy {} to {}", from_local, to.display())
+        })
+    }
+
+    fn link(&self, location: &str) -> Result<String> {
+        let path = LocalStorage::path(location)?;
+        if !path.exists() {
+            return Err(anyhow::anyhow!("Nothing stored at {}", path.display()));
+        }
+
+        let Some(public_url) = &self.public_url else {
+            return Ok(format!("file://{}", uri_encode(&path.to_string_lossy(), false)));
+        };
+
+        let relative = path
+            .strip_prefix(&self.root)
+            .with_context(|| format!("{} is not under storage.root", path.display()))?;
+
+        Ok(
+            format!(
+                "{}/{}",
+                public_url.trim_end_matches('/'),
+                uri_encode(&relative.to_string_lossy(), false)
+            )
+        )
+    }
+
+    fn size(&self, location: &str) -> Result<u64> {
+        let path = LocalStorage::path(location)?;
+        let metadata = std::fs
+            ::metadata(&path)
+            .with_context(|| format!("Nothing stored at {}", path.display()))?;
+
+        Ok(if metadata.is_dir() { dir_size(&path).bytes } else { metadata.len() })
+    }
+}
+
+impl StorageBackend for LocalStorage {
+    fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<StoredObject>>> {
+        Box::pin(async move { self.list_now(prefix) })
+    }
+
+    fn fetch<'a>(&'a self, location: &'a str, to_file: &'a Path) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.fetch_now(location, to_file) })
+    }
+
+    fn upload<'a>(&'a self, from_local: &'a str, to: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { self.upload_now(from_local, to) })
+    }
+
+    fn delete<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let path = LocalStorage::path(location)?;
+            std::fs::remove_file(&path).with_context(|| format!("Failed to delete {}", location))
+        })
+    }
+
+    fn signed_url<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { self.link(location) })
+    }
+
+    fn du<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<u64>> {
+        Box::pin(async move { self.size(location) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config: StorageConfig = serde_json::from_str(r#"{ "type": "gcs" }"#).unwrap();
+        assert_eq!(config, StorageConfig::default());
+        assert_eq!(config.scheme(), "gs://");
+
+        let config: StorageConfig = serde_json
+            ::from_str(r#"{ "type": "gcs", "link_expiry_days": 30 }"#)
+            .unwrap();
+        assert_eq!(config.problems(), vec!["storage.link_expiry_days must be 1 to 7, not 30"]);
+
+        let config: StorageConfig = serde_json
+            ::from_str(r#"{ "type": "gcs", "link_expiry_days": 3 }"#)
+            .unwrap();
+        assert_eq!(config.link_expiry_days(), Some(3));
+
+        let config: StorageConfig = serde_json
+            ::from_str(r#"{ "type": "s3", "endpoint": "http://minio:9000" }"#)
+            .unwrap();
+        assert_eq!(config.scheme(), "s3://");
+        assert_eq!(config.link_expiry_days(), Some(7));
+        assert_eq!(config.problems(), vec!["s3_access_key_id and s3_secret_access_key are needed"]);
+
+        let config: StorageConfig = serde_json
+            ::from_str(r#"{ "type": "local", "public_url": "https://hpc.uni.edu/files" }"#)
+            .unwrap();
+        assert_eq!(config.problems(), vec!["storage.root is needed for storage.public_url"]);
+        assert_eq!(config.link_expiry_days(), None);
+    }
+
+    #[tokio::test]
+    async fn test_local_storage() {
+        let dir = std::env::temp_dir().join(format!("local_storage_{}", std::process::id()));
+        let root = dir.join("bucket");
+        let uploads = dir.join("uploads");
+        std::fs::create_dir_all(uploads.join("lib1")).unwrap();
+        std::fs::write(uploads.join("a.fastq"), "ACGT").unwrap();
+        std::fs::write(uploads.join("lib1/b.fastq"), "ACGTACGT").unwrap();
+
+        let storage = LocalStorage {
+            root: root.display().to_string(),
+            public_url: Some("https://hpc.uni.edu/files/".to_string()),
+        };
+        let bucket = format!("file://{}/ogv/abc123", root.display());
+
+        for file in ["a.fastq", "lib1"] {
+            let from = uploads.join(file).display().to_string();
+            storage.upload(&from, &format!("{}/", bucket)).await.unwrap();
+        }
+        assert_eq!(storage.du(&bucket).await.unwrap(), 12);
+
+        let objects = storage.list(&format!("{}/", bucket)).await.unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[1].location, format!("{}/lib1/b.fastq", bucket));
+        assert_eq!(objects[1].size, 8);
+        assert!(storage.list(&format!("{}/lib", bucket)).await.unwrap().len() == 1);
+
+        // a partial file is continued
+        let partial = dir.join("b.fastq");
+        std::fs::write(&partial, "ACG").unwrap();
+        storage.fetch(&objects[1].location, &partial).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&partial).unwrap(), "ACGTACGT");
+
+        assert_eq!(
+            storage.signed_url(&format!("{}/a.fastq", bucket)).await.unwrap(),
+            "https://hpc.uni.edu/files/ogv/abc123/a.fastq"
+        );
+        assert!(storage.signed_url(&format!("{}/missing.zip", bucket)).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,269 @@
+use std::path::{ Path, PathBuf };
+use std::sync::Mutex;
+use anyhow::{ Context, Result };
+use chrono::{ DateTime, Utc };
+
+use super::{ copy_all, BoxFuture, Email, ToolOutput, Tools };
+use crate::cloud_storage::{ parse_du, parse_ls };
+use crate::storage::StoredObject;
+use crate::transfer::checksums;
+
+// what a faked call leaves behind
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    // (fixture, destination), destinations are relative to the call's directory
+    files: Vec<(String, String)>,
+    stdout: String,
+    stderr: String,
+    status: i32,
+    error: Option<String>,
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay::default()
+    }
+
+    // a file or directory from the fixtures, directories are copied recursively
+    pub fn copy(mut self, fixture: &str, destination: &str) -> Replay {
+        self.files.push((fixture.to_owned(), destination.to_owned()));
+        self
+    }
+
+    pub fn stdout(mut self, stdout: &str) -> Replay {
+        self.stdout = stdout.to_owned();
+        self
+    }
+
+    pub fn stderr(mut self, stderr: &str) -> Replay {
+        self.stderr = stderr.to_owned();
+        self
+    }
+
+    pub fn status(mut self, status: i32) -> Replay {
+        self.status = status;
+        self
+    }
+
+    // the call itself fails, like a command that couldn't be started
+    pub fn error(mut self, error: &str) -> Replay {
+        self.error = Some(error.to_owned());
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FakeTools {
+    fixtures: PathBuf,
+    // first rule whose pattern is part of the command wins, unmatched calls succeed silently
+    rules: Vec<(String, Replay)>,
+    calls: Mutex<Vec<String>>,
+    emails: Mutex<Vec<Email>>,
+    // (location, fixture)
+    buckets: Vec<(String, String)>,
+    uploaded: Mutex<Vec<StoredObject>>,
+}
+
+impl FakeTools {
+    // fixtures from tests/fixtures/{fixtures}
+    pub fn new(fixtures: &str) -> FakeTools {
+        FakeTools {
+            fixtures: Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(fixtures),
+            ..Default::default()
+        }
+    }
+
+    pub fn on(mut self, pattern: &str, replay: Replay) -> FakeTools {
+        self.rules.push((pattern.to_owned(), replay));
+        self
+    }
+
+    // everything under `location` is the fixture directory's files, ex)
+    // .bucket("gs://bucket/ogv-dating/abc123", "data") lists data/lib1/a.fasta as
+    // gs://bucket/ogv-dating/abc123/lib1/a.fasta
+    pub fn bucket(mut self, location: &str, fixture: &str) -> FakeTools {
+        self.buckets.push((location.trim_end_matches('/').to_owned(), fixture.to_owned()));
+        self
+    }
+
+    // (object, fixture file) for every file in the fixture buckets
+    fn bucket_objects(&self) -> Result<Vec<(StoredObject, PathBuf)>> {
+        let mut objects: Vec<(StoredObject, PathBuf)> = vec![];
+
+        for (location, fixture) in &self.buckets {
+            let dir = self.fixtures.join(fixture);
+            let pattern = format!("{}/**/*", dir.display());
+            for path in glob::glob(&pattern)?.filter_map(Result::ok) {
+                if !path.is_file() {
+                    continue;
+                }
+                let sums = checksums(&path)?;
+                let relative = path.strip_prefix(&dir)?.to_string_lossy().to_string();
+                let object = StoredObject {
+                    location: format!("{}/{}", location, relative),
+                    size: sums.size,
+                    md5: Some(sums.md5),
+                    crc32c: Some(sums.crc32c),
+                    updated: path.metadata()?.modified().ok().map(DateTime::<Utc>::from),
+                };
+                objects.push((object, path));
+            }
+        }
+
+        Ok(objects)
+    }
+
+    // every call as the command the shell tools would have run, oldest first
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn emails(&self) -> Vec<Email> {
+        self.emails.lock().unwrap().clone()
+    }
+
+    fn replay(&self, cmd: &str, dir: &str) -> Result<ToolOutput> {
+        self.calls.lock().unwrap().push(cmd.to_owned());
+
+        let Some((_, replay)) = self.rules.iter().find(|(pattern, _)| cmd.contains(pattern)) else {
+            return Ok(ToolOutput { status: Some(0), ..Default::default() });
+        };
+
+        if let Some(error) = &replay.error {
+            return Err(anyhow::anyhow!("{}", error));
+        }
+
+        for (fixture, destination) in &replay.files {
+            let from = self.fixtures.join(fixture);
+            let to = Path::new(dir).join(destination);
+            copy_all(&from, &to).with_context(|| {
+                format!("Failed to replay fixture {} to {}", from.display(), to.display())
+            })?;
+        }
+
+        Ok(ToolOutput {
+            status: Some(replay.status),
+            stdout: replay.stdout.clone(),
+            stderr: replay.stderr.clone(),
+        })
+    }
+}
+
+impl Tools for FakeTools {
+    fn run(&self, cmd: &str, dir: &str) -> Result<String> {
+        Ok(self.replay(cmd, dir)?.stdout)
+    }
+
+    fn output(&self, cmd: &str, dir: &str) -> Result<ToolOutput> {
+        self.replay(cmd, dir)
+    }
+
+    fn download(&self, from: &str, to_local: &str, recursive: bool) -> Result<()> {
+        let _ = std::fs::create_dir_all(to_local);
+
+        let flag = if recursive { "-r" } else { "" };
+        self.replay(&format!("gsutil cp {} {} {}", flag, from, to_local), to_local)?;
+
+        let objects = self.bucket_objects()?;
+        if let (false, Some((_, path))) = (
+            recursive,
+            objects.iter().find(|(object, _)| object.location == from),
+        ) {
+            let file_name = path.file_name().context("No file name")?;
+            std::fs::copy(path, Path::new(to_local).join(file_name))?;
+        }
+
+        Ok(())
+    }
+
+    fn upload(&self, from_local: &str, to: &str) -> Result<()> {
+        self.replay(&format!("gsutil cp {} {}", from_local, to), "")?;
+
+        // a real upload would have stored it, so it's listed from now on
+        if let Ok(sums) = checksums(Path::new(from_local)) {
+            let mut uploaded = self.uploaded.lock().unwrap();
+            uploaded.retain(|object| object.location != to);
+            uploaded.push(StoredObject {
+                location: to.to_owned(),
+                size: sums.size,
+                md5: Some(sums.md5),
+                crc32c: Some(sums.crc32c),
+                updated: Some(Utc::now()),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn ls(&self, prefix: &str) -> Result<Vec<StoredObject>> {
+        let output = self.replay(&format!("gsutil ls -L {}**", prefix), "")?;
+        let mut objects = parse_ls(&output.stdout)?;
+
+        objects.extend(self.bucket_objects()?.into_iter().map(|(object, _)| object));
+        objects.extend(self.uploaded.lock().unwrap().iter().cloned());
+        objects.retain(|object| object.location.starts_with(prefix));
+
+        Ok(objects)
+    }
+
+    // only what was uploaded goes away, fixture buckets are read-only
+    fn rm(&self, location: &str) -> Result<()> {
+        self.replay(&format!("gsutil rm {}", location), "")?;
+        self.uploaded.lock().unwrap().retain(|object| object.location != location);
+        Ok(())
+    }
+
+    fn du(&self, location: &str) -> Result<u64> {
+        let output = self.replay(&format!("gsutil du -s {}", location), "")?;
+        Ok(parse_du(&output.stdout).unwrap_or(0))
+    }
+
+    fn email(&self, email: Email) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            self.replay(&format!("email {} {}", &email.to, &email.subject), "")?;
+            self.emails.lock().unwrap().push(email);
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fake_tools() {
+        let dir = std::env::temp_dir().join(format!("fake_tools_{}", std::process::id()));
+        let dir_str = dir.display().to_string();
+
+        let tools = FakeTools::new("tcsdr")
+            .on("tcs_log", Replay::new().copy("log", "TCSDR_tcs"))
+            .on("locator", Replay::new().stderr("bad sequence").status(0))
+            .on("snakemake", Replay::new().error("conda not found"));
+
+        tools.run("conda run -n tcsdr tcs_log TCSDR", &dir_str).unwrap();
+        assert!(dir.join("TCSDR_tcs/log.html").exists());
+
+        let output = tools.output("conda run -n locator locator -i a.fasta", &dir_str).unwrap();
+        assert_eq!(output.stderr, "bad sequence");
+        assert!(tools.run("conda run -n ogv snakemake", &dir_str).is_err());
+
+        // unmatched calls succeed with no output
+        assert_eq!(tools.run("ls", &dir_str).unwrap(), "");
+
+        tools
+            .email(Email {
+                subject: "TCS Results".to_string(),
+                body: "done".to_string(),
+                to: "user@uni.edu".to_string(),
+                include_admin: false,
+            }).await
+            .unwrap();
+
+        assert_eq!(tools.emails()[0].subject, "TCS Results");
+        assert_eq!(tools.calls().len(), 5);
+        assert_eq!(tools.calls()[4], "email user@uni.edu TCS Results");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
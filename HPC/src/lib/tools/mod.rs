@@ -0,0 +1,138 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::process::Command;
+use std::time::Instant;
+use anyhow::{ Context, Result };
+use serde::Serialize;
+
+use crate::cloud_storage::{ download, du, ls, rm, upload };
+use crate::logging::log_command;
+use crate::run_command::run_command;
+use crate::send_email::send_email;
+use crate::storage::StoredObject;
+
+// FakeTools and Replay, only built for tests like mock_api
+#[cfg(any(test, feature = "test-support"))]
+mod fake;
+#[cfg(any(test, feature = "test-support"))]
+pub use fake::{ FakeTools, Replay };
+
+/*
+    Everything a pipeline hands off to another program: shell commands (conda, snakemake,
+    python3 ...), gsutil and email
+    Pipelines use ShellTools, tests swap in FakeTools which runs nothing, replays prepared
+    outputs from tests/fixtures and records what it was asked to do
+        let tools = Arc::new(
+            FakeTools::new("tcsdr")
+                .on("TCSDR/lib1", Replay::new().copy("error/.error", "TCSDR/lib1/.error"))
+        );
+        let pipeline = pipeline.with_tools(tools.clone());
+        ... tools.calls(), tools.emails()
+    FakeTools::bucket stands a fixture directory in for a bucket location, for gsutil ls and cp
+*/
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Email {
+    pub subject: String,
+    pub body: String,
+    pub to: String,
+    pub include_admin: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ToolOutput {
+    // None when killed by a signal
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+pub trait Tools: Debug + Send + Sync {
+    // bash -c in `dir`, output goes to the console, an exit code != 0 is not an error
+    fn run(&self, cmd: &str, dir: &str) -> Result<String>;
+    // same, but stdout and stderr are captured, for tools that only report errors on stderr
+    fn output(&self, cmd: &str, dir: &str) -> Result<ToolOutput>;
+
+    fn download(&self, from: &str, to_local: &str, recursive: bool) -> Result<()>;
+    fn upload(&self, from_local: &str, to: &str) -> Result<()>;
+    // every object whose location starts with `prefix`, with its size and hashes
+    fn ls(&self, prefix: &str) -> Result<Vec<StoredObject>>;
+    fn rm(&self, location: &str) -> Result<()>;
+    fn du(&self, location: &str) -> Result<u64>;
+
+    fn email(&self, email: Email) -> BoxFuture<'_, Result<()>>;
+}
+
+#[derive(Debug, Default)]
+pub struct ShellTools;
+
+impl Tools for ShellTools {
+    fn run(&self, cmd: &str, dir: &str) -> Result<String> {
+        run_command(cmd, dir)
+    }
+
+    fn output(&self, cmd: &str, dir: &str) -> Result<ToolOutput> {
+        let started = Instant::now();
+        let output = Command::new("bash")
+            .arg("-c")
+            .arg(cmd)
+            .current_dir(dir)
+            .output()
+            .with_context(|| format!("Failed running command:\n{}", cmd))?;
+
+        log_command(cmd, output.status.code(), started.elapsed());
+
+        Ok(ToolOutput {
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        })
+    }
+
+    fn download(&self, from: &str, to_local: &str, recursive: bool) -> Result<()> {
+        download(from, to_local, recursive)
+    }
+
+    fn upload(&self, from_local: &str, to: &str) -> Result<()> {
+        upload(from_local, to)
+    }
+
+    fn ls(&self, prefix: &str) -> Result<Vec<StoredObject>> {
+        ls(prefix)
+    }
+
+    fn rm(&self, location: &str) -> Result<()> {
+        rm(location)
+    }
+
+    fn du(&self, location: &str) -> Result<u64> {
+        du(location)
+    }
+
+    fn email(&self, email: Email) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            send_email(&email.subject, &email.body, &email.to, email.include_admin).await
+        })
+    }
+}
+
+pub(crate) fn copy_all(from: &Path, to: &Path) -> std::io::Result<()> {
+    if from.is_dir() {
+        std::fs::create_dir_all(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_all(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        return Ok(());
+    }
+
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(from, to)?;
+    Ok(())
+}
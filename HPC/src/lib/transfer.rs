@@ -0,0 +1,395 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{ Path, PathBuf };
+use std::sync::Arc;
+use anyhow::{ Context, Result };
+use openssl::hash::{ Hasher, MessageDigest };
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::signing::hex;
+use crate::storage::{ StorageBackend, StoredObject };
+
+/*
+    Copies between the bucket and the HPC that are checked and can be picked up again
+        let stats = download(storage, from, scratch_dir, true).await?, from ending in * or not
+    Downloads list the objects first, then fetch up to PARALLEL_TRANSFERS at a time. Every file
+    is checked against the size, CRC32C and MD5 the backend lists, files already complete are
+    skipped and partial ones continued, so rerunning a failed download only fetches what's missing
+    Uploads are skipped when the same file is already stored, and checked by size and hash after
+    gsutil also continues large uploads and downloads by itself, from its tracker files
+*/
+
+pub const PARALLEL_TRANSFERS: usize = 8;
+// a file that doesn't match is fetched again from scratch this many times
+const FETCH_ATTEMPTS: usize = 2;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TransferStats {
+    pub files: usize,
+    pub bytes: u64,
+    // already complete, nothing was copied
+    pub skipped: usize,
+}
+
+impl fmt::Display for TransferStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} files, {} bytes ({} already complete)", self.files, self.bytes, self.skipped)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checksums {
+    pub size: u64,
+    // hex
+    pub md5: String,
+    pub crc32c: u32,
+}
+
+impl Checksums {
+    // hashes the object doesn't list aren't compared
+    pub fn matches(&self, object: &StoredObject) -> bool {
+        self.size == object.size &&
+            object.md5.as_ref().is_none_or(|md5| md5.eq_ignore_ascii_case(&self.md5)) &&
+            object.crc32c.is_none_or(|crc32c| crc32c == self.crc32c)
+    }
+}
+
+// CRC-32C (Castagnoli), what GCS keeps for every object
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x82f63b78 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+// continues `crc`, start with 0
+pub fn crc32c(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc = CRC32C_TABLE[((crc ^ (*byte as u32)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+pub fn checksums(path: &Path) -> Result<Checksums> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut md5 = Hasher::new(MessageDigest::md5())?;
+    let mut crc = 0;
+    let mut size = 0;
+    let mut buffer = vec![0u8; 1 << 20];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        md5.update(&buffer[..read])?;
+        crc = crc32c(crc, &buffer[..read]);
+        size += read as u64;
+    }
+
+    Ok(Checksums { size, md5: hex(&md5.finish()?), crc32c: crc })
+}
+
+async fn matches(object: &StoredObject, path: &Path) -> Result<bool> {
+    if std::fs::metadata(path).map_or(true, |metadata| metadata.len() != object.size) {
+        return Ok(false);
+    }
+
+    let path = path.to_path_buf();
+    let sums = tokio::task::spawn_blocking(move || checksums(&path)).await??;
+    Ok(sums.matches(object))
+}
+
+// (bytes copied, already complete)
+async fn download_file(
+    storage: Arc<dyn StorageBackend>,
+    object: StoredObject,
+    path: PathBuf
+) -> Result<(u64, bool)> {
+    if let Some(parent) = path.parent() {
+        tokio::fs
+            ::create_dir_all(parent).await
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    let have = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+    if have == object.size && matches(&object, &path).await? {
+        return Ok((0, true));
+    }
+    // longer than the object, it isn't a partial download of it
+    if have > object.size {
+        tokio::fs::remove_file(&path).await?;
+    }
+
+    for _ in 0..FETCH_ATTEMPTS {
+        storage
+            .fetch(&object.location, &path).await
+            .with_context(|| format!("Failed to download {}", &object.location))?;
+        if matches(&object, &path).await? {
+            return Ok((object.size, false));
+        }
+        // don't continue a corrupt file
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+
+    Err(
+        anyhow::anyhow!(
+            "{} doesn't match the size or checksum of {} after {} attempts",
+            path.display(),
+            &object.location,
+            FETCH_ATTEMPTS
+        )
+    )
+}
+
+// which objects go where, a location ending in * is everything under it like gsutil cp -r,
+// subdirectories only when `recursive`
+async fn plan(
+    storage: &dyn StorageBackend,
+    from: &str,
+    to_local: &Path,
+    recursive: bool
+) -> Result<Vec<(StoredObject, PathBuf)>> {
+    let mut planned: Vec<(StoredObject, PathBuf)> = vec![];
+
+    if let Some(prefix) = from.strip_suffix('*') {
+        // relative to the "directory" the * is in
+        let dir = &prefix[..prefix.rfind('/').map_or(0, |slash| slash + 1)];
+        for object in storage.list(prefix).await? {
+            let relative = object.location[dir.len()..].to_owned();
+            // directory markers, and subdirectories unless recursive
+            if relative.ends_with('/') || (!recursive && relative.contains('/')) {
+                continue;
+            }
+            planned.push((object, to_local.join(relative)));
+        }
+        return Ok(planned);
+    }
+
+    let name = from.trim_end_matches('/').rsplit('/').next().unwrap_or(from);
+    let dir = format!("{}/", from.trim_end_matches('/'));
+    for object in storage.list(from).await? {
+        if object.location == from {
+            planned.push((object, to_local.join(name)));
+        } else if recursive && object.location.starts_with(&dir) {
+            let relative = object.location[dir.len()..].to_owned();
+            planned.push((object, to_local.join(name).join(relative)));
+        }
+    }
+
+    Ok(planned)
+}
+
+pub async fn download(
+    storage: Arc<dyn StorageBackend>,
+    from: &str,
+    to_local: &str,
+    recursive: bool
+) -> Result<TransferStats> {
+    let planned = plan(storage.as_ref(), from, Path::new(to_local), recursive).await?;
+    if planned.is_empty() {
+        return Err(anyhow::anyhow!("Nothing stored at {}", from));
+    }
+
+    let permits = Arc::new(Semaphore::new(PARALLEL_TRANSFERS));
+    let mut downloads = JoinSet::new();
+    for (object, path) in planned {
+        let (storage, permits) = (storage.clone(), permits.clone());
+        downloads.spawn(async move {
+            let _permit = permits.acquire_owned().await?;
+            download_file(storage, object, path).await
+        });
+    }
+
+    // the first failure drops the rest of the downloads
+    let mut stats = TransferStats::default();
+    while let Some(downloaded) = downloads.join_next().await {
+        let (bytes, skipped) = downloaded??;
+        stats.files += 1;
+        stats.bytes += bytes;
+        stats.skipped += skipped as usize;
+    }
+
+    Ok(stats)
+}
+
+async fn stored(storage: &dyn StorageBackend, location: &str) -> Result<Option<StoredObject>> {
+    let objects = storage.list(location).await?;
+    Ok(objects.into_iter().find(|object| object.location == location))
+}
+
+// `to` ending in / keeps the file name
+pub async fn upload(
+    storage: Arc<dyn StorageBackend>,
+    from_local: &str,
+    to: &str
+) -> Result<TransferStats> {
+    let location = if to.ends_with('/') {
+        let file_name = Path::new(from_local).file_name().context("No file name")?;
+        format!("{}{}", to, file_name.to_string_lossy())
+    } else {
+        to.to_owned()
+    };
+
+    let path = PathBuf::from(from_local);
+    let sums = tokio::task::spawn_blocking(move || checksums(&path)).await??;
+
+    if stored(storage.as_ref(), &location).await?.is_some_and(|object| sums.matches(&object)) {
+        return Ok(TransferStats { files: 1, bytes: 0, skipped: 1 });
+    }
+
+    storage.upload(from_local, &location).await?;
+
+    let object = stored(storage.as_ref(), &location).await?.with_context(|| {
+        format!("{} is missing after uploading {}", &location, from_local)
+    })?;
+    if !sums.matches(&object) {
+        return Err(
+            anyhow::anyhow!(
+                "{} doesn't match {} after upload, {} bytes stored of {}",
+                &location,
+                from_local,
+                object.size,
+                sums.size
+            )
+        );
+    }
+
+    Ok(TransferStats { files: 1, bytes: sums.size, skipped: 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalStorage;
+    use crate::tools::BoxFuture;
+
+    #[test]
+    fn test_checksums() {
+        // check value from RFC 3720 and the CRC catalogue
+        assert_eq!(crc32c(0, b"123456789"), 0xe3069283);
+        assert_eq!(crc32c(crc32c(0, b"1234"), b"56789"), 0xe3069283);
+        assert_eq!(crc32c(0, &[0u8; 32]), 0x8a9136aa);
+
+        let path = std::env::temp_dir().join(format!("checksums_{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let sums = checksums(&path).unwrap();
+        assert_eq!(sums.md5, "d41d8cd98f00b204e9800998ecf8427e");
+        assert_eq!(sums.crc32c, 0);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // a backend that corrupts the first fetch of every object
+    #[derive(Debug)]
+    struct Flaky {
+        storage: LocalStorage,
+        fetched: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl StorageBackend for Flaky {
+        fn list<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, Result<Vec<StoredObject>>> {
+            self.storage.list(prefix)
+        }
+
+        fn fetch<'a>(&'a self, location: &'a str, to_file: &'a Path) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move {
+                let first = {
+                    let mut fetched = self.fetched.lock().unwrap();
+                    let first = !fetched.contains(&location.to_owned());
+                    fetched.push(location.to_owned());
+                    first
+                };
+                if !first {
+                    return self.storage.fetch(location, to_file).await;
+                }
+                std::fs::write(to_file, "corrupt!")?;
+                Ok(())
+            })
+        }
+
+        fn upload<'a>(&'a self, from_local: &'a str, to: &'a str) -> BoxFuture<'a, Result<()>> {
+            self.storage.upload(from_local, to)
+        }
+
+        fn delete<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<()>> {
+            self.storage.delete(location)
+        }
+
+        fn signed_url<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<String>> {
+            self.storage.signed_url(location)
+        }
+
+        fn du<'a>(&'a self, location: &'a str) -> BoxFuture<'a, Result<u64>> {
+            self.storage.du(location)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfers() {
+        let dir = std::env::temp_dir().join(format!("transfers_{}", std::process::id()));
+        let root = dir.join("bucket");
+        let bucket = format!("file://{}/ogv/abc123", root.display());
+        std::fs::create_dir_all(root.join("ogv/abc123/lib1")).unwrap();
+        std::fs::write(root.join("ogv/abc123/a.fastq"), "ACGT").unwrap();
+        std::fs::write(root.join("ogv/abc123/lib1/b.fastq"), "ACGTACGT").unwrap();
+        std::fs::write(root.join("ogv/abc1234.fastq"), "other").unwrap();
+
+        let storage: Arc<dyn StorageBackend> = Arc::new(LocalStorage::default());
+        let scratch = dir.join("scratch");
+        let to_local = scratch.display().to_string();
+
+        let from = format!("{}/*", bucket);
+        let stats = download(storage.clone(), &from, &to_local, false).await.unwrap();
+        assert_eq!(stats, TransferStats { files: 1, bytes: 4, skipped: 0 });
+        assert!(!scratch.join("lib1").exists());
+
+        // a rerun only fetches what's missing or partial
+        std::fs::create_dir_all(scratch.join("lib1")).unwrap();
+        std::fs::write(scratch.join("lib1/b.fastq"), "ACGT").unwrap();
+        let stats = download(storage.clone(), &from, &to_local, true).await.unwrap();
+        assert_eq!(stats, TransferStats { files: 2, bytes: 8, skipped: 1 });
+        assert_eq!(std::fs::read_to_string(scratch.join("lib1/b.fastq")).unwrap(), "ACGTACGT");
+        assert!(!scratch.join("abc1234.fastq").exists());
+
+        // a single object and a "directory"
+        let stats = download(storage.clone(), &format!("{}/lib1", bucket), &to_local, true).await;
+        assert_eq!(stats.unwrap().files, 1);
+        let missing = format!("{}/missing", bucket);
+        assert!(download(storage.clone(), &missing, &to_local, true).await.is_err());
+
+        // corrupt files are fetched again from scratch
+        let flaky = Arc::new(Flaky {
+            storage: LocalStorage::default(),
+            fetched: Default::default(),
+        });
+        let to_flaky = dir.join("flaky").display().to_string();
+        let stats = download(flaky.clone(), &from, &to_flaky, true).await.unwrap();
+        assert_eq!(stats.bytes, 12);
+        assert_eq!(std::fs::read_to_string(dir.join("flaky/a.fastq")).unwrap(), "ACGT");
+
+        let results = dir.join("results.zip");
+        std::fs::write(&results, "zipped").unwrap();
+        let results = results.display().to_string();
+        let stats = upload(storage.clone(), &results, &format!("{}/", bucket)).await.unwrap();
+        assert_eq!(stats, TransferStats { files: 1, bytes: 6, skipped: 0 });
+        let stats = upload(storage.clone(), &results, &format!("{}/", bucket)).await.unwrap();
+        assert_eq!(stats.skipped, 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
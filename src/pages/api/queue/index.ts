@@ -1,9 +1,7 @@
 import type { NextApiRequest, NextApiResponse } from "next";
-import { fetchPublic } from "@/utils/api";
+import { fetchPublic, isAuthorized } from "@/utils/api";
 import prisma from "@/utils/prisma";
 
-const { API_KEY } = process.env;
-
 type QueueRow = Awaited<ReturnType<typeof fetchPublic>>;
 
 type QueueResponse = {
@@ -12,6 +10,7 @@ type QueueResponse = {
   tcss: QueueRow;
   splicings: QueueRow;
   locators: QueueRow;
+  coreceptors: QueueRow;
 };
 
 export default async function handler(
@@ -22,12 +21,12 @@ export default async function handler(
     return res.status(404).end();
   }
 
-  if (req.headers["x-api-key"] !== API_KEY) {
+  if (!isAuthorized(req)) {
     return res.status(401).json({ error: "Unauthorized request" });
   }
 
   try {
-    const [ogvs, intacts, tcss, splicings, locators] =
+    const [ogvs, intacts, tcss, splicings, locators, coreceptors] =
       await Promise.all([
         fetchPublic(prisma.ogvs.findMany, () => 5),
         fetchPublic(
@@ -40,6 +39,7 @@ export default async function handler(
         ),
         fetchPublic(prisma.splice.findMany, () => 0),
         fetchPublic(prisma.locators.findMany, () => 0),
+        fetchPublic(prisma.coreceptors.findMany, () => 0),
       ]);
 
     return res.status(200).json({
@@ -48,6 +48,7 @@ export default async function handler(
       tcss,
       splicings,
       locators,
+      coreceptors,
     });
   } catch (e) {
     return res.status(400).json({ error: `Database error:\n${e}` });
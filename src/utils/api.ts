@@ -1,10 +1,21 @@
 import { NextApiRequest, NextApiResponse } from "next";
 
-const { API_KEY, TEST_ENV } = process.env;
+const { API_KEY, API_KEY_NEXT, TEST_ENV } = process.env;
+
+// API_KEY_NEXT is accepted too while the HPC rotates to it
+export const isAuthorized = (req: NextApiRequest) => {
+  const key = req.headers["x-api-key"];
+  return !!key && (key === API_KEY || (!!API_KEY_NEXT && key === API_KEY_NEXT));
+};
 
 export const publicQueueWhere = {
   processingError: { not: true },
-  OR: [{ submit: true }, { pending: true }],
+  OR: [
+    { submit: true },
+    { pending: true },
+    { cancel: true },
+    { relink: true },
+  ],
 };
 
 // 'middleware' to ensure API Key or return id
@@ -12,7 +23,7 @@ export const validateIdRequest = (req: NextApiRequest) => {
   let error = "";
   let id = req.query.id || "";
 
-  if (req.headers["x-api-key"] !== API_KEY) {
+  if (!isAuthorized(req)) {
     error = "Unauthorized reqest";
   } else if (!id || Array.isArray(id)) {
     error = "Missing id";
@@ -38,13 +49,35 @@ export const fetchPublic = async (
   // allow all data in test env, otherwise filter out sensitive fields
   return !!TEST_ENV
     ? all.map((item) => ({ ...item, uploadCount: calcUploadCount(item) }))
-    : all.map(({ id, submit, pending, createdAt, ...item }) => ({
-        id,
-        submit,
-        pending,
-        createdAt,
-        uploadCount: calcUploadCount(item),
-      }));
+    : all.map(
+        ({
+          id,
+          submit,
+          pending,
+          createdAt,
+          schedulerJobId,
+          schedulerState,
+          cancel,
+          relink,
+          attempts,
+          submittedAt,
+          resources,
+          ...item
+        }) => ({
+          id,
+          submit,
+          pending,
+          createdAt,
+          schedulerJobId,
+          schedulerState,
+          cancel,
+          relink,
+          attempts,
+          submittedAt,
+          resources,
+          uploadCount: calcUploadCount(item),
+        }),
+      );
 };
 
 export const getPublic = async (